@@ -0,0 +1,76 @@
+#ifndef RAYTRACER_H
+#define RAYTRACER_H
+
+/* Generated with cbindgen from src/ray_tracer/ffi - see cbindgen.toml */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define RT_OK 0
+#define RT_INVALID_ARGUMENT -1
+#define RT_BUFFER_TOO_SMALL -2
+#define RT_RENDER_FAILED -3
+
+/**
+ * A scene loaded from a configuration, ready to render.
+ */
+typedef struct RtScene RtScene;
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/**
+ * Create a scene from a JSON configuration string.
+ *
+ * Returns null if the configuration is invalid. Free with `rt_scene_free`.
+ *
+ * # Safety
+ *
+ * `configuration` must be a valid, NUL terminated string.
+ */
+RtScene *rt_scene_new(const char *configuration);
+
+/**
+ * Override the configured output resolution.
+ *
+ * # Safety
+ *
+ * `scene` must come from `rt_scene_new` and must not be rendering.
+ */
+int rt_scene_set_resolution(RtScene *scene, size_t width, size_t height);
+
+/**
+ * Render into `buffer` as tone mapped, row-major RGBA (4 bytes per pixel).
+ *
+ * Blocks until the render finishes; `rt_scene_progress` may be polled from another thread.
+ *
+ * # Safety
+ *
+ * `scene` must come from `rt_scene_new` and `buffer` must be valid for `buffer_len` bytes.
+ */
+int rt_scene_render(const RtScene *scene, uint8_t *buffer, size_t buffer_len);
+
+/**
+ * Fraction of pixels completed by the current or last render, from 0.0 to 1.0.
+ *
+ * # Safety
+ *
+ * `scene` must come from `rt_scene_new`.
+ */
+double rt_scene_progress(const RtScene *scene);
+
+/**
+ * Release a scene created by `rt_scene_new`. Null is ignored.
+ *
+ * # Safety
+ *
+ * `scene` must come from `rt_scene_new` and must not be used afterwards.
+ */
+void rt_scene_free(RtScene *scene);
+
+#ifdef __cplusplus
+} /* extern "C" */
+#endif
+
+#endif /* RAYTRACER_H */
@@ -1,2 +1,25 @@
+#![allow(dead_code)]
+#![allow(unused_variables, unused_mut)]
+
+extern crate cgmath;
+#[cfg(not(target_arch = "wasm32"))]
+extern crate image;
+#[cfg(unix)]
+extern crate libc;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "python")]
+extern crate numpy;
+#[cfg(feature = "python")]
+extern crate pyo3;
+extern crate rand;
+#[cfg(target_arch = "wasm32")]
+extern crate wasm_bindgen;
+
+#[macro_use]
+extern crate serde_derive;
+
+pub mod ray_tracer;
+
 #[cfg(test)]
 mod tests {}
@@ -1,15 +1,520 @@
-#![allow(dead_code)]
-#![allow(unused_variables, unused_mut)]
+extern crate raytracer;
 
-extern crate cgmath;
-extern crate image;
-extern crate rand;
+use std::env;
+use std::process;
+use std::str::FromStr;
 
-#[macro_use]
-extern crate serde_derive;
+use raytracer::ray_tracer;
+use raytracer::ray_tracer::backend::Backend;
+use raytracer::ray_tracer::bake::{self, BakeSettings};
+use raytracer::ray_tracer::budget;
+use raytracer::ray_tracer::contact_sheet::{self, Sweep};
+use raytracer::ray_tracer::debug_view::DebugView;
+use raytracer::ray_tracer::edge_overlay::EdgeOverlay;
+use raytracer::ray_tracer::exr::ExrImage;
+use raytracer::ray_tracer::logging;
+use raytracer::ray_tracer::lookdev;
+use raytracer::ray_tracer::scene::configuration::Configuration;
 
-mod ray_tracer;
+const USAGE: &str = "Usage: raytracer [options] [configuration file] [output file]
+       raytracer serve [address] [--root <directory>]
+       raytracer coordinate <address> [--tile-size <n>] [configuration file] [output file]
+       raytracer work <address>
+       raytracer preview-material <configuration file> <object name> [output file]
+       raytracer bake <object name> [--mode <lightmap|ao>] [--size <n>] [--padding <n>]
+                      [--ao-rays <n>] [--ao-distance <d>] [--set ...]
+                      [configuration file] [output file]
+       raytracer contact-sheet [--vary <path>=<values>]... [--cell-size <n>] [--set ...]
+                               [configuration file] [output file]
+
+Options:
+    -v, -vv                Log more detail to stderr: per-object loading and stage starts, then
+                           render progress (also for serve, coordinate and work)
+    --log-json             Log as JSON lines
+    --set <path>=<value>   Override a setting of the configuration, e.g. --set samples=4 or
+                           --set objects[2].color=[255,0,0] (repeatable)
+    --describe             Print a summary of the scene without rendering
+    --export <file>        Write the configuration as loaded, with generated and file geometry
+                           inline, and exit
+    --watch                Re-render a preview whenever the scene or its files change
+    --preview-scale <f>    Resolution scale for --watch previews (default 0.25)
+    --focus-at <x> <y>     Set the configuration's focal_distance to whatever is at that pixel
+                           (of the preview, with --preview-scale) and exit
+    --backend <cpu|gpu>    Where primary rays are intersected (default cpu, gpu is experimental)
+    --threads <n>          Render threads, overriding the configuration (0 = one per core,
+                           1 = reproducible output)
+    --low-priority         Lower the render threads' scheduling priority
+    --time-limit <t>       Keep adding samples after the first pass until the time is up, e.g.
+                           90s, 10m or 1.5h
+    --sample-budget <n>    Keep adding samples after the first pass until n in total
+    --auto-tune            Tune k-d tree, tile and first pass settings from earlier renders to the
+                           same output, recorded in <output>.profile.json
+    --diagnostics          Also write samples-per-pixel and time-per-pixel heatmaps
+                           (<output>.samples.png and <output>.time.png)
+    --cryptomatte          Also write Cryptomatte object and material ID mattes (as layers of
+                           .exr output, otherwise <output>.cryptomatte.exr)
+    --deep                 Also write per-pixel depth, color and coverage samples as a deep EXR
+                           (<output>.deep.exr)
+    --debug-view <view>    Render a debug view instead of shading: normals, uv, depth, object-id,
+                           intersections (objects tested) or traversals (k-d tree nodes visited)
+    --edges                Draw triangle edges over the shaded image
+    --tiled <n>            Render .exr output n pixels square at a time straight into a tiled EXR,
+                           for images too large to hold in memory
+
+Output files ending in .exr are written as linear HDR without tone mapping.
+serve listens on 127.0.0.1:8080 unless an address is given, and renders scenes whose meshes and
+images are under --root (default the working directory), reading relative paths from there.
+coordinate splits the image into tiles (default 32 pixels square) for workers to render.
+preview-material renders the named object's material on a standard stage, lit by point lights
+and a sky and ground fill rather than an HDRI, to img/material.png unless an output file is given.
+bake writes an object's lighting (lightmap, the default) or ambient occlusion (ao) over its
+texture coordinates to a --size texture (default 512), img/bake.png unless an output file is
+given. .exr output keeps lightmaps in linear HDR.
+contact-sheet renders a labeled grid of small images (default 160 pixels) to
+img/contact_sheet.png, the first --vary across and the second down. Values are start:end:count
+or a comma separated list, e.g. --vary objects[0].specular_exponent=5:80:4.";
+
+struct Options {
+    config_file: String,
+    out_file: String,
+    overrides: Vec<String>,
+    describe: bool,
+    export: Option<String>,
+    watch: bool,
+    preview_scale: Option<f64>,
+    focus_at: Option<(usize, usize)>,
+    backend: Backend,
+    threads: Option<usize>,
+    low_priority: bool,
+    time_limit: Option<f64>,
+    sample_budget: Option<usize>,
+    auto_tune: bool,
+    diagnostics: bool,
+    cryptomatte: bool,
+    deep: bool,
+    debug_view: Option<DebugView>,
+    edges: bool,
+    tile_size: Option<usize>,
+}
+
+impl Options {
+    fn parse(args: Vec<String>) -> Result<Options, String> {
+        let mut options = Options {
+            config_file: String::from("./configuration.json"),
+            out_file: String::from("img/scene.png"),
+            overrides: Vec::new(),
+            describe: false,
+            export: None,
+            watch: false,
+            preview_scale: None,
+            focus_at: None,
+            backend: Backend::Cpu,
+            threads: None,
+            low_priority: false,
+            time_limit: None,
+            sample_budget: None,
+            auto_tune: false,
+            diagnostics: false,
+            cryptomatte: false,
+            deep: false,
+            debug_view: None,
+            edges: false,
+            tile_size: None,
+        };
+
+        let mut positional: Vec<String> = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--set" => options.overrides.push(parse_value(&arg, args.next())?),
+                "--describe" => options.describe = true,
+                "--export" => options.export = Some(parse_value(&arg, args.next())?),
+                "--watch" => options.watch = true,
+                "--preview-scale" => {
+                    let preview_scale: f64 = parse_value(&arg, args.next())?;
+                    if preview_scale <= 0f64 {
+                        return Err(format!("{} must be positive", arg));
+                    }
+                    options.preview_scale = Some(preview_scale);
+                }
+                "--focus-at" => {
+                    let x: usize = parse_value(&arg, args.next())?;
+                    let y: usize = parse_value(&arg, args.next())?;
+                    options.focus_at = Some((x, y));
+                }
+                "--backend" => options.backend = parse_value(&arg, args.next())?,
+                "--threads" => options.threads = Some(parse_value(&arg, args.next())?),
+                "--low-priority" => options.low_priority = true,
+                "--time-limit" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    match budget::parse_duration(&value) {
+                        Some(seconds) => options.time_limit = Some(seconds),
+                        None => return Err(format!("Invalid value for {}: {}", arg, value)),
+                    }
+                }
+                "--sample-budget" => options.sample_budget = Some(parse_value(&arg, args.next())?),
+                "--auto-tune" => options.auto_tune = true,
+                "--diagnostics" => options.diagnostics = true,
+                "--cryptomatte" => options.cryptomatte = true,
+                "--deep" => options.deep = true,
+                "--debug-view" => options.debug_view = Some(parse_value(&arg, args.next())?),
+                "--edges" => options.edges = true,
+                "--tiled" => {
+                    let tile_size: usize = parse_value(&arg, args.next())?;
+                    if tile_size == 0 {
+                        return Err(format!("{} must be positive", arg));
+                    }
+                    options.tile_size = Some(tile_size);
+                }
+                "-h" | "--help" => return Err(String::from(USAGE)),
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unknown option: {}\n{}", arg, USAGE))
+                }
+                _ => positional.push(arg),
+            }
+        }
+
+        if positional.len() > 2 {
+            return Err(String::from(USAGE));
+        }
+
+        let mut positional = positional.into_iter();
+        if let Some(config_file) = positional.next() {
+            options.config_file = config_file;
+        }
+        if let Some(out_file) = positional.next() {
+            options.out_file = out_file;
+        }
+
+        if options.tile_size.is_some() && !options.out_file.ends_with(".exr") {
+            return Err(String::from("--tiled needs an .exr output file"));
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_value<T: FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid value for {}: {}", option, value)),
+        None => Err(format!("Missing value for {}", option)),
+    }
+}
+
+fn serve(args: Vec<String>) -> Result<(), String> {
+    let mut args = args.into_iter();
+
+    let mut root: String = String::from(".");
+    let mut positional: Vec<String> = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--root" => root = args.next().ok_or_else(|| String::from(USAGE))?,
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.len() > 1 {
+        return Err(String::from(USAGE));
+    }
+    let address: &str = positional.first().map_or("127.0.0.1:8080", |address| address.as_str());
+
+    ray_tracer::serve(address, &root).map_err(|error| format!("{}: {}", address, error))
+}
+
+fn coordinate(args: Vec<String>) -> Result<(), String> {
+    let mut args = args.into_iter();
+    let address: String = args.next().ok_or_else(|| String::from(USAGE))?;
+
+    let mut tile_size: usize = 32;
+    let mut positional: Vec<String> = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tile-size" => tile_size = parse_value(&arg, args.next())?,
+            _ => positional.push(arg),
+        }
+    }
+
+    if tile_size == 0 || positional.len() > 2 {
+        return Err(String::from(USAGE));
+    }
+
+    let mut positional = positional.into_iter();
+    let config_file: String = positional
+        .next()
+        .unwrap_or_else(|| String::from("./configuration.json"));
+    let out_file: String = positional
+        .next()
+        .unwrap_or_else(|| String::from("img/scene.png"));
+
+    ray_tracer::coordinate(&address, &config_file, &out_file, tile_size)
+        .map_err(|error| format!("{}: {}", address, error))
+}
+
+fn work(args: Vec<String>) -> Result<(), String> {
+    let address: &String = args.first().ok_or_else(|| String::from(USAGE))?;
+
+    ray_tracer::work(address).map_err(|error| format!("{}: {}", address, error))
+}
+
+fn preview_material(args: Vec<String>) -> Result<(), String> {
+    if args.len() < 2 || args.len() > 3 {
+        return Err(String::from(USAGE));
+    }
+    let config_file: &String = &args[0];
+    let out_file: &str = args.get(2).map_or("img/material.png", |out_file| out_file.as_str());
+
+    let configuration: Configuration = Configuration::parse_configuration(config_file)
+        .and_then(|configuration| lookdev::preview_configuration(&configuration, &args[1]))
+        .map_err(|error| format!("{}: {}", config_file, error))?;
+
+    ray_tracer::draw(&configuration, out_file, Backend::Cpu)
+        .map_err(|error| format!("{}: {}", config_file, error))
+}
+
+fn bake(args: Vec<String>) -> Result<(), String> {
+    let mut settings: BakeSettings = BakeSettings::default();
+    let mut overrides: Vec<String> = Vec::new();
+    let mut positional: Vec<String> = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mode" => settings.mode = parse_value(&arg, args.next())?,
+            "--size" => settings.size = parse_value(&arg, args.next())?,
+            "--padding" => settings.padding = parse_value(&arg, args.next())?,
+            "--ao-rays" => settings.ao_rays = parse_value(&arg, args.next())?,
+            "--ao-distance" => settings.ao_distance = Some(parse_value(&arg, args.next())?),
+            "--set" => overrides.push(parse_value(&arg, args.next())?),
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown option: {}\n{}", arg, USAGE))
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.is_empty() || positional.len() > 3 || settings.size == 0 {
+        return Err(String::from(USAGE));
+    }
+
+    let mut positional = positional.into_iter();
+    let object_name: String = positional.next().unwrap();
+    let config_file: String = positional
+        .next()
+        .unwrap_or_else(|| String::from("./configuration.json"));
+    let out_file: String = positional
+        .next()
+        .unwrap_or_else(|| String::from("img/bake.png"));
+
+    let texture = Configuration::parse_configuration(&config_file)
+        .and_then(|configuration| configuration.with_overrides(&overrides))
+        .and_then(|configuration| bake::bake(&configuration, &object_name, &settings))
+        .map_err(|error| format!("{}: {}", config_file, error))?;
+
+    if out_file.ends_with(".exr") {
+        ExrImage::from_radiance(&texture, settings.size, settings.size)
+            .save(&out_file)
+            .map_err(|error| format!("{}: {}", out_file, error))?;
+    } else {
+        ray_tracer::save_image(&out_file, &texture, settings.size, settings.size)
+            .map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
+
+fn contact_sheet(args: Vec<String>) -> Result<(), String> {
+    let mut sweeps: Vec<Sweep> = Vec::new();
+    let mut cell_size: usize = 160;
+    let mut overrides: Vec<String> = Vec::new();
+    let mut positional: Vec<String> = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--vary" => sweeps.push(Sweep::parse(&parse_value::<String>(&arg, args.next())?)?),
+            "--cell-size" => cell_size = parse_value(&arg, args.next())?,
+            "--set" => overrides.push(parse_value(&arg, args.next())?),
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown option: {}\n{}", arg, USAGE))
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    if sweeps.is_empty() || sweeps.len() > 2 || cell_size == 0 || positional.len() > 2 {
+        return Err(String::from(USAGE));
+    }
+
+    let mut positional = positional.into_iter();
+    let config_file: String = positional
+        .next()
+        .unwrap_or_else(|| String::from("./configuration.json"));
+    let out_file: String = positional
+        .next()
+        .unwrap_or_else(|| String::from("img/contact_sheet.png"));
+
+    let (sheet, width, height) = Configuration::parse_configuration(&config_file)
+        .and_then(|configuration| configuration.with_overrides(&overrides))
+        .and_then(|configuration| contact_sheet::render(&configuration, &sweeps, cell_size))
+        .map_err(|error| format!("{}: {}", config_file, error))?;
+
+    ray_tracer::save_image(&out_file, &sheet, width, height).map_err(|error| error.to_string())
+}
+
+// Logging flags apply to every command, so they are taken out before any parsing
+fn init_logging(args: Vec<String>) -> Vec<String> {
+    let mut verbosity: u8 = 0;
+    let mut json: bool = false;
+
+    let args: Vec<String> = args
+        .into_iter()
+        .filter(|arg| match arg.as_str() {
+            "-v" => {
+                verbosity += 1;
+                false
+            }
+            "-vv" => {
+                verbosity += 2;
+                false
+            }
+            "--log-json" => {
+                json = true;
+                false
+            }
+            _ => true,
+        })
+        .collect();
+
+    logging::init(verbosity, json);
+    args
+}
 
 fn main() {
-    ray_tracer::draw("./configuration.json", "img/scene.png");
+    let args: Vec<String> = init_logging(env::args().skip(1).collect());
+
+    let result: Option<Result<(), String>> = match args.first().map(|arg| arg.as_str()) {
+        Some("serve") => Some(serve(args[1..].to_vec())),
+        Some("coordinate") => Some(coordinate(args[1..].to_vec())),
+        Some("work") => Some(work(args[1..].to_vec())),
+        Some("preview-material") => Some(preview_material(args[1..].to_vec())),
+        Some("bake") => Some(bake(args[1..].to_vec())),
+        Some("contact-sheet") => Some(contact_sheet(args[1..].to_vec())),
+        _ => None,
+    };
+
+    if let Some(result) = result {
+        if let Err(message) = result {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let options: Options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    };
+
+    if options.describe {
+        match ray_tracer::describe(&options.config_file, &options.overrides) {
+            Ok(description) => println!("{}", description),
+            Err(error) => {
+                eprintln!("{}: {}", options.config_file, error);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some((x, y)) = options.focus_at {
+        match ray_tracer::focus_at(
+            &options.config_file,
+            &options.overrides,
+            x,
+            y,
+            options.preview_scale,
+        ) {
+            Ok(focal_distance) => println!("Focal distance set to {:.3}", focal_distance),
+            Err(error) => {
+                eprintln!("{}: {}", options.config_file, error);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if options.watch {
+        let preview_scale: f64 = options.preview_scale.unwrap_or(0.25);
+        if let Err(error) = ray_tracer::watch(
+            &options.config_file,
+            &options.overrides,
+            &options.out_file,
+            preview_scale,
+        ) {
+            eprintln!("{}: {}", options.config_file, error);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut configuration: Configuration =
+        match Configuration::parse_configuration(&options.config_file)
+            .and_then(|configuration| configuration.with_overrides(&options.overrides))
+        {
+            Ok(configuration) => configuration,
+            Err(error) => {
+                eprintln!("{}: {}", options.config_file, error);
+                process::exit(1);
+            }
+        };
+
+    if let Some(threads) = options.threads {
+        configuration.threads = threads;
+    }
+    configuration.low_priority |= options.low_priority;
+    if options.time_limit.is_some() {
+        configuration.time_limit = options.time_limit;
+    }
+    if options.sample_budget.is_some() {
+        configuration.sample_budget = options.sample_budget;
+    }
+    configuration.auto_tune |= options.auto_tune;
+    configuration.diagnostics |= options.diagnostics;
+    configuration.cryptomatte |= options.cryptomatte;
+    configuration.deep |= options.deep;
+    if options.debug_view.is_some() {
+        configuration.debug_view = options.debug_view;
+    }
+    if options.edges && configuration.edge_overlay.is_none() {
+        configuration.edge_overlay = Some(EdgeOverlay::default());
+    }
+
+    if let Some(ref export_file) = options.export {
+        if let Err(error) = configuration
+            .resolved()
+            .and_then(|resolved| resolved.save(export_file))
+        {
+            eprintln!("{}: {}", export_file, error);
+            process::exit(1);
+        }
+        println!("Resolved configuration written to {}", export_file);
+        return;
+    }
+
+    if let Some(tile_size) = options.tile_size {
+        if let Err(error) = ray_tracer::draw_tiled(&configuration, &options.out_file, tile_size) {
+            eprintln!("{}: {}", options.out_file, error);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Err(error) = ray_tracer::draw(&configuration, &options.out_file, options.backend) {
+        eprintln!("{}: {}", options.config_file, error);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,311 @@
+extern crate cgmath;
+extern crate pollster;
+extern crate wgpu;
+
+use self::cgmath::*;
+use self::wgpu::util::DeviceExt;
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use ray_tracer::camera::Projection;
+use ray_tracer::color::Radiance;
+use ray_tracer::kd_tree::KdTree;
+use ray_tracer::light::Light;
+use ray_tracer::object::Shape;
+use ray_tracer::scene::Scene;
+use ray_tracer::scene::configuration::Configuration;
+use ray_tracer::volume::Volume;
+
+const WORKGROUP_SIZE: u32 = 8;
+
+// Experimental hybrid path - a compute shader finds the closest primitive along
+// each pixel's center ray, then CPU threads shade, reflect and multisample. The shader
+// tests every primitive without the k-d tree, so it helps most with high resolution
+// previews of modest scenes
+pub struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Gpu {
+    pub fn new() -> Result<Gpu, String> {
+        let instance: wgpu::Instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+
+        let adapter: wgpu::Adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            })).ok_or_else(|| String::from("no compatible GPU adapter"))?;
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("raytracer"),
+                required_features: wgpu::Features::empty(),
+                required_limits: wgpu::Limits::downlevel_defaults(),
+            },
+            None,
+        )).map_err(|error| error.to_string())?;
+
+        let shader: wgpu::ShaderModule =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("primary"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("primary.wgsl").into()),
+            });
+
+        let pipeline: wgpu::ComputePipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("primary"),
+                layout: None,
+                module: &shader,
+                entry_point: "main",
+            });
+
+        Ok(Gpu {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    // Index into shapes of the closest hit for each pixel, row-major - u32::MAX for a miss
+    pub fn primary_hits(&self, scene: &Scene, shapes: &[Shape]) -> Result<Vec<u32>, String> {
+        let width: usize = scene.width();
+        let height: usize = scene.height();
+        let (origin, right, up, forward) = scene.viewport_basis();
+
+        let mut camera: Vec<u8> = Vec::with_capacity(80);
+        for vector in &[origin, right, up, forward] {
+            push_vector(&mut camera, *vector, 0f32);
+        }
+        for value in &[width as u32, height as u32, shapes.len() as u32, 0u32] {
+            camera.extend_from_slice(&value.to_ne_bytes());
+        }
+
+        // Storage buffers can't be empty - an unused primitive pads an empty scene
+        let mut primitives: Vec<u8> = Vec::with_capacity(shapes.len().max(1) * 48);
+        for shape in shapes {
+            match *shape {
+                Shape::Sphere(sphere) => {
+                    push_vector(&mut primitives, sphere.origin(), sphere.radius() as f32);
+                    push_vector(&mut primitives, Vector3::zero(), 0f32);
+                    push_vector(&mut primitives, Vector3::zero(), 0f32);
+                }
+                Shape::Triangle(triangle) => {
+                    let [p1, p2, p3] = triangle.vertices();
+                    push_vector(&mut primitives, p1, 0f32);
+                    push_vector(&mut primitives, p2, 1f32);
+                    push_vector(&mut primitives, p3, 0f32);
+                }
+            }
+        }
+        primitives.resize(primitives.len().max(48), 0u8);
+
+        let hits_size: u64 = (width * height * 4) as u64;
+
+        let camera_buffer: wgpu::Buffer =
+            self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("camera"),
+                contents: &camera,
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let primitive_buffer: wgpu::Buffer =
+            self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("primitives"),
+                contents: &primitives,
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let hit_buffer: wgpu::Buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hits"),
+            size: hits_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer: wgpu::Buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size: hits_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group: wgpu::BindGroup = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("primary"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: primitive_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: hit_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder: wgpu::CommandEncoder = self.device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("primary"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                (width as u32).div_ceil(WORKGROUP_SIZE),
+                (height as u32).div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&hit_buffer, 0, &staging_buffer, 0, hits_size);
+        self.queue.submit(Some(encoder.finish()));
+
+        // Wait for the results
+        let slice: wgpu::BufferSlice = staging_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        match receiver.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => return Err(error.to_string()),
+            Err(error) => return Err(error.to_string()),
+        }
+
+        let hits: Vec<u32> = slice
+            .get_mapped_range()
+            .chunks_exact(4)
+            .map(|bytes| u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        staging_buffer.unmap();
+
+        Ok(hits)
+    }
+}
+
+fn push_vector(bytes: &mut Vec<u8>, vector: Vector3<f64>, w: f32) {
+    for value in &[vector.x as f32, vector.y as f32, vector.z as f32, w] {
+        bytes.extend_from_slice(&value.to_ne_bytes());
+    }
+}
+
+// Trace the configured scene with GPU primary visibility and CPU shading
+pub fn trace(
+    configuration: &Configuration,
+    shapes: &[Shape],
+    progress: &Arc<AtomicUsize>,
+) -> Result<Vec<Vec<Radiance>>, String> {
+    // The shader tests every primitive - clipped geometry would still be hit
+    if !configuration.clipping_planes.is_empty() {
+        return Err(String::from("clipping planes are not supported"));
+    }
+    if configuration.objects.iter().any(|object| object.volume.is_some()) {
+        return Err(String::from("volumes are not supported"));
+    }
+    if configuration.stereo.is_some() {
+        return Err(String::from("stereo is not supported"));
+    }
+    if configuration.projection != Projection::Perspective {
+        return Err(String::from("fulldome projection is not supported"));
+    }
+    if configuration.lens_distortion.is_some() || !configuration.lens_elements.is_empty() {
+        return Err(String::from("lens distortion and lens elements are not supported"));
+    }
+    // Primary hits are shaded directly, so nothing else about the samples is recorded
+    if configuration.debug_view.is_some() {
+        return Err(String::from("debug views are not supported"));
+    }
+    if configuration.cryptomatte || configuration.deep || configuration.diagnostics {
+        return Err(String::from("ID mattes, deep output and diagnostics are not supported"));
+    }
+    if configuration.time_limit.is_some() || configuration.sample_budget.is_some() {
+        return Err(String::from("time limits and sample budgets are not supported"));
+    }
+
+    // Find an adapter before spending time on the tree
+    let gpu: Gpu = Gpu::new()?;
+    let kd_tree: Arc<KdTree> = Arc::new(
+        super::super::kd_tree_from_shapes(shapes.to_vec(), configuration)
+            .map_err(|error| error.to_string())?,
+    );
+
+    let width: usize = configuration.width;
+    let height: usize = configuration.height;
+    let threads: usize = configuration.thread_count();
+
+    // Volumes were rejected above
+    let volumes: Arc<Vec<Volume>> = Arc::new(Vec::new());
+    let lights: Arc<Vec<Light>> = Arc::new(
+        super::super::load_lights(configuration).map_err(|error| error.to_string())?,
+    );
+    let scenes: Vec<Scene> = (0..threads)
+        .map(|_| {
+            Scene::new(
+                configuration,
+                Arc::clone(&kd_tree),
+                Arc::clone(&volumes),
+                Arc::clone(&lights),
+            )
+        })
+        .collect::<io::Result<Vec<Scene>>>()
+        .map_err(|error| error.to_string())?;
+    let hits: Vec<u32> = gpu.primary_hits(&scenes[0], shapes)?;
+
+    // Columns are interleaved between threads, each with its own scene
+    let columns: Vec<(usize, Vec<Radiance>)> = thread::scope(|scope| {
+        let handles: Vec<thread::ScopedJoinHandle<Vec<(usize, Vec<Radiance>)>>> = scenes
+            .into_iter()
+            .enumerate()
+            .map(|(thread_number, mut scene)| {
+                let hits: &[u32] = &hits;
+
+                scope.spawn(move || {
+                    if configuration.low_priority {
+                        super::super::lower_thread_priority();
+                    }
+
+                    (thread_number..width)
+                        .step_by(threads)
+                        .map(|x| {
+                            let column: Vec<Radiance> = (0..height)
+                                .map(|y| {
+                                    let primary: Option<Shape> =
+                                        shapes.get(hits[y * width + x] as usize).cloned();
+                                    let color: Radiance = scene.sample_with_primary(x, y, primary);
+                                    progress.fetch_add(1, Ordering::Relaxed);
+                                    color
+                                })
+                                .collect();
+
+                            (x, column)
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let mut color_buffer: Vec<Vec<Radiance>> = vec![Vec::new(); width];
+    for (x, column) in columns {
+        color_buffer[x] = column;
+    }
+
+    Ok(color_buffer)
+}
@@ -0,0 +1,24 @@
+use std::str::FromStr;
+
+#[cfg(feature = "gpu")]
+pub mod gpu;
+
+// Where primary visibility is computed - shading always happens on the CPU
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Backend {
+    #[default]
+    Cpu,
+    Gpu,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Backend, String> {
+        match name {
+            "cpu" => Ok(Backend::Cpu),
+            "gpu" => Ok(Backend::Gpu),
+            _ => Err(format!("Unknown backend: {} (expected cpu or gpu)", name)),
+        }
+    }
+}
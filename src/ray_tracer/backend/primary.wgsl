@@ -0,0 +1,121 @@
+// Primary visibility - the index of the closest primitive along each pixel's
+// center ray, or MISS. Mirrors Scene::generate_ray and the CPU intersection tests.
+
+struct Camera {
+    origin: vec4<f32>,
+    right: vec4<f32>,
+    up: vec4<f32>,
+    forward: vec4<f32>,
+    // width, height, primitive count
+    size: vec4<u32>,
+};
+
+// Sphere: a = origin + radius, b.w = 0
+// Triangle: a, b, c = vertices, b.w = 1
+struct Primitive {
+    a: vec4<f32>,
+    b: vec4<f32>,
+    c: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var<storage, read> primitives: array<Primitive>;
+@group(0) @binding(2) var<storage, read_write> hits: array<u32>;
+
+const MISS: u32 = 0xffffffffu;
+const NO_HIT: f32 = -1.0;
+// Squared determinant, relative to the ray's and edges' squared lengths, below which a ray
+// is taken as parallel to the triangle - as on the CPU, so it holds in any units
+const PARALLEL_TOLERANCE: f32 = 1e-12;
+
+fn intersect_sphere(origin: vec3<f32>, direction: vec3<f32>, sphere: Primitive) -> f32 {
+    let diff = sphere.a.xyz - origin;
+    let tca = dot(diff, direction);
+    if tca < 0.0 {
+        return NO_HIT;
+    }
+
+    let d2 = dot(diff, diff) - tca * tca;
+    let radius_squared = sphere.a.w * sphere.a.w;
+    if d2 > radius_squared {
+        return NO_HIT;
+    }
+
+    let t = tca - sqrt(radius_squared - d2);
+    if t < 0.0 {
+        return NO_HIT;
+    }
+
+    return t;
+}
+
+// Moller-Trumbore
+fn intersect_triangle(origin: vec3<f32>, direction: vec3<f32>, triangle: Primitive) -> f32 {
+    let v1v2 = triangle.b.xyz - triangle.a.xyz;
+    let v1v3 = triangle.c.xyz - triangle.a.xyz;
+
+    let h = cross(direction, v1v3);
+    let a = dot(v1v2, h);
+    if a * a <= PARALLEL_TOLERANCE * dot(direction, direction) * dot(v1v2, v1v2) * dot(v1v3, v1v3) {
+        return NO_HIT;
+    }
+
+    let f = 1.0 / a;
+    let s = origin - triangle.a.xyz;
+    let u = f * dot(s, h);
+    if u < 0.0 || u > 1.0 {
+        return NO_HIT;
+    }
+
+    let q = cross(s, v1v2);
+    let v = f * dot(direction, q);
+    if v < 0.0 || u + v > 1.0 {
+        return NO_HIT;
+    }
+
+    let t = f * dot(v1v3, q);
+    if t <= 0.0 {
+        return NO_HIT;
+    }
+
+    return t;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let width = camera.size.x;
+    let height = camera.size.y;
+    if id.x >= width || id.y >= height {
+        return;
+    }
+
+    // normalize x and y from -0.5 to 0.5
+    let normalized_x = f32(id.x) / f32(width) - 0.5;
+    let normalized_y = f32(id.y) / f32(height) - 0.5;
+
+    let origin = camera.origin.xyz;
+    let direction = normalize(
+        normalized_x * camera.right.xyz + normalized_y * camera.up.xyz + camera.forward.xyz
+    );
+
+    var closest: f32 = 3.4e38;
+    var hit: u32 = MISS;
+
+    for (var i: u32 = 0u; i < camera.size.z; i = i + 1u) {
+        let primitive = primitives[i];
+
+        var t: f32;
+        if primitive.b.w == 0.0 {
+            t = intersect_sphere(origin, direction, primitive);
+        } else {
+            t = intersect_triangle(origin, direction, primitive);
+        }
+
+        if t >= 0.0 && t < closest {
+            closest = t;
+            hit = i;
+        }
+    }
+
+    hits[id.y * width + id.x] = hit;
+}
@@ -0,0 +1,311 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+
+use super::color::Radiance;
+use super::kd_tree::KdTree;
+use super::light::Light;
+use super::logging::{self, Stage};
+use super::object::Shape;
+use super::object::triangle::Triangle;
+use super::scene::Scene;
+use super::scene::configuration::Configuration;
+use super::volume::Volume;
+
+// What a bake writes for each texel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BakeMode {
+    // Light arriving at the surface, as Scene::irradiance - diffuse and ambient weighted by
+    // the material's coefficients but not its color, so multiplying by the color shades it
+    // without specular highlights or reflections. .exr output keeps values above 1
+    Lightmap,
+    // How open the surface is to its surroundings, white where nothing is nearby
+    AmbientOcclusion,
+}
+
+impl FromStr for BakeMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<BakeMode, String> {
+        match name {
+            "lightmap" => Ok(BakeMode::Lightmap),
+            "ao" => Ok(BakeMode::AmbientOcclusion),
+            _ => Err(format!("Unknown bake mode: {} (expected lightmap or ao)", name)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BakeSettings {
+    pub mode: BakeMode,
+    // Width and height of the texture
+    pub size: usize,
+    // Texels grown out from the edges of each UV island, so filtering doesn't bleed
+    // the background into them
+    pub padding: usize,
+    pub ao_rays: usize,
+    // How far away geometry still occludes, defaults to half the object's diagonal
+    pub ao_distance: Option<f64>,
+}
+
+impl Default for BakeSettings {
+    fn default() -> BakeSettings {
+        BakeSettings {
+            mode: BakeMode::Lightmap,
+            size: 512,
+            padding: 2,
+            ao_rays: 64,
+            ao_distance: None,
+        }
+    }
+}
+
+// The surface each texel's center lands on
+type Texel = Option<(Triangle, Vector3<f64>)>;
+
+// Baked columns by x, None where no surface was found
+type Columns = Vec<(usize, Vec<Option<Radiance>>)>;
+
+// Evaluate the lighting of the named object over its texture coordinates, lit by the rest
+// of the scene, into a size x size texture - column-major as the renderer's color buffers,
+// v = 1 along the top row. Surfaces are lit on their front, counter-clockwise, side
+pub fn bake(
+    configuration: &Configuration,
+    object_name: &str,
+    settings: &BakeSettings,
+) -> io::Result<Vec<Vec<Radiance>>> {
+    let object_id: usize = configuration.find_object(object_name).ok_or_else(|| {
+        io::Error::other(format!(
+            "No object named {} - objects are: {}",
+            object_name,
+            configuration.object_names().join(", ")
+        ))
+    })?;
+
+    let shapes: Vec<Shape> = super::load_shapes(configuration)?;
+    let triangles: Vec<Triangle> = shapes
+        .iter()
+        .filter_map(|shape| match *shape {
+            Shape::Triangle(triangle)
+                if triangle.object_id == object_id && triangle.texture_coordinates.is_some() =>
+            {
+                Some(triangle)
+            }
+            _ => None,
+        })
+        .collect();
+    if triangles.is_empty() {
+        return Err(io::Error::other(format!(
+            "{} has no triangles with texture coordinates to bake",
+            object_name
+        )));
+    }
+
+    let ao_distance: f64 = settings.ao_distance.unwrap_or_else(|| diagonal(&triangles) / 2f64);
+    let texels: Arc<Vec<Texel>> = Arc::new(rasterize(&triangles, settings.size));
+    let kd_tree: Arc<KdTree> = Arc::new(super::kd_tree_from_shapes(shapes, configuration)?);
+    let volumes: Arc<Vec<Volume>> = Arc::new(super::load_volumes(configuration)?);
+    let lights: Arc<Vec<Light>> = Arc::new(super::load_lights(configuration)?);
+
+    let _stage: Stage = logging::stage("Baking");
+    let threads: usize = configuration.thread_count();
+    info!(
+        "Baking {} texels of {} on {} threads",
+        texels.iter().filter(|texel| texel.is_some()).count(),
+        object_name,
+        threads
+    );
+
+    let scenes: Vec<Scene> = (0..threads)
+        .map(|_| {
+            Scene::new(
+                configuration,
+                Arc::clone(&kd_tree),
+                Arc::clone(&volumes),
+                Arc::clone(&lights),
+            )
+        })
+        .collect::<io::Result<Vec<Scene>>>()?;
+
+    // Each thread takes every threads-th column
+    let thread_handles: Vec<thread::JoinHandle<Columns>> = scenes
+        .into_iter()
+        .enumerate()
+        .map(|(i, scene)| {
+            let texels: Arc<Vec<Texel>> = Arc::clone(&texels);
+            let settings: BakeSettings = *settings;
+
+            thread::spawn(move || {
+                (i..settings.size)
+                    .step_by(threads)
+                    .map(|x| {
+                        let column: Vec<Option<Radiance>> = (0..settings.size)
+                            .map(|y| {
+                                texels[y * settings.size + x].map(|(triangle, point)| {
+                                    evaluate(&scene, triangle, point, &settings, ao_distance)
+                                })
+                            })
+                            .collect();
+                        (x, column)
+                    })
+                    .collect()
+            })
+        })
+        .collect();
+
+    let mut texture: Vec<Vec<Option<Radiance>>> = vec![Vec::new(); settings.size];
+    for thread_handle in thread_handles {
+        for (x, column) in thread_handle.join().unwrap() {
+            texture[x] = column;
+        }
+    }
+    kd_tree.check_pages()?;
+
+    for _ in 0..settings.padding {
+        dilate(&mut texture);
+    }
+
+    Ok(texture
+        .into_iter()
+        .map(|column| {
+            column
+                .into_iter()
+                .map(|texel| texel.unwrap_or_else(Radiance::black))
+                .collect()
+        })
+        .collect())
+}
+
+fn evaluate(
+    scene: &Scene,
+    triangle: Triangle,
+    point: Vector3<f64>,
+    settings: &BakeSettings,
+    ao_distance: f64,
+) -> Radiance {
+    let shape: Shape = Shape::Triangle(triangle);
+
+    match settings.mode {
+        BakeMode::Lightmap => scene.irradiance(shape, point, triangle.normal),
+        BakeMode::AmbientOcclusion => {
+            let open: f64 = scene.ambient_occlusion(
+                shape,
+                point,
+                triangle.normal,
+                settings.ao_rays,
+                ao_distance,
+            );
+            Radiance::new(open, open, open)
+        }
+    }
+}
+
+// Row-major, the triangle and point on it under each texel's center - where UVs overlap,
+// the last triangle wins
+fn rasterize(triangles: &[Triangle], size: usize) -> Vec<Texel> {
+    let mut texels: Vec<Texel> = vec![None; size * size];
+    let scale: f64 = size as f64;
+
+    for triangle in triangles {
+        let uvs: [Vector2<f64>; 3] = match triangle.texture_coordinates {
+            Some(uvs) => uvs,
+            None => continue,
+        };
+        // Texel space, y down
+        let corners: Vec<Vector2<f64>> = uvs
+            .iter()
+            .map(|uv| Vector2::new(uv.x * scale, (1f64 - uv.y) * scale))
+            .collect();
+
+        let min_x: f64 = corners.iter().map(|corner| corner.x).fold(f64::MAX, f64::min);
+        let max_x: f64 = corners.iter().map(|corner| corner.x).fold(f64::MIN, f64::max);
+        let min_y: f64 = corners.iter().map(|corner| corner.y).fold(f64::MAX, f64::min);
+        let max_y: f64 = corners.iter().map(|corner| corner.y).fold(f64::MIN, f64::max);
+
+        let vertices: [Vector3<f64>; 3] = triangle.vertices();
+        for y in texel_range(min_y, max_y, size) {
+            for x in texel_range(min_x, max_x, size) {
+                let center: Vector2<f64> = Vector2::new(x as f64 + 0.5, y as f64 + 0.5);
+                if let Some(weights) = barycentric(&corners, center) {
+                    let point: Vector3<f64> =
+                        vertices[0] * weights.x + vertices[1] * weights.y + vertices[2] * weights.z;
+                    texels[y * size + x] = Some((*triangle, point));
+                }
+            }
+        }
+    }
+
+    texels
+}
+
+// Texels whose centers may fall between min and max
+fn texel_range(min: f64, max: f64, size: usize) -> ::std::ops::Range<usize> {
+    let start: usize = (min - 0.5).ceil().max(0f64) as usize;
+    let end: usize = ((max - 0.5).floor() + 1f64).clamp(0f64, size as f64) as usize;
+
+    start..end.max(start)
+}
+
+// Weights of the corners for a point inside the triangle they make
+fn barycentric(corners: &[Vector2<f64>], point: Vector2<f64>) -> Option<Vector3<f64>> {
+    let (a, b, c): (Vector2<f64>, Vector2<f64>, Vector2<f64>) =
+        (corners[0], corners[1], corners[2]);
+    let area: f64 = (b - a).perp_dot(c - a);
+    if area == 0f64 {
+        return None;
+    }
+
+    let v: f64 = (point - a).perp_dot(c - a) / area;
+    let w: f64 = (b - a).perp_dot(point - a) / area;
+    let u: f64 = 1f64 - v - w;
+
+    if u < 0f64 || v < 0f64 || w < 0f64 {
+        None
+    } else {
+        Some(Vector3::new(u, v, w))
+    }
+}
+
+fn diagonal(triangles: &[Triangle]) -> f64 {
+    let min: Vector3<f64> = triangles.iter().fold(Vector3::from_value(f64::MAX), |min, triangle| {
+        let extent: Vector3<f64> = triangle.min_extent();
+        Vector3::new(min.x.min(extent.x), min.y.min(extent.y), min.z.min(extent.z))
+    });
+    let max: Vector3<f64> = triangles.iter().fold(Vector3::from_value(f64::MIN), |max, triangle| {
+        let extent: Vector3<f64> = triangle.max_extent();
+        Vector3::new(max.x.max(extent.x), max.y.max(extent.y), max.z.max(extent.z))
+    });
+
+    (max - min).magnitude()
+}
+
+// Fill each empty texel next to baked ones with their average
+fn dilate(texture: &mut [Vec<Option<Radiance>>]) {
+    let size: usize = texture.len();
+    let source: Vec<Vec<Option<Radiance>>> = texture.to_vec();
+
+    for x in 0..size {
+        for y in 0..size {
+            if source[x][y].is_some() {
+                continue;
+            }
+
+            let neighbors: Vec<Radiance> = (x.saturating_sub(1)..(x + 2).min(size))
+                .flat_map(|nx| {
+                    (y.saturating_sub(1)..(y + 2).min(size)).filter_map(|ny| source[nx][ny])
+                        .collect::<Vec<Radiance>>()
+                })
+                .collect();
+            if !neighbors.is_empty() {
+                let sum: Radiance =
+                    neighbors.iter().fold(Radiance::black(), |sum, neighbor| sum + *neighbor);
+                texture[x][y] = Some(sum * (1f64 / neighbors.len() as f64));
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Time and samples a render may spend refining beyond its first pass, shared by every thread.
+// Refinement adds one jittered sample per pixel at a time across the whole image; if time
+// runs out during the first pass, its remaining pixels get a single sample each. Time counts
+// from the start of tracing, and stereo renders give each eye half
+pub struct RenderBudget {
+    deadline: Option<Instant>,
+    // Time the render spent paused, which moves the deadline back
+    paused_nanos: AtomicU64,
+    // Primary samples left for the whole image
+    samples: Option<usize>,
+    // Refinement samples taken so far
+    taken: AtomicUsize,
+}
+
+// Longest time limit accepted - a year
+pub const MAX_TIME_LIMIT: f64 = 365f64 * 24f64 * 3600f64;
+
+// Seconds from e.g. "90", "90s", "10m" or "1.5h"
+pub fn parse_duration(value: &str) -> Option<f64> {
+    let (number, unit): (&str, f64) = match value.chars().last()? {
+        's' => (&value[..value.len() - 1], 1f64),
+        'm' => (&value[..value.len() - 1], 60f64),
+        'h' => (&value[..value.len() - 1], 3600f64),
+        _ => (value, 1f64),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .map(|seconds| seconds * unit)
+        .filter(|seconds| seconds.is_finite() && (0f64..=MAX_TIME_LIMIT).contains(seconds))
+}
+
+impl RenderBudget {
+    // Starting now, with samples already spent on the first pass
+    pub fn new(
+        time_limit: Option<f64>,
+        sample_budget: Option<usize>,
+        spent: usize,
+    ) -> io::Result<RenderBudget> {
+        let deadline: Option<Instant> = match time_limit {
+            // Also rejects NaN
+            Some(seconds) if !(0f64..=MAX_TIME_LIMIT).contains(&seconds) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "time_limit of {} seconds is not between 0 and {}",
+                        seconds, MAX_TIME_LIMIT
+                    ),
+                ));
+            }
+            Some(seconds) => Some(
+                Instant::now()
+                    .checked_add(Duration::from_secs_f64(seconds))
+                    .ok_or_else(|| io::Error::other("time_limit is past the end of the clock"))?,
+            ),
+            None => None,
+        };
+
+        Ok(RenderBudget {
+            deadline,
+            paused_nanos: AtomicU64::new(0),
+            samples: sample_budget.map(|samples| samples.saturating_sub(spent)),
+            taken: AtomicUsize::new(0),
+        })
+    }
+
+    // Samples a thread may take for its share of the image's pixels, so every thread
+    // refines as far - None without a sample budget
+    pub fn share(&self, pixels: usize, total_pixels: usize) -> Option<usize> {
+        self.samples
+            .map(|samples| (samples as f64 * pixels as f64 / total_pixels as f64) as usize)
+    }
+
+    // Whether the time limit has passed, not counting time paused
+    pub fn expired(&self) -> bool {
+        let paused: Duration = Duration::from_nanos(self.paused_nanos.load(Ordering::Relaxed));
+        self.deadline
+            .and_then(|deadline| deadline.checked_add(paused))
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    pub fn extend(&self, paused: Duration) {
+        self.paused_nanos
+            .fetch_add(paused.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    // Record a sample - false once time is up
+    pub fn take(&self) -> bool {
+        if self.expired() {
+            return false;
+        }
+
+        self.taken.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    pub fn taken(&self) -> usize {
+        self.taken.load(Ordering::Relaxed)
+    }
+}
@@ -2,6 +2,7 @@ extern crate cgmath;
 
 use self::cgmath::*;
 
+#[derive(Clone, Copy)]
 pub struct Camera {
     pub origin: Vector3<f64>,
     pub target: Vector3<f64>,
@@ -17,3 +18,112 @@ impl Camera {
         (self.target - self.origin).normalize()
     }
 }
+
+// Lens and exposure settings of a real camera, used in place of the viewport width, e.g.
+// "physical_camera": {"focal_length": 50, "sensor_width": 36, "f_stop": 2.8, "shutter": 0.01}
+// Shutter, ISO and f-stop fix the exposure in place of auto exposure, so a sequence keeps one
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PhysicalCamera {
+    // Millimetres
+    pub focal_length: f64,
+    // Millimetres - the image spans the sensor's width
+    #[serde(default = "full_frame_width")]
+    pub sensor_width: f64,
+    #[serde(default = "default_f_stop")]
+    pub f_stop: f64,
+    // Seconds
+    #[serde(default = "default_shutter")]
+    pub shutter: f64,
+    #[serde(default = "default_iso")]
+    pub iso: f64,
+    // Millimetres per scene unit - with it, the f-stop's opening gives depth of field
+    #[serde(default)]
+    pub unit_length: Option<f64>,
+}
+
+fn full_frame_width() -> f64 {
+    36f64
+}
+
+fn default_f_stop() -> f64 {
+    8f64
+}
+
+fn default_shutter() -> f64 {
+    1f64 / 125f64
+}
+
+fn default_iso() -> f64 {
+    100f64
+}
+
+impl PhysicalCamera {
+    // Viewport width at the given distance with the lens' horizontal field of view
+    pub fn viewport_width(&self, viewport_distance: f64) -> f64 {
+        viewport_distance * self.sensor_width / self.focal_length
+    }
+
+    // Change the sensor width to give the viewport width at the given distance
+    pub fn set_viewport_width(&mut self, viewport_width: f64, viewport_distance: f64) {
+        self.sensor_width = viewport_width * self.focal_length / viewport_distance;
+    }
+
+    // Radiance scale before the tone curve - 1 at the "sunny 16" exposure of f/16,
+    // 1/100 s and ISO 100, doubling with each stop
+    pub fn exposure(&self) -> f64 {
+        256f64 * self.shutter * self.iso / (self.f_stop * self.f_stop)
+    }
+
+    // Thin lens radius in scene units, if the scene's scale is known
+    pub fn aperture(&self) -> Option<f64> {
+        self.unit_length
+            .map(|unit_length| self.focal_length / self.f_stop / 2f64 / unit_length)
+    }
+}
+
+// How primary rays leave the camera
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    #[default]
+    Perspective,
+    // Angular fisheye dome master - the camera direction is the dome's zenith at the image
+    // center, the horizon at the edge of the inscribed circle, camera up toward the image top.
+    // Pixels outside the circle stay black without tracing, and lens settings are ignored
+    Fulldome {
+        // Degrees across the circle
+        #[serde(default = "hemisphere")]
+        angle: f64,
+    },
+}
+
+fn hemisphere() -> f64 {
+    180f64
+}
+
+// Position in a dome master's circle, of radius 1, of the normalized image point x, y
+// (-0.5 to 0.5) of a width x height image
+pub fn fulldome_coordinates(x: f64, y: f64, width: usize, height: usize) -> (f64, f64) {
+    let size: f64 = width.min(height) as f64;
+    (2f64 * x * width as f64 / size, 2f64 * y * height as f64 / size)
+}
+
+// Whether the point is within the dome - the rest of the image is masked
+pub fn in_fulldome(x: f64, y: f64, width: usize, height: usize) -> bool {
+    let (u, v): (f64, f64) = fulldome_coordinates(x, y, width, height);
+    u * u + v * v <= 1f64
+}
+
+// Direction, in the camera's right, down and forward axes, a dome of angle degrees sees at
+// the point - beyond the circle it keeps going past the horizon, for antialiased edges
+pub fn fulldome_direction(angle: f64, x: f64, y: f64, width: usize, height: usize) -> Vector3<f64> {
+    let (u, v): (f64, f64) = fulldome_coordinates(x, y, width, height);
+    let theta: f64 = (u * u + v * v).sqrt() * angle.to_radians() / 2f64;
+    let phi: f64 = v.atan2(u);
+
+    Vector3::new(
+        theta.sin() * phi.cos(),
+        theta.sin() * phi.sin(),
+        theta.cos(),
+    )
+}
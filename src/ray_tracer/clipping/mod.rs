@@ -0,0 +1,70 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use std::f64;
+
+use super::color::Radiance;
+use super::ray::Ray;
+
+// Everything on the side of the plane its normal points to is cut away
+#[derive(Clone, Copy)]
+pub struct ClippingPlane {
+    pub point: Vector3<f64>,
+    pub normal: Vector3<f64>,
+    // Flat color for solids cut open by the plane - None leaves them hollow. Solids must be
+    // closed with outward facing (counter-clockwise) winding, since an inward facing
+    // surface is taken as the inside of one
+    pub cap_color: Option<Radiance>,
+}
+
+impl ClippingPlane {
+    pub fn new(point: Vector3<f64>, normal: Vector3<f64>, cap_color: Option<Radiance>) -> ClippingPlane {
+        ClippingPlane {
+            point,
+            normal: normal.normalize(),
+            cap_color,
+        }
+    }
+
+    // Positive on the clipped side
+    pub fn signed_distance(&self, point: Vector3<f64>) -> f64 {
+        (point - self.point).dot(self.normal)
+    }
+}
+
+// Distances along the ray where it is inside every plane's kept half-space,
+// and the plane it enters through if that isn't at the ray origin.
+// The kept region is convex, so this is a single span - None if it's empty.
+pub fn clip_ray(planes: &[ClippingPlane], ray: &Ray) -> Option<(f64, f64, Option<ClippingPlane>)> {
+    let mut start: f64 = 0f64;
+    let mut end: f64 = f64::MAX;
+    let mut entry: Option<ClippingPlane> = None;
+
+    for plane in planes {
+        let distance: f64 = plane.signed_distance(ray.origin);
+        let approach: f64 = ray.direction.dot(plane.normal);
+
+        if approach.abs() < f64::EPSILON {
+            // Parallel - either always kept or always clipped
+            if distance > 0f64 {
+                return None;
+            }
+            continue;
+        }
+
+        let t: f64 = -distance / approach;
+        if approach > 0f64 {
+            end = end.min(t);
+        } else if t > start {
+            start = t;
+            entry = Some(*plane);
+        }
+    }
+
+    if start < end {
+        Some((start, end, entry))
+    } else {
+        None
+    }
+}
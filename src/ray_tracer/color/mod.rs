@@ -1,98 +1,134 @@
+#[cfg(not(target_arch = "wasm32"))]
 extern crate image;
 
+#[cfg(not(target_arch = "wasm32"))]
 use self::image::{Pixel, Rgba};
 
 use std::ops;
 
+// Linear light quantity used throughout shading - unbounded, no gamma
 #[derive(Copy, Clone, Debug, PartialEq)]
-pub struct Color {
+pub struct Radiance {
     pub r: f64,
     pub g: f64,
     pub b: f64,
 }
 
-impl Color {
-    pub fn new(r: f64, g: f64, b: f64) -> Color {
-        Color { r, g, b }
+// 8-bit color as written to (or read from) an image or configuration file
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DisplayColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Radiance {
+    pub fn new(r: f64, g: f64, b: f64) -> Radiance {
+        Radiance { r, g, b }
     }
 
-    pub fn from_rgb(r: u8, g: u8, b: u8) -> Color {
-        Color::new(
-            f64::from(r) / 256f64,
-            f64::from(g) / 256f64,
-            f64::from(b) / 256f64,
+    pub fn black() -> Radiance {
+        Radiance::new(0f64, 0f64, 0f64)
+    }
+
+    // 0-255 channels map onto 0.0-1.0
+    pub fn from_display(color: DisplayColor) -> Radiance {
+        Radiance::new(
+            f64::from(color.r) / 255f64,
+            f64::from(color.g) / 255f64,
+            f64::from(color.b) / 255f64,
         )
     }
 
-    pub fn to_rgba(&self) -> Rgba<u8> {
-        Rgba::from_channels(
-            (self.r * 255f64) as u8,
-            (self.g * 255f64) as u8,
-            (self.b * 255f64) as u8,
-            255,
+    // Quantize to 8 bits - values outside 0.0-1.0 are clamped, so tone map first
+    pub fn to_display(self) -> DisplayColor {
+        DisplayColor::new(
+            Radiance::quantize(self.r),
+            Radiance::quantize(self.g),
+            Radiance::quantize(self.b),
         )
     }
 
-    pub fn to_luminance(&self) -> f64 {
+    fn quantize(channel: f64) -> u8 {
+        (channel.clamp(0f64, 1f64) * 255f64).round() as u8
+    }
+
+    pub fn to_luminance(self) -> f64 {
         // Magic numbers from  https://en.wikipedia.org/wiki/Relative_luminance
         self.r * 0.2126 + self.g * 0.7152 + self.b * 0.0722
     }
 }
 
+impl DisplayColor {
+    pub fn new(r: u8, g: u8, b: u8) -> DisplayColor {
+        DisplayColor { r, g, b }
+    }
+
+    // Configuration files specify colors as [r, g, b]
+    pub fn from_slice(channels: &[u8]) -> DisplayColor {
+        DisplayColor::new(channels[0], channels[1], channels[2])
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn to_rgba(self) -> Rgba<u8> {
+        Rgba::from_channels(self.r, self.g, self.b, 255)
+    }
+}
+
 // Operator overloads
 
-impl ops::Add<f64> for Color {
-    type Output = Color;
+impl ops::Add<f64> for Radiance {
+    type Output = Radiance;
 
-    fn add(self, factor: f64) -> Color {
-        Color::new(self.r + factor, self.g + factor, self.b + factor)
+    fn add(self, factor: f64) -> Radiance {
+        Radiance::new(self.r + factor, self.g + factor, self.b + factor)
     }
 }
 
-impl ops::Add<Color> for Color {
-    type Output = Color;
+impl ops::Add<Radiance> for Radiance {
+    type Output = Radiance;
 
-    fn add(self, other: Color) -> Color {
-        Color::new(self.r + other.r, self.g + other.g, self.b + other.b)
+    fn add(self, other: Radiance) -> Radiance {
+        Radiance::new(self.r + other.r, self.g + other.g, self.b + other.b)
     }
 }
 
-impl ops::AddAssign<Color> for Color {
-    fn add_assign(&mut self, other: Color) {
+impl ops::AddAssign<Radiance> for Radiance {
+    fn add_assign(&mut self, other: Radiance) {
         self.r += other.r;
         self.g += other.g;
         self.b += other.b;
     }
 }
 
-impl ops::Mul<f64> for Color {
-    type Output = Color;
+impl ops::Mul<f64> for Radiance {
+    type Output = Radiance;
 
-    fn mul(self, factor: f64) -> Color {
-        Color::new(self.r * factor, self.g * factor, self.b * factor)
+    fn mul(self, factor: f64) -> Radiance {
+        Radiance::new(self.r * factor, self.g * factor, self.b * factor)
     }
 }
 
-impl ops::Mul<Color> for Color {
-    type Output = Color;
+impl ops::Mul<Radiance> for Radiance {
+    type Output = Radiance;
 
-    fn mul(self, other: Color) -> Color {
-        Color::new(self.r * other.r, self.g * other.g, self.b * other.b)
+    fn mul(self, other: Radiance) -> Radiance {
+        Radiance::new(self.r * other.r, self.g * other.g, self.b * other.b)
     }
 }
 
-impl ops::Div<f64> for Color {
-    type Output = Color;
+impl ops::Div<f64> for Radiance {
+    type Output = Radiance;
 
-    fn div(self, factor: f64) -> Color {
-        Color::new(self.r / factor, self.g / factor, self.b / factor)
+    fn div(self, factor: f64) -> Radiance {
+        Radiance::new(self.r / factor, self.g / factor, self.b / factor)
     }
 }
 
-impl ops::Div<Color> for Color {
-    type Output = Color;
+impl ops::Div<Radiance> for Radiance {
+    type Output = Radiance;
 
-    fn div(self, other: Color) -> Color {
-        Color::new(self.r / other.r, self.g / other.g, self.b / other.b)
+    fn div(self, other: Radiance) -> Radiance {
+        Radiance::new(self.r / other.r, self.g / other.g, self.b / other.b)
     }
 }
@@ -0,0 +1,278 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+
+use super::color::Radiance;
+use super::scene::configuration::Configuration;
+
+// Pixels between cells, and around text
+const GAP: usize = 4;
+// Glyphs are 3x5, drawn at this many pixels per dot
+const TEXT_SCALE: usize = 2;
+const CHARACTER_WIDTH: usize = 4 * TEXT_SCALE;
+const LINE_HEIGHT: usize = 5 * TEXT_SCALE + GAP;
+
+// A setting varied across the sheet, by key path as for --set
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sweep {
+    pub path: String,
+    // JSON, as written in the overrides
+    pub values: Vec<String>,
+}
+
+impl Sweep {
+    // path=start:end:count for evenly spaced numbers, or path=a,b,c for a list of values
+    pub fn parse(argument: &str) -> Result<Sweep, String> {
+        let equals: usize = argument
+            .find('=')
+            .ok_or_else(|| format!("Expected <key path>=<values>: {}", argument))?;
+        let (path, values): (&str, &str) = (&argument[..equals], &argument[equals + 1..]);
+
+        let values: Vec<String> = match Sweep::range(values) {
+            Some(range) => range,
+            None => split_top_level(values),
+        };
+        if path.is_empty() || values.iter().any(|value| value.is_empty()) {
+            return Err(format!("Invalid sweep: {}", argument));
+        }
+
+        Ok(Sweep {
+            path: String::from(path),
+            values,
+        })
+    }
+
+    fn range(values: &str) -> Option<Vec<String>> {
+        let parts: Vec<&str> = values.split(':').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        let start: f64 = parts[0].parse().ok()?;
+        let end: f64 = parts[1].parse().ok()?;
+        let count: usize = parts[2].parse().ok().filter(|&count| count > 0)?;
+
+        Some(
+            (0..count)
+                .map(|i| {
+                    let t: f64 = if count > 1 { i as f64 / (count - 1) as f64 } else { 0f64 };
+                    // Rounded so labels stay short
+                    format!("{}", ((start + (end - start) * t) * 1e4).round() / 1e4)
+                })
+                .collect(),
+        )
+    }
+}
+
+// Split on commas outside brackets and braces, so lists of arrays work
+fn split_top_level(values: &str) -> Vec<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut current: String = String::new();
+    let mut depth: i32 = 0;
+
+    for c in values.chars() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => (),
+        }
+        current.push(c);
+    }
+    parts.push(current.trim().to_string());
+
+    parts
+}
+
+// Every combination of one or two sweeps rendered small and laid out in a grid - the first
+// sweep across the columns, the second down the rows - with the values labeled, for picking
+// settings side by side. The cells are exposed together so they compare with each other.
+// Column-major, as the renderer's color buffers
+pub fn render(
+    configuration: &Configuration,
+    sweeps: &[Sweep],
+    cell_size: usize,
+) -> io::Result<(Vec<Vec<Radiance>>, usize, usize)> {
+    if sweeps.is_empty() || sweeps.len() > 2 {
+        return Err(io::Error::other("A contact sheet varies one or two settings"));
+    }
+    let columns: &Sweep = &sweeps[0];
+    let rows: Option<&Sweep> = sweeps.get(1);
+    let row_values: Vec<Option<&String>> = match rows {
+        Some(rows) => rows.values.iter().map(Some).collect(),
+        None => vec![None],
+    };
+
+    // Each cell keeps the configured aspect ratio, cell_size on its longer side
+    let mut cell: Configuration = configuration.clone();
+    cell.scale_resolution(cell_size as f64 / configuration.width.max(configuration.height) as f64);
+    let (cell_width, cell_height): (usize, usize) = (cell.width, cell.height);
+
+    let grid_width: usize = columns.values.len() * (cell_width + GAP) - GAP;
+    let grid_height: usize = row_values.len() * (cell_height + GAP) - GAP;
+    let mut grid: Vec<Vec<Radiance>> = vec![vec![Radiance::black(); grid_height]; grid_width];
+
+    for (row, row_value) in row_values.iter().enumerate() {
+        for (column, column_value) in columns.values.iter().enumerate() {
+            let mut assignments: Vec<String> = vec![format!("{}={}", columns.path, column_value)];
+            if let (Some(rows), Some(row_value)) = (rows, row_value) {
+                assignments.push(format!("{}={}", rows.path, row_value));
+            }
+            info!("Rendering {}", assignments.join(" "));
+
+            let mut cell_configuration: Configuration = cell.with_overrides(&assignments)?;
+            // The sweep may have changed the resolution
+            cell_configuration.width = cell_width;
+            cell_configuration.height = cell_height;
+
+            let color_buffer: Vec<Vec<Radiance>> =
+                super::trace(&cell_configuration, &Arc::new(AtomicUsize::new(0)))?;
+            let (left, top): (usize, usize) =
+                (column * (cell_width + GAP), row * (cell_height + GAP));
+            for (x, pixels) in color_buffer.iter().enumerate() {
+                grid[left + x][top..top + cell_height].copy_from_slice(pixels);
+            }
+        }
+    }
+
+    let mut exposure: Configuration = configuration.clone();
+    exposure.width = grid_width;
+    exposure.height = grid_height;
+    super::tone_map(&exposure, &mut grid);
+
+    Ok(layout(&grid, (grid_width, grid_height), (cell_width, cell_height), columns, rows))
+}
+
+// The grid with a title, the column values above it and the row values to its left
+fn layout(
+    grid: &[Vec<Radiance>],
+    (grid_width, grid_height): (usize, usize),
+    (cell_width, cell_height): (usize, usize),
+    columns: &Sweep,
+    rows: Option<&Sweep>,
+) -> (Vec<Vec<Radiance>>, usize, usize) {
+    let label_width: usize = rows.map_or(0, |rows| {
+        rows.values.iter().map(|value| value.len()).max().unwrap_or(0) * CHARACTER_WIDTH + GAP
+    });
+    let (left, top): (usize, usize) = (GAP + label_width, GAP + 2 * LINE_HEIGHT);
+    let width: usize = left + grid_width + GAP;
+    let height: usize = top + grid_height + GAP;
+
+    let background: Radiance = Radiance::new(0.12, 0.12, 0.12);
+    let mut sheet: Vec<Vec<Radiance>> = vec![vec![background; height]; width];
+
+    for (x, pixels) in grid.iter().enumerate() {
+        sheet[left + x][top..top + grid_height].copy_from_slice(pixels);
+    }
+
+    let title: String = match rows {
+        Some(rows) => format!("{} across, {} down", columns.path, rows.path),
+        None => columns.path.clone(),
+    };
+    let title_characters: usize = (width - 2 * GAP) / CHARACTER_WIDTH;
+    draw_text(&mut sheet, &title, (GAP, GAP), title_characters);
+
+    let cell_characters: usize = cell_width / CHARACTER_WIDTH;
+    for (column, value) in columns.values.iter().enumerate() {
+        let x: usize = left + column * (cell_width + GAP);
+        draw_text(&mut sheet, value, (x, GAP + LINE_HEIGHT), cell_characters);
+    }
+    if let Some(rows) = rows {
+        for (row, value) in rows.values.iter().enumerate() {
+            let y: usize = top + row * (cell_height + GAP);
+            draw_text(&mut sheet, value, (GAP, y), value.len());
+        }
+    }
+
+    (sheet, width, height)
+}
+
+// Up to max_characters of text, its top left corner at position - characters without a
+// glyph are drawn as ?
+fn draw_text(
+    sheet: &mut [Vec<Radiance>],
+    text: &str,
+    position: (usize, usize),
+    max_characters: usize,
+) {
+    let color: Radiance = Radiance::new(0.9, 0.9, 0.9);
+
+    for (i, c) in text.chars().take(max_characters).enumerate() {
+        let rows: [u8; 5] = glyph(c.to_ascii_lowercase());
+        for (dot_y, bits) in rows.iter().enumerate() {
+            for dot_x in 0..3 {
+                if bits & (0b100 >> dot_x) == 0 {
+                    continue;
+                }
+
+                for y in 0..TEXT_SCALE {
+                    for x in 0..TEXT_SCALE {
+                        let px: usize = position.0 + i * CHARACTER_WIDTH + dot_x * TEXT_SCALE + x;
+                        let py: usize = position.1 + dot_y * TEXT_SCALE + y;
+                        if px < sheet.len() && py < sheet[px].len() {
+                            sheet[px][py] = color;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 3x5 dots, a row of three bits per line, most significant on the left
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'a' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'b' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'c' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'd' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'e' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'f' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'g' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'h' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'i' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'j' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'k' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'l' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'm' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'n' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'o' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'p' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'r' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        's' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        't' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'u' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'v' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'w' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'x' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '"' => [0b101, 0b101, 0b000, 0b000, 0b000],
+        ' ' => [0b000; 5],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
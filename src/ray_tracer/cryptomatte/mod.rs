@@ -0,0 +1,178 @@
+extern crate serde_json;
+
+use std::collections::BTreeMap;
+
+use super::exr::ExrImage;
+
+// Cryptomatte ID mattes - per pixel, the fraction of samples that saw each object. Objects
+// are named by "name" or their file name, and objects with identical materials share a
+// material ID. Written as layers of .exr output, or else to <output>.cryptomatte.exr
+// https://github.com/Psyop/Cryptomatte/blob/master/specification/cryptomatte_specification.pdf
+
+// IDs and coverages stored per pixel, two per RGBA channel group
+const RANKS: usize = 6;
+
+pub struct Cryptomatte {
+    // Object index and coverage pairs, per pixel
+    pub coverage: Vec<Vec<Vec<(usize, f32)>>>,
+}
+
+// MurmurHash3 x86 32-bit
+pub fn murmur3_32(key: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mix = |k: u32| -> u32 { k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2) };
+
+    let mut hash: u32 = seed;
+    let mut chunks = key.chunks_exact(4);
+    for chunk in &mut chunks {
+        let k: u32 = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        hash ^= mix(k);
+        hash = hash.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+
+    let tail: &[u8] = chunks.remainder();
+    if !tail.is_empty() {
+        let k: u32 = tail
+            .iter()
+            .rev()
+            .fold(0u32, |k, &byte| (k << 8) | u32::from(byte));
+        hash ^= mix(k);
+    }
+
+    hash ^= key.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+// Hash of the name with the exponent nudged away from denormals, infinity and NaN,
+// so it survives storage as a float
+pub fn id_hash(name: &str) -> u32 {
+    let hash: u32 = murmur3_32(name.as_bytes(), 0);
+    let exponent: u32 = (hash >> 23) & 0xff;
+
+    if exponent == 0 || exponent == 0xff {
+        hash ^ (1 << 23)
+    } else {
+        hash
+    }
+}
+
+impl Cryptomatte {
+    pub fn new(width: usize, height: usize) -> Cryptomatte {
+        Cryptomatte {
+            coverage: vec![vec![Vec::new(); height]; width],
+        }
+    }
+
+    // Each sample's primary hit object, if any - misses leave the background uncovered
+    pub fn record(&mut self, x: usize, y: usize, hits: &[Option<usize>]) {
+        let weight: f32 = 1f32 / hits.len() as f32;
+        let pixel: &mut Vec<(usize, f32)> = &mut self.coverage[x][y];
+
+        for object_id in hits.iter().filter_map(|hit| *hit) {
+            match pixel.iter_mut().find(|&&mut (id, _)| id == object_id) {
+                Some(entry) => entry.1 += weight,
+                None => pixel.push((object_id, weight)),
+            }
+        }
+    }
+
+    // One more sample of a pixel that already has samples, e.g. while refining - the
+    // coverage recorded so far is scaled to make room for it
+    pub fn add(&mut self, x: usize, y: usize, hit: Option<usize>, samples: usize) {
+        let weight: f32 = 1f32 / (samples + 1) as f32;
+        let pixel: &mut Vec<(usize, f32)> = &mut self.coverage[x][y];
+
+        for entry in pixel.iter_mut() {
+            entry.1 *= 1f32 - weight;
+        }
+
+        if let Some(object_id) = hit {
+            match pixel.iter_mut().find(|&&mut (id, _)| id == object_id) {
+                Some(entry) => entry.1 += weight,
+                None => pixel.push((object_id, weight)),
+            }
+        }
+    }
+
+    // Copy the pixels another thread drew
+    pub fn merge<I>(&mut self, other: &Cryptomatte, pixels: I)
+    where
+        I: Iterator<Item = (usize, usize)>,
+    {
+        for (x, y) in pixels {
+            self.coverage[x][y] = other.coverage[x][y].clone();
+        }
+    }
+
+    // Add a Cryptomatte layer, with object indices mapped through groups to names
+    // e.g. objects to their materials
+    pub fn add_layer(&self, image: &mut ExrImage, layer: &str, names: &[String], groups: &[usize]) {
+        // Coverage per group, most covered first
+        let ranked: Vec<Vec<Vec<(u32, f32)>>> = self
+            .coverage
+            .iter()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|pixel| {
+                        let mut grouped: Vec<(usize, f32)> = Vec::new();
+                        for &(object_id, coverage) in pixel {
+                            let group: usize = groups[object_id];
+                            match grouped.iter_mut().find(|&&mut (id, _)| id == group) {
+                                Some(entry) => entry.1 += coverage,
+                                None => grouped.push((group, coverage)),
+                            }
+                        }
+
+                        grouped.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+                        grouped
+                            .into_iter()
+                            .take(RANKS)
+                            .map(|(group, coverage)| (id_hash(&names[group]), coverage))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // Rank pairs: R, G hold the id and coverage of one rank, B, A the next
+        for rank in (0..RANKS).step_by(2) {
+            let channels: [(&str, usize, bool); 4] = [
+                ("R", rank, true),
+                ("G", rank, false),
+                ("B", rank + 1, true),
+                ("A", rank + 1, false),
+            ];
+
+            for &(channel, channel_rank, is_id) in &channels {
+                let name: String = format!("{}{:02}.{}", layer, rank / 2, channel);
+                image.add_channel(&name, |x, y| match ranked[x][y].get(channel_rank) {
+                    Some(&(id, _)) if is_id => f32::from_bits(id),
+                    Some(&(_, coverage)) => coverage,
+                    None => 0f32,
+                });
+            }
+        }
+
+        // Metadata keyed by the first seven hex digits of the layer name's hash
+        let key: String = format!("{:08x}", murmur3_32(layer.as_bytes(), 0))[..7].to_string();
+        let manifest: BTreeMap<&str, String> = names
+            .iter()
+            .map(|name| (name.as_str(), format!("{:08x}", id_hash(name))))
+            .collect();
+
+        image.add_string_attribute(&format!("cryptomatte/{}/name", key), layer);
+        image.add_string_attribute(&format!("cryptomatte/{}/hash", key), "MurmurHash3_32");
+        image.add_string_attribute(&format!("cryptomatte/{}/conversion", key), "uint32_to_float32");
+        image.add_string_attribute(
+            &format!("cryptomatte/{}/manifest", key),
+            &serde_json::to_string(&manifest).unwrap(),
+        );
+    }
+}
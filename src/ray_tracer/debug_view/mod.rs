@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+use super::color::Radiance;
+use super::diagnostics;
+
+// Render something other than shading, e.g. --debug-view normals
+// Scenes write raw per-pixel values which finish() turns into a displayable image
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DebugView {
+    // Objects tested against the primary ray. With Traversals, hot spots show where the
+    // tree could be split better or geometry is overly dense; .exr output keeps the counts
+    Intersections,
+    // K-D tree nodes visited by the primary ray
+    Traversals,
+    // Geometric normal as modeled, x y z mapped to red green blue - not flipped toward the
+    // camera, so inconsistent winding shows
+    Normals,
+    // Texture coordinates as red and green, repeating outside 0 - 1, or barycentric
+    // coordinates for meshes without vt statements
+    Uv,
+    // Distance from the camera, near is white
+    Depth,
+    // A distinct color per configured object
+    ObjectId,
+}
+
+const NAMES: &str = "intersections, traversals, normals, uv, depth or object-id";
+
+impl FromStr for DebugView {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<DebugView, String> {
+        match name {
+            "intersections" => Ok(DebugView::Intersections),
+            "traversals" => Ok(DebugView::Traversals),
+            "normals" => Ok(DebugView::Normals),
+            "uv" => Ok(DebugView::Uv),
+            "depth" => Ok(DebugView::Depth),
+            "object-id" => Ok(DebugView::ObjectId),
+            _ => Err(format!("Unknown debug view: {} (expected {})", name, NAMES)),
+        }
+    }
+}
+
+// Evenly spread hues, so neighbouring ids look different
+pub fn id_color(id: usize) -> Radiance {
+    let hue: f64 = (id as f64 * 0.618_033_988_75).fract() * 6f64;
+    let sector: usize = hue.floor() as usize;
+    let f: f64 = hue - sector as f64;
+
+    let (r, g, b): (f64, f64, f64) = match sector {
+        0 => (1f64, f, 0f64),
+        1 => (1f64 - f, 1f64, 0f64),
+        2 => (0f64, 1f64, f),
+        3 => (0f64, 1f64 - f, 1f64),
+        4 => (f, 0f64, 1f64),
+        _ => (1f64, 0f64, 1f64 - f),
+    };
+
+    // Slightly desaturated so edges between objects stay readable
+    Radiance::new(0.2 + 0.8 * r, 0.2 + 0.8 * g, 0.2 + 0.8 * b)
+}
+
+impl DebugView {
+    // Views that only look at the primary hit skip lighting, reflections and multisampling
+    pub fn finish(self, color_buffer: &mut [Vec<Radiance>]) {
+        match self {
+            DebugView::Intersections | DebugView::Traversals => {
+                let maximum: f64 = maximum(color_buffer);
+
+                map(color_buffer, |value| {
+                    diagnostics::false_color(if maximum > 0f64 { value.r / maximum } else { 0f64 })
+                });
+            }
+            DebugView::Normals | DebugView::Uv => {}
+            DebugView::Depth => {
+                // Misses are negative
+                let maximum: f64 = maximum(color_buffer);
+                let minimum: f64 = color_buffer
+                    .iter()
+                    .flat_map(|column| column.iter())
+                    .filter(|value| value.r >= 0f64)
+                    .fold(maximum, |minimum, value| minimum.min(value.r));
+                let range: f64 = (maximum - minimum).max(f64::EPSILON);
+
+                map(color_buffer, |value| {
+                    if value.r < 0f64 {
+                        return Radiance::black();
+                    }
+
+                    let brightness: f64 = 1f64 - 0.9 * (value.r - minimum) / range;
+                    Radiance::new(brightness, brightness, brightness)
+                });
+            }
+            DebugView::ObjectId => {
+                // Misses are 0, objects are their id + 1
+                map(color_buffer, |value| {
+                    if value.r < 1f64 {
+                        Radiance::black()
+                    } else {
+                        id_color(value.r as usize - 1)
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn maximum(color_buffer: &[Vec<Radiance>]) -> f64 {
+    color_buffer
+        .iter()
+        .flat_map(|column| column.iter())
+        .fold(0f64, |maximum, value| maximum.max(value.r))
+}
+
+fn map<F: Fn(Radiance) -> Radiance>(color_buffer: &mut [Vec<Radiance>], f: F) {
+    for column in color_buffer.iter_mut() {
+        for value in column.iter_mut() {
+            *value = f(*value);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use super::color::Radiance;
+use super::exr::{DeepExrImage, DeepSample};
+
+// Deep output - each pixel's samples merged per object into depth-sorted, partially
+// covering samples, so the render can be composited with other deep elements. Each holds
+// premultiplied linear color, coverage as alpha and the nearest and farthest hit as Z and
+// ZBack. Misses are left out, and fog in front of a surface is part of its sample
+pub struct DeepBuffer {
+    // Each sample with the object it belongs to, nearest first
+    pub pixels: Vec<Vec<Vec<(usize, DeepSample)>>>,
+}
+
+// Add a sample's share of color and coverage to its object's deep sample
+fn merge(pixel: &mut Vec<(usize, DeepSample)>, hit: (usize, f64), color: Radiance, weight: f64) {
+    let (object_id, distance) = hit;
+    let distance: f32 = distance as f32;
+
+    match pixel.iter_mut().find(|&&mut (id, _)| id == object_id) {
+        Some(&mut (_, ref mut sample)) => {
+            sample.depth = sample.depth.min(distance);
+            sample.depth_back = sample.depth_back.max(distance);
+            sample.color += color * weight;
+            sample.alpha += weight as f32;
+        }
+        None => pixel.push((
+            object_id,
+            DeepSample {
+                depth: distance,
+                depth_back: distance,
+                color: color * weight,
+                alpha: weight as f32,
+            },
+        )),
+    }
+
+    pixel.sort_by(|a, b| a.1.depth.partial_cmp(&b.1.depth).unwrap());
+}
+
+impl DeepBuffer {
+    pub fn new(width: usize, height: usize) -> DeepBuffer {
+        DeepBuffer {
+            pixels: vec![vec![Vec::new(); height]; width],
+        }
+    }
+
+    // Each sample's color with the object and distance of its primary hit
+    // Misses leave that share of the pixel transparent
+    pub fn record(&mut self, x: usize, y: usize, hits: &[(Option<(usize, f64)>, Radiance)]) {
+        let weight: f64 = 1f64 / hits.len() as f64;
+        let mut pixel: Vec<(usize, DeepSample)> = Vec::new();
+
+        for &(hit, color) in hits {
+            if let Some(hit) = hit {
+                merge(&mut pixel, hit, color, weight);
+            }
+        }
+
+        self.pixels[x][y] = pixel;
+    }
+
+    // One more sample of a pixel that already has samples, e.g. while refining - the
+    // color and coverage recorded so far are scaled to make room for it
+    pub fn add(
+        &mut self,
+        x: usize,
+        y: usize,
+        hit: Option<(usize, f64)>,
+        color: Radiance,
+        samples: usize,
+    ) {
+        let weight: f64 = 1f64 / (samples + 1) as f64;
+        let pixel: &mut Vec<(usize, DeepSample)> = &mut self.pixels[x][y];
+
+        for &mut (_, ref mut sample) in pixel.iter_mut() {
+            sample.color = sample.color * (1f64 - weight);
+            sample.alpha *= 1f32 - weight as f32;
+        }
+
+        if let Some(hit) = hit {
+            merge(pixel, hit, color, weight);
+        }
+    }
+
+    // Copy the pixels another thread drew
+    pub fn merge<I>(&mut self, other: &DeepBuffer, pixels: I)
+    where
+        I: Iterator<Item = (usize, usize)>,
+    {
+        for (x, y) in pixels {
+            self.pixels[x][y] = other.pixels[x][y].clone();
+        }
+    }
+
+    pub fn to_exr(&self, width: usize, height: usize) -> DeepExrImage {
+        DeepExrImage::new(width, height, |x, y| {
+            self.pixels[x][y].iter().map(|&(_, sample)| sample).collect()
+        })
+    }
+}
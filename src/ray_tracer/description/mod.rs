@@ -0,0 +1,206 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use std::fmt;
+use std::io;
+use std::mem;
+
+use ray_tracer::camera::Camera;
+use ray_tracer::color::Radiance;
+use ray_tracer::kd_tree::KdTree;
+use ray_tracer::kd_tree::bounding_box::BoundingBox;
+use ray_tracer::light::Light;
+use ray_tracer::object::Shape;
+use ray_tracer::scene::configuration::Configuration;
+
+const MEBIBYTE: f64 = 1024f64 * 1024f64;
+
+pub struct ObjectSummary {
+    pub filename: String,
+    pub triangles: usize,
+    pub spheres: usize,
+}
+
+// Summary of a configuration for sanity-checking a scene without rendering it - object and
+// triangle counts, bounds, lights, camera and estimated memory
+pub struct SceneDescription {
+    pub camera: Camera,
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub threads: usize,
+    pub viewport_width: f64,
+    pub viewport_distance: f64,
+    pub objects: Vec<ObjectSummary>,
+    pub bounding_box: Option<BoundingBox>,
+    pub lights: Vec<Light>,
+    pub geometry_bytes: usize,
+    pub color_buffer_bytes: usize,
+    pub memory_limit_bytes: Option<usize>,
+}
+
+impl SceneDescription {
+    pub fn new(configuration: &Configuration) -> io::Result<SceneDescription> {
+        let mut objects: Vec<ObjectSummary> = Vec::new();
+        let mut shapes: Vec<Shape> = Vec::new();
+
+        for object_definition in &configuration.objects {
+            let mut object_shapes: Vec<Shape> =
+                object_definition.read_shapes(configuration.units)?;
+
+            let triangles: usize = object_shapes
+                .iter()
+                .filter(|shape| matches!(**shape, Shape::Triangle(_)))
+                .count();
+
+            objects.push(ObjectSummary {
+                filename: String::from(object_definition.name()),
+                triangles,
+                spheres: object_shapes.len() - triangles,
+            });
+
+            shapes.append(&mut object_shapes);
+        }
+
+        let bounding_box: Option<BoundingBox> = if shapes.is_empty() {
+            None
+        } else {
+            Some(KdTree::scene_bounding_box(&shapes))
+        };
+
+        // One buffer per thread plus the combined buffer
+        let color_buffer_bytes: usize = configuration.width * configuration.height *
+            mem::size_of::<Radiance>() * (configuration.thread_count() + 1);
+
+        Ok(SceneDescription {
+            camera: configuration.camera(),
+            width: configuration.width,
+            height: configuration.height,
+            samples: configuration.samples,
+            threads: configuration.thread_count(),
+            viewport_width: configuration.viewport_width(),
+            viewport_distance: configuration.viewport_distance,
+            objects,
+            bounding_box,
+            lights: configuration
+                .lights
+                .iter()
+                .map(|light_definition| light_definition.as_light(configuration.units))
+                .collect::<io::Result<Vec<Light>>>()?,
+            geometry_bytes: shapes.len() * mem::size_of::<Shape>(),
+            color_buffer_bytes,
+            memory_limit_bytes: configuration
+                .memory_limit_mb
+                .map(|memory_limit_mb| memory_limit_mb * 1024 * 1024),
+        })
+    }
+
+    pub fn triangles(&self) -> usize {
+        self.objects.iter().map(|object| object.triangles).sum()
+    }
+
+    pub fn spheres(&self) -> usize {
+        self.objects.iter().map(|object| object.spheres).sum()
+    }
+}
+
+fn format_vector(vector: Vector3<f64>) -> String {
+    format!("({}, {}, {})", vector.x, vector.y, vector.z)
+}
+
+impl fmt::Display for SceneDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Camera")?;
+        writeln!(f, "  position:  {}", format_vector(self.camera.origin))?;
+        writeln!(f, "  target:    {}", format_vector(self.camera.target))?;
+        writeln!(f, "  up:        {}", format_vector(self.camera.up))?;
+        writeln!(
+            f,
+            "  viewport:  {} wide at distance {}",
+            self.viewport_width,
+            self.viewport_distance
+        )?;
+
+        writeln!(f, "Image")?;
+        writeln!(
+            f,
+            "  {} x {}, {} samples per pixel, {} threads",
+            self.width,
+            self.height,
+            self.samples,
+            self.threads
+        )?;
+
+        writeln!(f, "Objects ({})", self.objects.len())?;
+        for object in &self.objects {
+            writeln!(
+                f,
+                "  {}: {} triangles, {} spheres",
+                object.filename,
+                object.triangles,
+                object.spheres
+            )?;
+        }
+        writeln!(
+            f,
+            "  total: {} triangles, {} spheres",
+            self.triangles(),
+            self.spheres()
+        )?;
+
+        match self.bounding_box {
+            Some(ref bounding_box) => writeln!(
+                f,
+                "  bounds: {} to {}",
+                format_vector(bounding_box.min),
+                format_vector(bounding_box.max)
+            )?,
+            None => writeln!(f, "  bounds: empty scene")?,
+        }
+
+        writeln!(f, "Lights ({})", self.lights.len())?;
+        for light in &self.lights {
+            write!(
+                f,
+                "  {} intensity {} color ({:.3}, {:.3}, {:.3})",
+                format_vector(light.origin),
+                light.intensity,
+                light.color.r,
+                light.color.g,
+                light.color.b
+            )?;
+            match light.spot {
+                Some(ref spot) => writeln!(
+                    f,
+                    " spot {} {:.1} degrees{}",
+                    format_vector(spot.direction()),
+                    spot.cone_angle().to_degrees(),
+                    if spot.gobo.is_some() { " with gobo" } else { "" }
+                )?,
+                None => writeln!(f)?,
+            }
+        }
+
+        writeln!(f, "Estimated memory")?;
+        writeln!(
+            f,
+            "  geometry:      {:.1} MiB (before k-d tree duplication)",
+            self.geometry_bytes as f64 / MEBIBYTE
+        )?;
+        write!(
+            f,
+            "  color buffers: {:.1} MiB",
+            self.color_buffer_bytes as f64 / MEBIBYTE
+        )?;
+
+        match self.memory_limit_bytes {
+            Some(memory_limit_bytes) => write!(
+                f,
+                "\n  geometry limit: {:.1} MiB, k-d tree leaves paged to disk",
+                memory_limit_bytes as f64 / MEBIBYTE
+            ),
+            None => Ok(()),
+        }
+    }
+}
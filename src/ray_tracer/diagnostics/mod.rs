@@ -0,0 +1,136 @@
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use super::color::Radiance;
+
+// Per-pixel render cost, written out as false color images with --diagnostics. Times are
+// wall clock, so they only compare fairly with no more threads than cores
+pub struct PixelStatistics {
+    pub samples: Vec<Vec<usize>>,
+    pub milliseconds: Vec<Vec<f64>>,
+}
+
+// Blue (cheap) through cyan, green and yellow to red (expensive)
+const RAMP: [(f64, f64, f64); 5] = [
+    (0f64, 0f64, 0.5),
+    (0f64, 0.8, 1f64),
+    (0.2, 0.9, 0.2),
+    (1f64, 0.9, 0f64),
+    (0.9, 0f64, 0f64),
+];
+
+// Map 0.0 - 1.0 onto the color ramp
+pub fn false_color(value: f64) -> Radiance {
+    let position: f64 = value.clamp(0f64, 1f64) * (RAMP.len() - 1) as f64;
+    let index: usize = (position.floor() as usize).min(RAMP.len() - 2);
+    let t: f64 = position - index as f64;
+
+    let (r1, g1, b1) = RAMP[index];
+    let (r2, g2, b2) = RAMP[index + 1];
+
+    Radiance::new(
+        r1 + (r2 - r1) * t,
+        g1 + (g2 - g1) * t,
+        b1 + (b2 - b1) * t,
+    )
+}
+
+// Value at the given fraction through the sorted values
+fn percentile(values: &[Vec<f64>], fraction: f64) -> f64 {
+    let mut sorted: Vec<f64> = values.iter().flat_map(|column| column.iter().cloned()).collect();
+    if sorted.is_empty() {
+        return 0f64;
+    }
+
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    sorted[((sorted.len() - 1) as f64 * fraction).round() as usize]
+}
+
+// False color image with values scaled so that maximum is red
+fn heatmap(values: &[Vec<f64>], maximum: f64) -> Vec<Vec<Radiance>> {
+    values
+        .iter()
+        .map(|column| {
+            column
+                .iter()
+                .map(|value| false_color(if maximum > 0f64 { value / maximum } else { 0f64 }))
+                .collect()
+        })
+        .collect()
+}
+
+// img/scene.png -> img/scene.<name>.png
+pub fn diagnostic_filename(out_file: &str, name: &str) -> String {
+    let path: &Path = Path::new(out_file);
+    let stem: &str = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("scene");
+
+    path.with_file_name(format!("{}.{}.png", stem, name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+impl PixelStatistics {
+    pub fn new(width: usize, height: usize) -> PixelStatistics {
+        PixelStatistics {
+            samples: vec![vec![0; height]; width],
+            milliseconds: vec![vec![0f64; height]; width],
+        }
+    }
+
+    pub fn record(&mut self, x: usize, y: usize, samples: usize, elapsed: Duration) {
+        self.samples[x][y] = samples;
+        self.milliseconds[x][y] = elapsed.as_secs_f64() * 1000f64;
+    }
+
+    // Count further samples and time spent on a pixel, e.g. refining it
+    pub fn add(&mut self, x: usize, y: usize, samples: usize, elapsed: Duration) {
+        self.samples[x][y] += samples;
+        self.milliseconds[x][y] += elapsed.as_secs_f64() * 1000f64;
+    }
+
+    // Copy the given pixels from another thread's statistics
+    pub fn merge<I: Iterator<Item = (usize, usize)>>(&mut self, other: &PixelStatistics, pixels: I) {
+        for (x, y) in pixels {
+            self.samples[x][y] = other.samples[x][y];
+            self.milliseconds[x][y] = other.milliseconds[x][y];
+        }
+    }
+
+    pub fn samples_heatmap(&self) -> Vec<Vec<Radiance>> {
+        let samples: Vec<Vec<f64>> = self.samples
+            .iter()
+            .map(|column| column.iter().map(|samples| *samples as f64).collect())
+            .collect();
+
+        heatmap(&samples, percentile(&samples, 1f64))
+    }
+
+    // A few very slow pixels would otherwise wash out the rest of the image
+    pub fn time_heatmap(&self) -> Vec<Vec<Radiance>> {
+        heatmap(&self.milliseconds, percentile(&self.milliseconds, 0.99))
+    }
+}
+
+impl fmt::Display for PixelStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pixels: usize = self.samples.iter().map(|column| column.len()).sum();
+        let total_samples: usize = self.samples.iter().flatten().sum();
+        let total_milliseconds: f64 = self.milliseconds.iter().flatten().sum();
+
+        writeln!(
+            f,
+            "Samples per pixel: {} min, {:.2} mean, {} max",
+            self.samples.iter().flatten().min().cloned().unwrap_or(0),
+            total_samples as f64 / pixels.max(1) as f64,
+            self.samples.iter().flatten().max().cloned().unwrap_or(0)
+        )?;
+        write!(
+            f,
+            "Time per pixel:    {:.3} ms mean, {:.3} ms 99th percentile, {:.3} ms max",
+            total_milliseconds / pixels.max(1) as f64,
+            percentile(&self.milliseconds, 0.99),
+            percentile(&self.milliseconds, 1f64)
+        )
+    }
+}
@@ -0,0 +1,185 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::Message;
+
+use ray_tracer::color::Radiance;
+use ray_tracer::scene::configuration::Configuration;
+use ray_tracer::tile::Tile;
+
+// A worker that doesn't answer for this long is dropped and its tile given to another, as if
+// it had disconnected - generous, since one tile of a slow scene can take minutes
+const WORKER_TIMEOUT_SECONDS: u64 = 600;
+
+struct Progress {
+    pending: Vec<Tile>,
+    remaining: usize,
+    color_buffer: Vec<Vec<Radiance>>,
+}
+
+pub struct Coordinator {
+    scene: String,
+    progress: Arc<(Mutex<Progress>, Condvar)>,
+}
+
+impl Coordinator {
+    pub fn new(config_file: &str, tile_size: usize) -> io::Result<Coordinator> {
+        let mut scene = String::new();
+        File::open(config_file)?.read_to_string(&mut scene)?;
+
+        let configuration: Configuration = Configuration::parse_configuration(config_file)?;
+
+        // Reversed so tiles are handed out top to bottom by pop()
+        let mut pending: Vec<Tile> =
+            Tile::split(configuration.width, configuration.height, tile_size);
+        pending.reverse();
+
+        Ok(Coordinator {
+            scene,
+            progress: Arc::new((
+                Mutex::new(Progress {
+                    remaining: pending.len(),
+                    pending,
+                    color_buffer: vec![
+                        vec![Radiance::black(); configuration.height];
+                        configuration.width
+                    ],
+                }),
+                Condvar::new(),
+            )),
+        })
+    }
+
+    // Next tile to render, waiting on tiles held by other workers in case they fail
+    // None once every tile has been returned
+    fn next_tile(progress: &(Mutex<Progress>, Condvar)) -> Option<Tile> {
+        let (ref lock, ref changed) = *progress;
+        let mut state = lock.lock().unwrap();
+
+        loop {
+            if let Some(tile) = state.pending.pop() {
+                return Some(tile);
+            }
+
+            if state.remaining == 0 {
+                return None;
+            }
+
+            state = changed.wait(state).unwrap();
+        }
+    }
+
+    fn render_tile(
+        stream: &TcpStream,
+        reader: &mut BufReader<&TcpStream>,
+        tile: Tile,
+    ) -> io::Result<Vec<(f64, f64, f64)>> {
+        Message::Tile(tile).send(&mut &*stream)?;
+
+        match Message::receive(reader)? {
+            Message::Pixels(ref returned, pixels)
+                if *returned == tile && pixels.len() == tile.pixel_count() =>
+            {
+                Ok(pixels)
+            }
+            _ => Err(io::Error::other("Unexpected response from worker")),
+        }
+    }
+
+    fn serve_worker(
+        scene: &str,
+        progress: &(Mutex<Progress>, Condvar),
+        stream: &TcpStream,
+    ) -> io::Result<()> {
+        let timeout: Option<Duration> = Some(Duration::from_secs(WORKER_TIMEOUT_SECONDS));
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+
+        Message::Scene(String::from(scene)).send(&mut &*stream)?;
+        let mut reader = BufReader::new(stream);
+
+        while let Some(tile) = Coordinator::next_tile(progress) {
+            let (ref lock, ref changed) = *progress;
+
+            match Coordinator::render_tile(stream, &mut reader, tile) {
+                Ok(pixels) => {
+                    let mut state = lock.lock().unwrap();
+                    for ((x, y), (r, g, b)) in tile.pixels().zip(pixels) {
+                        state.color_buffer[x][y] = Radiance::new(r, g, b);
+                    }
+                    state.remaining -= 1;
+                }
+                Err(error) => {
+                    // Give the tile to someone else
+                    lock.lock().unwrap().pending.push(tile);
+                    changed.notify_all();
+                    return Err(error);
+                }
+            }
+
+            changed.notify_all();
+        }
+
+        Message::Finished.send(&mut &*stream)
+    }
+
+    // Accept workers until every tile is rendered, returning the linear color buffer
+    pub fn run(self, address: &str) -> io::Result<Vec<Vec<Radiance>>> {
+        let listener: TcpListener = TcpListener::bind(address)?;
+        info!(
+            "Waiting for workers on {} ({} tiles)",
+            listener.local_addr()?,
+            self.progress.0.lock().unwrap().remaining
+        );
+
+        let scene: Arc<String> = Arc::new(self.scene);
+        let progress = Arc::clone(&self.progress);
+        let connections: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::new(Mutex::new(Vec::new()));
+        let accepted = Arc::clone(&connections);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream: TcpStream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+
+                let scene = Arc::clone(&scene);
+                let progress = Arc::clone(&progress);
+
+                accepted.lock().unwrap().push(thread::spawn(move || {
+                    let peer = stream
+                        .peer_addr()
+                        .map(|address| address.to_string())
+                        .unwrap_or_default();
+                    info!("Worker {} connected", peer);
+
+                    if let Err(error) = Coordinator::serve_worker(&scene, &progress, &stream) {
+                        warn!("Worker {} dropped: {}", peer, error);
+                    }
+                }));
+            }
+        });
+
+        let (ref lock, ref changed) = *self.progress;
+        {
+            let mut state = lock.lock().unwrap();
+            while state.remaining > 0 {
+                state = changed.wait(state).unwrap();
+            }
+        }
+
+        // Let connected workers receive Finished before exiting
+        let finished: Vec<JoinHandle<()>> = connections.lock().unwrap().drain(..).collect();
+        for connection in finished {
+            connection.join().ok();
+        }
+
+        let color_buffer: Vec<Vec<Radiance>> = lock.lock().unwrap().color_buffer.clone();
+        Ok(color_buffer)
+    }
+}
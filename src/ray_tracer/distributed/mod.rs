@@ -0,0 +1,47 @@
+extern crate serde_json;
+
+mod coordinator;
+pub mod worker;
+
+pub use self::coordinator::Coordinator;
+
+use std::io::{self, BufRead, Write};
+
+use ray_tracer::tile::Tile;
+
+// Coordinator / worker protocol - one JSON message per line
+//
+// coordinator -> worker: Scene, then Tile ... Finished
+// worker -> coordinator: Pixels for each Tile
+#[derive(Serialize, Deserialize)]
+pub enum Message {
+    // Contents of the configuration file - object files must exist at the same paths on workers
+    Scene(String),
+    Tile(Tile),
+    // Row-major linear RGB for the tile
+    Pixels(Tile, Vec<(f64, f64, f64)>),
+    Finished,
+}
+
+impl Message {
+    pub fn send<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut line: Vec<u8> = serde_json::to_vec(self)
+            .map_err(|error| io::Error::other(error.to_string()))?;
+        line.push(b'\n');
+
+        writer.write_all(&line)?;
+        writer.flush()
+    }
+
+    pub fn receive<R: BufRead>(reader: &mut R) -> io::Result<Message> {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Connection closed",
+            ));
+        }
+
+        serde_json::from_str(&line).map_err(|error| io::Error::other(error.to_string()))
+    }
+}
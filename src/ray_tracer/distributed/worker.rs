@@ -0,0 +1,85 @@
+extern crate serde_json;
+
+use std::io::{self, BufReader};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+
+use super::Message;
+
+use ray_tracer::color::Radiance;
+use ray_tracer::kd_tree::KdTree;
+use ray_tracer::light::Light;
+use ray_tracer::scene::Scene;
+use ray_tracer::scene::configuration::Configuration;
+use ray_tracer::tile::Tile;
+use ray_tracer::volume::Volume;
+
+// Split the tile into bands, one per scene (thread)
+fn render_tile(scenes: &mut [Scene], tile: &Tile) -> Vec<(f64, f64, f64)> {
+    let bands: Vec<Tile> = tile.bands(scenes.len());
+
+    let band_pixels: Vec<Vec<Radiance>> = thread::scope(|scope| {
+        let handles: Vec<_> = scenes
+            .iter_mut()
+            .zip(bands.iter())
+            .map(|(scene, band)| scope.spawn(move || scene.render_tile(band)))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    // Bands are consecutive rows, so concatenating them is row-major for the tile
+    band_pixels
+        .into_iter()
+        .flat_map(|pixels| pixels.into_iter())
+        .map(|color| (color.r, color.g, color.b))
+        .collect()
+}
+
+pub fn work(address: &str) -> io::Result<()> {
+    let stream: TcpStream = TcpStream::connect(address)?;
+    let mut reader = BufReader::new(&stream);
+
+    let configuration: Configuration = match Message::receive(&mut reader)? {
+        Message::Scene(scene) => serde_json::from_str(&scene)
+            .map_err(|error| io::Error::other(error.to_string()))?,
+        _ => return Err(io::Error::other("Expected scene from coordinator")),
+    };
+
+    // Band threads are spawned from this one and inherit its priority
+    if configuration.low_priority {
+        super::super::lower_thread_priority();
+    }
+
+    let kd_tree: Arc<KdTree> = Arc::new(super::super::build_kd_tree(&configuration)?);
+    let volumes: Arc<Vec<Volume>> = Arc::new(super::super::load_volumes(&configuration)?);
+    let lights: Arc<Vec<Light>> = Arc::new(super::super::load_lights(&configuration)?);
+    let mut scenes: Vec<Scene> = (0..configuration.thread_count())
+        .map(|_| {
+            Scene::new(
+                &configuration,
+                Arc::clone(&kd_tree),
+                Arc::clone(&volumes),
+                Arc::clone(&lights),
+            )
+        })
+        .collect::<io::Result<Vec<Scene>>>()?;
+
+    info!("Connected to {}", address);
+
+    loop {
+        match Message::receive(&mut reader)? {
+            Message::Tile(tile) => {
+                let pixels: Vec<(f64, f64, f64)> = render_tile(&mut scenes, &tile);
+                kd_tree.check_pages()?;
+                Message::Pixels(tile, pixels).send(&mut &stream)?;
+            }
+            Message::Finished => return Ok(()),
+            _ => return Err(io::Error::other("Unexpected message from coordinator")),
+        }
+    }
+}
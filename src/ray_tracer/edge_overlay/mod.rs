@@ -0,0 +1,36 @@
+use super::color::{DisplayColor, Radiance};
+
+// Draw triangle edges over the shaded image, e.g. --edges
+// Lines keep the same width in pixels at any distance from the camera. Only triangles seen
+// directly are outlined - not spheres or reflections
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EdgeOverlay {
+    // Line width in pixels
+    #[serde(default = "default_width")]
+    pub width: f64,
+    #[serde(default = "default_color")]
+    pub color: Vec<u8>,
+}
+
+fn default_width() -> f64 {
+    1f64
+}
+
+fn default_color() -> Vec<u8> {
+    vec![0, 0, 0]
+}
+
+impl EdgeOverlay {
+    pub fn color(&self) -> Radiance {
+        Radiance::from_display(DisplayColor::from_slice(&self.color))
+    }
+}
+
+impl Default for EdgeOverlay {
+    fn default() -> EdgeOverlay {
+        EdgeOverlay {
+            width: default_width(),
+            color: default_color(),
+        }
+    }
+}
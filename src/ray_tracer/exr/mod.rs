@@ -0,0 +1,472 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::color::Radiance;
+use super::pixel_buffer::{self, Layer, PixelBuffer};
+use super::tile::Tile;
+
+// Minimal OpenEXR writer - single part, scanline, uncompressed, 32-bit float channels
+// https://www.openexr.com/documentation/openexrfilelayout.pdf
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const VERSION: u32 = 2;
+// Version flags for tiled and deep data
+const SINGLE_TILE: u32 = 0x200;
+const NON_IMAGE: u32 = 0x800;
+
+const PIXEL_TYPE_FLOAT: i32 = 2;
+const NO_COMPRESSION: u8 = 0;
+const INCREASING_Y: u8 = 0;
+const RANDOM_Y: u8 = 2;
+// One resolution level, rounded down
+const ONE_LEVEL: u8 = 0;
+
+pub struct Channel {
+    pub name: String,
+    // Row-major, width * height values
+    pub values: Vec<f32>,
+}
+
+pub struct ExrImage {
+    width: usize,
+    height: usize,
+    channels: Vec<Channel>,
+    // Extra string attributes written after the required ones, e.g. Cryptomatte metadata
+    attributes: Vec<(String, String)>,
+    overscan: (usize, usize),
+}
+
+fn box2i(x_min: i32, y_min: i32, x_max: i32, y_max: i32) -> Vec<u8> {
+    let mut value: Vec<u8> = Vec::with_capacity(16);
+    value.extend_from_slice(&x_min.to_le_bytes());
+    value.extend_from_slice(&y_min.to_le_bytes());
+    value.extend_from_slice(&x_max.to_le_bytes());
+    value.extend_from_slice(&y_max.to_le_bytes());
+    value
+}
+
+// Attributes every image needs, for float channels with the given names in alphabetical order
+// Overscan pixels on each side are outside the display window
+fn write_standard_attributes(
+    header: &mut Vec<u8>,
+    channels: &[&str],
+    width: usize,
+    height: usize,
+    overscan: (usize, usize),
+    line_order: u8,
+) -> io::Result<()> {
+    let mut channel_list: Vec<u8> = Vec::new();
+    for name in channels {
+        channel_list.write_all(name.as_bytes())?;
+        channel_list.write_all(&[0])?;
+        channel_list.write_all(&PIXEL_TYPE_FLOAT.to_le_bytes())?;
+        // pLinear + reserved
+        channel_list.write_all(&[0, 0, 0, 0])?;
+        // x and y sampling
+        channel_list.write_all(&1i32.to_le_bytes())?;
+        channel_list.write_all(&1i32.to_le_bytes())?;
+    }
+    channel_list.write_all(&[0])?;
+
+    let mut screen_window_center: Vec<u8> = Vec::with_capacity(8);
+    screen_window_center.extend_from_slice(&0f32.to_le_bytes());
+    screen_window_center.extend_from_slice(&0f32.to_le_bytes());
+
+    ExrImage::write_attribute(header, "channels", "chlist", &channel_list)?;
+    ExrImage::write_attribute(header, "compression", "compression", &[NO_COMPRESSION])?;
+    let (x, y) = (overscan.0 as i32, overscan.1 as i32);
+    let data_window: Vec<u8> = box2i(-x, -y, width as i32 - x - 1, height as i32 - y - 1);
+    let display_window: Vec<u8> = box2i(0, 0, width as i32 - 2 * x - 1, height as i32 - 2 * y - 1);
+
+    ExrImage::write_attribute(header, "dataWindow", "box2i", &data_window)?;
+    ExrImage::write_attribute(header, "displayWindow", "box2i", &display_window)?;
+    ExrImage::write_attribute(header, "lineOrder", "lineOrder", &[line_order])?;
+    ExrImage::write_attribute(header, "pixelAspectRatio", "float", &1f32.to_le_bytes())?;
+    ExrImage::write_attribute(header, "screenWindowCenter", "v2f", &screen_window_center)?;
+    ExrImage::write_attribute(header, "screenWindowWidth", "float", &1f32.to_le_bytes())
+}
+
+impl ExrImage {
+    pub fn new(width: usize, height: usize) -> ExrImage {
+        ExrImage {
+            width,
+            height,
+            channels: Vec::new(),
+            attributes: Vec::new(),
+            overscan: (0, 0),
+        }
+    }
+
+    // Linear RGB - no tone mapping is applied
+    pub fn from_radiance(color_buffer: &[Vec<Radiance>], width: usize, height: usize) -> ExrImage {
+        ExrImage::from_pixel_buffer(&PixelBuffer::from_radiance(color_buffer, width, height))
+    }
+
+    // Every layer - the color layer's channels unprefixed, as viewers expect, and the
+    // others' as <layer>.<channel>
+    pub fn from_pixel_buffer(pixel_buffer: &PixelBuffer) -> ExrImage {
+        let mut image: ExrImage = ExrImage::new(pixel_buffer.width(), pixel_buffer.height());
+        for layer in pixel_buffer.layers() {
+            image.add_layer(layer);
+        }
+
+        image
+    }
+
+    pub fn add_layer(&mut self, layer: &Layer) {
+        for (index, channel) in layer.format.channels().iter().enumerate() {
+            let name: String = if layer.name == pixel_buffer::COLOR {
+                channel.to_string()
+            } else {
+                format!("{}.{}", layer.name, channel)
+            };
+
+            self.channels.push(Channel {
+                name,
+                values: layer.channel(index).collect(),
+            });
+        }
+
+        // Channels must be stored in alphabetical order
+        self.channels.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    pub fn add_channel<F>(&mut self, name: &str, value: F)
+    where
+        F: Fn(usize, usize) -> f32,
+    {
+        let mut values: Vec<f32> = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                values.push(value(x, y));
+            }
+        }
+
+        self.channels.push(Channel {
+            name: String::from(name),
+            values,
+        });
+
+        // Channels must be stored in alphabetical order
+        self.channels.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    // Pixels beyond each horizontal and vertical edge of the frame
+    pub fn set_overscan(&mut self, overscan: (usize, usize)) {
+        self.overscan = overscan;
+    }
+
+    pub fn add_string_attribute(&mut self, name: &str, value: &str) {
+        self.attributes.push((String::from(name), String::from(value)));
+    }
+
+    fn write_attribute<W: Write>(
+        writer: &mut W,
+        name: &str,
+        attribute_type: &str,
+        value: &[u8],
+    ) -> io::Result<()> {
+        writer.write_all(name.as_bytes())?;
+        writer.write_all(&[0])?;
+        writer.write_all(attribute_type.as_bytes())?;
+        writer.write_all(&[0])?;
+        writer.write_all(&(value.len() as i32).to_le_bytes())?;
+        writer.write_all(value)
+    }
+
+    fn header(&self) -> io::Result<Vec<u8>> {
+        let names: Vec<&str> = self.channels.iter().map(|channel| channel.name.as_str()).collect();
+
+        let mut header: Vec<u8> = Vec::new();
+        header.write_all(&MAGIC)?;
+        header.write_all(&VERSION.to_le_bytes())?;
+        write_standard_attributes(
+            &mut header,
+            &names,
+            self.width,
+            self.height,
+            self.overscan,
+            INCREASING_Y,
+        )?;
+        for (name, value) in &self.attributes {
+            ExrImage::write_attribute(&mut header, name, "string", value.as_bytes())?;
+        }
+        header.write_all(&[0])?;
+
+        Ok(header)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let header: Vec<u8> = self.header()?;
+        writer.write_all(&header)?;
+
+        // One scanline per chunk: y coordinate, data size, then each channel's row
+        let line_size: usize = self.width * self.channels.len() * 4;
+        let chunk_size: usize = 8 + line_size;
+
+        // Offset table - absolute position of each chunk
+        let first_chunk: usize = header.len() + self.height * 8;
+        for y in 0..self.height {
+            writer.write_all(&((first_chunk + y * chunk_size) as u64).to_le_bytes())?;
+        }
+
+        for y in 0..self.height {
+            // Scanlines are numbered from the top of the data window
+            writer.write_all(&(y as i32 - self.overscan.1 as i32).to_le_bytes())?;
+            writer.write_all(&(line_size as i32).to_le_bytes())?;
+
+            for channel in &self.channels {
+                for value in &channel.values[y * self.width..(y + 1) * self.width] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(Path::new(filename))?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+}
+
+// One sample of a deep pixel - color is premultiplied by alpha
+#[derive(Clone, Copy, Debug)]
+pub struct DeepSample {
+    pub depth: f32,
+    pub depth_back: f32,
+    pub color: Radiance,
+    pub alpha: f32,
+}
+
+// Single part deep scanline image with RGBA, Z and ZBack channels
+// Each pixel holds any number of samples, nearest first
+pub struct DeepExrImage {
+    width: usize,
+    height: usize,
+    // Row-major, width * height sample lists
+    pixels: Vec<Vec<DeepSample>>,
+    overscan: (usize, usize),
+}
+
+const DEEP_CHANNELS: [&str; 6] = ["A", "B", "G", "R", "Z", "ZBack"];
+
+impl DeepExrImage {
+    pub fn new<F>(width: usize, height: usize, samples: F) -> DeepExrImage
+    where
+        F: Fn(usize, usize) -> Vec<DeepSample>,
+    {
+        let mut pixels: Vec<Vec<DeepSample>> = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(samples(x, y));
+            }
+        }
+
+        DeepExrImage {
+            width,
+            height,
+            pixels,
+            overscan: (0, 0),
+        }
+    }
+
+    // Pixels beyond each horizontal and vertical edge of the frame
+    pub fn set_overscan(&mut self, overscan: (usize, usize)) {
+        self.overscan = overscan;
+    }
+
+    fn header(&self) -> io::Result<Vec<u8>> {
+        let max_samples: usize = self.pixels.iter().map(|pixel| pixel.len()).max().unwrap_or(0);
+
+        let mut header: Vec<u8> = Vec::new();
+        header.write_all(&MAGIC)?;
+        header.write_all(&(VERSION | NON_IMAGE).to_le_bytes())?;
+        write_standard_attributes(
+            &mut header,
+            &DEEP_CHANNELS,
+            self.width,
+            self.height,
+            self.overscan,
+            INCREASING_Y,
+        )?;
+        ExrImage::write_attribute(
+            &mut header,
+            "chunkCount",
+            "int",
+            &(self.height as i32).to_le_bytes(),
+        )?;
+        ExrImage::write_attribute(
+            &mut header,
+            "maxSamplesPerPixel",
+            "int",
+            &(max_samples as i32).to_le_bytes(),
+        )?;
+        ExrImage::write_attribute(&mut header, "name", "string", b"deep")?;
+        ExrImage::write_attribute(&mut header, "type", "string", b"deepscanline")?;
+        ExrImage::write_attribute(&mut header, "version", "int", &1i32.to_le_bytes())?;
+        header.write_all(&[0])?;
+
+        Ok(header)
+    }
+
+    // Scanline chunk: y, table and data sizes, then the cumulative sample count of each pixel
+    // followed by each channel's samples for the whole line
+    fn chunk(&self, y: usize) -> Vec<u8> {
+        let line: &[Vec<DeepSample>] = &self.pixels[y * self.width..(y + 1) * self.width];
+        let sample_count: usize = line.iter().map(|pixel| pixel.len()).sum();
+        let data_size: u64 = (sample_count * DEEP_CHANNELS.len() * 4) as u64;
+
+        let mut chunk: Vec<u8> = Vec::new();
+        chunk.extend_from_slice(&(y as i32 - self.overscan.1 as i32).to_le_bytes());
+        chunk.extend_from_slice(&((self.width * 4) as u64).to_le_bytes());
+        chunk.extend_from_slice(&data_size.to_le_bytes());
+        chunk.extend_from_slice(&data_size.to_le_bytes());
+
+        let mut total: i32 = 0;
+        for pixel in line {
+            total += pixel.len() as i32;
+            chunk.extend_from_slice(&total.to_le_bytes());
+        }
+
+        let channel_values: [fn(&DeepSample) -> f32; 6] = [
+            |sample| sample.alpha,
+            |sample| sample.color.b as f32,
+            |sample| sample.color.g as f32,
+            |sample| sample.color.r as f32,
+            |sample| sample.depth,
+            |sample| sample.depth_back,
+        ];
+        for value in &channel_values {
+            for sample in line.iter().flat_map(|pixel| pixel.iter()) {
+                chunk.extend_from_slice(&value(sample).to_le_bytes());
+            }
+        }
+
+        chunk
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let header: Vec<u8> = self.header()?;
+        writer.write_all(&header)?;
+
+        let chunks: Vec<Vec<u8>> = (0..self.height).map(|y| self.chunk(y)).collect();
+
+        // Offset table - absolute position of each chunk
+        let mut offset: usize = header.len() + self.height * 8;
+        for chunk in &chunks {
+            writer.write_all(&(offset as u64).to_le_bytes())?;
+            offset += chunk.len();
+        }
+
+        for chunk in &chunks {
+            writer.write_all(chunk)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(Path::new(filename))?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+}
+
+const TILED_CHANNELS: [&str; 3] = ["B", "G", "R"];
+
+// Linear RGB written a tile at a time, in any order, so the whole image is never in memory
+pub struct TiledExrWriter {
+    writer: BufWriter<File>,
+    width: usize,
+    height: usize,
+    tile_size: usize,
+    // Where each tile was written, row by row
+    offsets: Vec<u64>,
+    offset_table: u64,
+}
+
+impl TiledExrWriter {
+    // Write the header and leave room for the offset table
+    pub fn create(
+        filename: &str,
+        width: usize,
+        height: usize,
+        tile_size: usize,
+        overscan: (usize, usize),
+    ) -> io::Result<TiledExrWriter> {
+        let mut header: Vec<u8> = Vec::new();
+        header.write_all(&MAGIC)?;
+        header.write_all(&(VERSION | SINGLE_TILE).to_le_bytes())?;
+        write_standard_attributes(&mut header, &TILED_CHANNELS, width, height, overscan, RANDOM_Y)?;
+
+        let mut tile_description: Vec<u8> = Vec::with_capacity(9);
+        tile_description.extend_from_slice(&(tile_size as u32).to_le_bytes());
+        tile_description.extend_from_slice(&(tile_size as u32).to_le_bytes());
+        tile_description.push(ONE_LEVEL);
+        ExrImage::write_attribute(&mut header, "tiles", "tiledesc", &tile_description)?;
+        header.write_all(&[0])?;
+
+        let tiles: usize = width.div_ceil(tile_size) * height.div_ceil(tile_size);
+
+        let mut writer = BufWriter::new(File::create(Path::new(filename))?);
+        writer.write_all(&header)?;
+        writer.write_all(&vec![0; tiles * 8])?;
+
+        Ok(TiledExrWriter {
+            writer,
+            width,
+            height,
+            tile_size,
+            offsets: vec![0; tiles],
+            offset_table: header.len() as u64,
+        })
+    }
+
+    // Tiles must come from Tile::split with the same tile size - pixels are row-major
+    pub fn write_tile(&mut self, tile: &Tile, pixels: &[Radiance]) -> io::Result<()> {
+        let (column, row) = (tile.x / self.tile_size, tile.y / self.tile_size);
+        self.offsets[row * self.width.div_ceil(self.tile_size) + column] =
+            self.writer.stream_position()?;
+
+        // Tile and level coordinates, data size, then each channel of each line in turn
+        let data_size: usize = tile.pixel_count() * TILED_CHANNELS.len() * 4;
+        for value in &[column as i32, row as i32, 0, 0, data_size as i32] {
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+
+        let channel_values: [fn(&Radiance) -> f64; 3] =
+            [|color| color.b, |color| color.g, |color| color.r];
+        for line in pixels.chunks(tile.width) {
+            for value in &channel_values {
+                for color in line {
+                    self.writer.write_all(&(value(color) as f32).to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Fill in the offset table once every tile has been written
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.offsets.contains(&0) {
+            return Err(io::Error::other(format!(
+                "{} of the {}x{} image's tiles were never written",
+                self.offsets.iter().filter(|&&offset| offset == 0).count(),
+                self.width,
+                self.height
+            )));
+        }
+
+        self.writer.seek(SeekFrom::Start(self.offset_table))?;
+        for offset in &self.offsets {
+            self.writer.write_all(&offset.to_le_bytes())?;
+        }
+
+        self.writer.flush()
+    }
+}
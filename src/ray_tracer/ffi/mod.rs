@@ -0,0 +1,155 @@
+extern crate serde_json;
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_double, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::scene::configuration::Configuration;
+
+// C interface for embedding the renderer - see include/raytracer.h
+// Panics never cross the boundary; failures are reported through return values
+
+pub const RT_OK: c_int = 0;
+pub const RT_INVALID_ARGUMENT: c_int = -1;
+pub const RT_BUFFER_TOO_SMALL: c_int = -2;
+pub const RT_RENDER_FAILED: c_int = -3;
+
+/// A scene loaded from a configuration, ready to render.
+pub struct RtScene {
+    configuration: Configuration,
+    progress: Arc<AtomicUsize>,
+}
+
+/// Create a scene from a JSON configuration string.
+///
+/// Returns null if the configuration is invalid. Free with `rt_scene_free`.
+///
+/// # Safety
+///
+/// `configuration` must be a valid, NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_new(configuration: *const c_char) -> *mut RtScene {
+    if configuration.is_null() {
+        return ptr::null_mut();
+    }
+
+    let json: &str = match CStr::from_ptr(configuration).to_str() {
+        Ok(json) => json,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    match serde_json::from_str(json) {
+        Ok(configuration) => Box::into_raw(Box::new(RtScene {
+            configuration,
+            progress: Arc::new(AtomicUsize::new(0)),
+        })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Override the configured output resolution.
+///
+/// # Safety
+///
+/// `scene` must come from `rt_scene_new` and must not be rendering.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_resolution(
+    scene: *mut RtScene,
+    width: usize,
+    height: usize,
+) -> c_int {
+    if scene.is_null() || width == 0 || height == 0 {
+        return RT_INVALID_ARGUMENT;
+    }
+
+    let scene: &mut RtScene = &mut *scene;
+    scene.configuration.width = width;
+    scene.configuration.height = height;
+
+    RT_OK
+}
+
+/// Render into `buffer` as tone mapped, row-major RGBA (4 bytes per pixel).
+///
+/// Blocks until the render finishes; `rt_scene_progress` may be polled from another thread.
+///
+/// # Safety
+///
+/// `scene` must come from `rt_scene_new` and `buffer` must be valid for `buffer_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_render(
+    scene: *const RtScene,
+    buffer: *mut u8,
+    buffer_len: usize,
+) -> c_int {
+    if scene.is_null() || buffer.is_null() {
+        return RT_INVALID_ARGUMENT;
+    }
+
+    let scene: &RtScene = &*scene;
+    let configuration: &Configuration = &scene.configuration;
+
+    // A resolution whose size doesn't fit in memory can't be rendered
+    let size: usize = match configuration
+        .width
+        .checked_mul(configuration.height)
+        .and_then(|pixels| pixels.checked_mul(4))
+    {
+        Some(size) => size,
+        None => return RT_INVALID_ARGUMENT,
+    };
+
+    if buffer_len < size {
+        return RT_BUFFER_TOO_SMALL;
+    }
+
+    scene.progress.store(0, Ordering::Relaxed);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        super::trace(configuration, &scene.progress).map(|mut color_buffer| {
+            super::tone_map(configuration, &mut color_buffer);
+            super::rgba_bytes(&color_buffer, configuration.width, configuration.height)
+        })
+    }));
+
+    match result {
+        Ok(Ok(pixels)) => {
+            slice::from_raw_parts_mut(buffer, pixels.len()).copy_from_slice(&pixels);
+            RT_OK
+        }
+        Ok(Err(_)) | Err(_) => RT_RENDER_FAILED,
+    }
+}
+
+/// Fraction of pixels completed by the current or last render, from 0.0 to 1.0.
+///
+/// # Safety
+///
+/// `scene` must come from `rt_scene_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_progress(scene: *const RtScene) -> c_double {
+    if scene.is_null() {
+        return 0f64;
+    }
+
+    let scene: &RtScene = &*scene;
+    let pixels: usize = scene.configuration.pixel_count();
+
+    scene.progress.load(Ordering::Relaxed) as f64 / pixels.max(1) as f64
+}
+
+/// Release a scene created by `rt_scene_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `scene` must come from `rt_scene_new` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_free(scene: *mut RtScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
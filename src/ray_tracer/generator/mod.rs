@@ -0,0 +1,303 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::collections::HashSet;
+use std::io;
+
+use super::math::OrthonormalBasis;
+use super::noise;
+use super::object::Shape;
+use super::object::material::Material;
+use super::object::sphere::Sphere;
+use super::object::triangle::Triangle;
+
+// Procedural geometry expanded when the configuration is loaded
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Generator {
+    // counts[0] x counts[1] x counts[2] spheres, spacing apart from origin
+    SphereGrid {
+        counts: [usize; 3],
+        spacing: f64,
+        radius: f64,
+        #[serde(default = "zero_vector")]
+        origin: [f64; 3],
+    },
+    // Spheres resting at random points on the surface
+    Scatter {
+        count: usize,
+        radius: f64,
+        #[serde(default)]
+        seed: u64,
+        surface: Surface,
+    },
+    // The surface itself, as a resolution x resolution grid of quads
+    Heightfield { resolution: usize, surface: Surface },
+    // Cube of the given size with the middle of each face and the center removed, level times
+    MengerSponge {
+        level: usize,
+        size: f64,
+        #[serde(default = "zero_vector")]
+        center: [f64; 3],
+    },
+}
+
+// Square of side size centered on center, facing up and displaced by noise when height is set.
+// Generators given the same surface line up, e.g. a scatter resting on a heightfield
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Surface {
+    #[serde(default = "zero_vector")]
+    pub center: [f64; 3],
+    #[serde(default = "default_up")]
+    pub up: [f64; 3],
+    pub size: f64,
+    // Largest displacement above or below center
+    #[serde(default)]
+    pub height: f64,
+    // Size of the largest hills
+    #[serde(default = "default_noise_scale")]
+    pub noise_scale: f64,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn zero_vector() -> [f64; 3] {
+    [0f64, 0f64, 0f64]
+}
+
+fn default_up() -> [f64; 3] {
+    [0f64, 1f64, 0f64]
+}
+
+fn default_noise_scale() -> f64 {
+    10f64
+}
+
+// Each Menger sponge level multiplies the cubes by 20 - level 5 is already 3.2 million
+const MAX_MENGER_LEVEL: usize = 5;
+
+// Corners of a unit cube, and its faces wound outward with the direction each faces
+const CORNERS: [(f64, f64, f64); 8] = [
+    (0f64, 0f64, 0f64),
+    (1f64, 0f64, 0f64),
+    (1f64, 1f64, 0f64),
+    (0f64, 1f64, 0f64),
+    (0f64, 0f64, 1f64),
+    (1f64, 0f64, 1f64),
+    (1f64, 1f64, 1f64),
+    (0f64, 1f64, 1f64),
+];
+const FACES: [([usize; 4], (i64, i64, i64)); 6] = [
+    ([0, 3, 2, 1], (0, 0, -1)),
+    ([4, 5, 6, 7], (0, 0, 1)),
+    ([0, 1, 5, 4], (0, -1, 0)),
+    ([1, 2, 6, 5], (1, 0, 0)),
+    ([2, 3, 7, 6], (0, 1, 0)),
+    ([3, 0, 4, 7], (-1, 0, 0)),
+];
+
+fn vector(values: [f64; 3]) -> Vector3<f64> {
+    Vector3::from(values)
+}
+
+// Same positions for the same seed - rand's xorshift needs a nonzero seed
+fn rng_from_seed(seed: u64) -> XorShiftRng {
+    XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x9e37_79b9, 0x7f4a_7c15])
+}
+
+fn quad(
+    a: Vector3<f64>,
+    b: Vector3<f64>,
+    c: Vector3<f64>,
+    d: Vector3<f64>,
+    material: Material,
+) -> [Shape; 2] {
+    [
+        Shape::Triangle(Triangle::new(a, b, c, material)),
+        Shape::Triangle(Triangle::new(a, c, d, material)),
+    ]
+}
+
+impl Surface {
+    // Point on the surface at u, v from the center along the square's sides
+    pub fn point(&self, u: f64, v: f64) -> Vector3<f64> {
+        let basis: OrthonormalBasis = OrthonormalBasis::from_normal(vector(self.up));
+        basis.local_to_world(Vector3::new(u, v, self.height_at(u, v))) + vector(self.center)
+    }
+
+    // Displacement along up
+    fn height_at(&self, u: f64, v: f64) -> f64 {
+        if self.height == 0f64 {
+            return 0f64;
+        }
+
+        // The seed moves to a different part of the noise
+        let offset: f64 = (self.seed % 65_536) as f64 * 17.3;
+        let point: Vector3<f64> = Vector3::new(u + offset, v - offset, offset) / self.noise_scale;
+
+        noise::fbm(point, 4) * self.height
+    }
+
+    // Uniformly random u and v within the square
+    fn random_point<R: Rng>(&self, rng: &mut R) -> (f64, f64) {
+        (
+            (rng.gen::<f64>() - 0.5) * self.size,
+            (rng.gen::<f64>() - 0.5) * self.size,
+        )
+    }
+}
+
+impl Generator {
+    // Most shapes the generator can expand to, without expanding it - None if that
+    // overflows
+    pub fn max_shapes(&self) -> Option<usize> {
+        match *self {
+            Generator::SphereGrid { counts, .. } => counts[0]
+                .checked_mul(counts[1])
+                .and_then(|count| count.checked_mul(counts[2])),
+            Generator::Scatter { count, .. } => Some(count),
+            Generator::Heightfield { resolution, .. } => resolution
+                .max(1)
+                .checked_mul(resolution.max(1))
+                .and_then(|quads| quads.checked_mul(2)),
+            // Two triangles on each face of every cube
+            Generator::MengerSponge { level, .. } => 20usize
+                .checked_pow(level.min(MAX_MENGER_LEVEL) as u32)
+                .and_then(|cubes| cubes.checked_mul(12)),
+        }
+    }
+
+    pub fn shapes(&self, material: Material) -> Result<Vec<Shape>, io::Error> {
+        let shapes: Vec<Shape> = match *self {
+            Generator::SphereGrid {
+                counts,
+                spacing,
+                radius,
+                origin,
+            } => {
+                let origin: Vector3<f64> = vector(origin);
+                let mut shapes: Vec<Shape> = Vec::new();
+
+                for x in 0..counts[0] {
+                    for y in 0..counts[1] {
+                        for z in 0..counts[2] {
+                            let offset: Vector3<f64> = Vector3::new(x as f64, y as f64, z as f64);
+                            shapes.push(Shape::Sphere(Sphere::new(
+                                origin + offset * spacing,
+                                radius,
+                                material,
+                            )));
+                        }
+                    }
+                }
+
+                shapes
+            }
+            Generator::Scatter {
+                count,
+                radius,
+                seed,
+                ref surface,
+            } => {
+                let mut rng: XorShiftRng = rng_from_seed(seed);
+
+                (0..count)
+                    .map(|_| {
+                        let (u, v) = surface.random_point(&mut rng);
+                        let origin: Vector3<f64> =
+                            surface.point(u, v) + vector(surface.up).normalize() * radius;
+                        Shape::Sphere(Sphere::new(origin, radius, material))
+                    })
+                    .collect()
+            }
+            Generator::Heightfield {
+                resolution,
+                ref surface,
+            } => Generator::heightfield(resolution.max(1), surface, material),
+            Generator::MengerSponge { level, .. } if level > MAX_MENGER_LEVEL => {
+                return Err(io::Error::other(format!(
+                    "menger_sponge level {} is above the maximum of {}",
+                    level, MAX_MENGER_LEVEL
+                )));
+            }
+            Generator::MengerSponge {
+                level,
+                size,
+                center,
+            } => Generator::menger_sponge(level, size, vector(center), material),
+        };
+
+        Ok(shapes)
+    }
+
+    fn heightfield(resolution: usize, surface: &Surface, material: Material) -> Vec<Shape> {
+        let step: f64 = surface.size / resolution as f64;
+        let point = |i: usize, j: usize| -> Vector3<f64> {
+            let start: f64 = -surface.size / 2f64;
+            surface.point(start + i as f64 * step, start + j as f64 * step)
+        };
+
+        let mut shapes: Vec<Shape> = Vec::with_capacity(resolution * resolution * 2);
+        for i in 0..resolution {
+            for j in 0..resolution {
+                // Counter-clockwise seen from above
+                shapes.extend_from_slice(&quad(
+                    point(i, j),
+                    point(i + 1, j),
+                    point(i + 1, j + 1),
+                    point(i, j + 1),
+                    material,
+                ));
+            }
+        }
+
+        shapes
+    }
+
+    // Only faces between a cube and empty space are kept
+    fn menger_sponge(
+        level: usize,
+        size: f64,
+        center: Vector3<f64>,
+        material: Material,
+    ) -> Vec<Shape> {
+        let mut cubes: Vec<(i64, i64, i64)> = vec![(0, 0, 0)];
+        for _ in 0..level {
+            cubes = cubes
+                .iter()
+                .flat_map(|&(x, y, z)| {
+                    (0..27)
+                        .map(|i: i64| (i % 3, i / 3 % 3, i / 9))
+                        .filter(|&(dx, dy, dz)| {
+                            // Drop cubes in the middle of two or three axes
+                            [dx, dy, dz].iter().filter(|&&d| d == 1).count() < 2
+                        })
+                        .map(move |(dx, dy, dz)| (x * 3 + dx, y * 3 + dy, z * 3 + dz))
+                })
+                .collect();
+        }
+
+        let occupied: HashSet<(i64, i64, i64)> = cubes.iter().cloned().collect();
+        let cube_size: f64 = size / 3f64.powi(level as i32);
+        let low: Vector3<f64> = center - Vector3::new(size, size, size) / 2f64;
+
+        let mut shapes: Vec<Shape> = Vec::new();
+        for &(x, y, z) in &cubes {
+            let corner = |i: usize| -> Vector3<f64> {
+                let (cx, cy, cz) = CORNERS[i];
+                low + Vector3::new(x as f64 + cx, y as f64 + cy, z as f64 + cz) * cube_size
+            };
+
+            for &(face, (dx, dy, dz)) in &FACES {
+                if !occupied.contains(&(x + dx, y + dy, z + dz)) {
+                    let [a, b, c, d] = face.map(corner);
+                    shapes.extend_from_slice(&quad(a, b, c, d, material));
+                }
+            }
+        }
+
+        shapes
+    }
+}
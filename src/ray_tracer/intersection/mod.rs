@@ -4,9 +4,9 @@ use self::cgmath::*;
 use std::f64;
 
 use ray_tracer::object::*;
+use ray_tracer::object::material::Material;
 use ray_tracer::ray::Ray;
 
-
 pub struct Intersection {
     pub ray_direction: Vector3<f64>,
     pub shape: Shape,
@@ -58,3 +58,30 @@ impl Intersection {
         }
     }
 }
+
+// What a ray query found, for uses other than rendering, e.g. picking
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub distance: f64,
+    pub point: Vector3<f64>,
+    // Facing back along the ray
+    pub normal: Vector3<f64>,
+    // Texture coordinates - barycentric coordinates for meshes without them
+    pub uv: Vector2<f64>,
+    // Index of the configured object, or of the SceneBuilder add_shape call
+    pub object_id: usize,
+    pub material: Material,
+}
+
+impl Hit {
+    pub fn from_intersection(intersection: &Intersection) -> Hit {
+        Hit {
+            distance: intersection.distance,
+            point: intersection.point,
+            normal: intersection.normal,
+            uv: intersection.shape.uv(intersection.point),
+            object_id: intersection.shape.object_id(),
+            material: intersection.shape.material(),
+        }
+    }
+}
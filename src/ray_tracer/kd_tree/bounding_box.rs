@@ -5,7 +5,7 @@ use ray_tracer::ray::Ray;
 
 use std::f64;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct BoundingBox {
     pub min: Vector3<f64>,
     pub max: Vector3<f64>,
@@ -69,6 +69,22 @@ impl BoundingBox {
         Some(intersection)
     }
 
+    // Distance along the ray to where it leaves the box
+    pub fn exit_distance(&self, ray: &Ray) -> Option<f64> {
+        let mut tmin: f64 = f64::MIN;
+        let mut tmax: f64 = f64::MAX;
+
+        for i in 0..3 {
+            let t1: f64 = (self.min[i] - ray.origin[i]) * ray.inv_dir[i];
+            let t2: f64 = (self.max[i] - ray.origin[i]) * ray.inv_dir[i];
+
+            tmin = tmin.max(t1.min(t2));
+            tmax = tmax.min(t1.max(t2));
+        }
+
+        if tmax < tmin.max(0f64) { None } else { Some(tmax) }
+    }
+
     pub fn new(min: Vector3<f64>, max: Vector3<f64>) -> BoundingBox {
         BoundingBox { min, max }
     }
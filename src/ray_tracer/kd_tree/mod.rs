@@ -1,27 +1,78 @@
 extern crate cgmath;
 
 mod node;
+mod paging;
 pub mod bounding_box;
 
 use std::f64;
+use std::io;
+use std::mem;
+use std::sync::Arc;
 use std::usize;
 
 use self::cgmath::*;
 use self::node::Node;
+use self::paging::{PageStore, Spool};
 use self::bounding_box::BoundingBox;
 use ray_tracer::intersection::Intersection;
 use ray_tracer::object::*;
 use ray_tracer::ray::Ray;
 
+// Work done finding an intersection - for the cost debug views
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraversalCost {
+    pub nodes: usize,
+    pub intersection_tests: usize,
+}
+
+impl TraversalCost {
+    pub fn add(&mut self, other: &TraversalCost) {
+        self.nodes += other.nodes;
+        self.intersection_tests += other.intersection_tests;
+    }
+}
+
 pub struct KdTree {
     nodes: Vec<Node>,
     next_index: usize,
     root_index: usize,
     max_depth: usize,
+    // Nodes with this many objects or fewer aren't split
+    leaf_size: usize,
+    // Leaf geometry kept on disk when a memory limit is set
+    pages: Option<PageStore>,
+}
+
+// Shapes streamed to disk for a paged tree as they are loaded, so they are never all held
+// at once - see KdTree::from_spool
+pub struct ShapeSpool {
+    pages: PageStore,
+    spool: Spool,
+}
+
+impl ShapeSpool {
+    pub fn new(memory_limit: usize) -> io::Result<ShapeSpool> {
+        let pages: PageStore = PageStore::new(memory_limit)?;
+        let spool: Spool = pages.spool()?;
+
+        Ok(ShapeSpool { pages, spool })
+    }
+
+    pub fn push(&mut self, shapes: &[Shape]) -> io::Result<()> {
+        self.pages.append(&mut self.spool, shapes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.spool.shapes()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 impl KdTree {
-    fn scene_bounding_box(objects: &[Shape]) -> BoundingBox {
+    pub fn scene_bounding_box(objects: &[Shape]) -> BoundingBox {
         let mut min: Vector3<f64> = Vector3::new(f64::MAX, f64::MAX, f64::MAX);
         let mut max: Vector3<f64> = Vector3::new(f64::MIN, f64::MIN, f64::MIN);
 
@@ -62,15 +113,15 @@ impl KdTree {
         split_axis: usize,
         attempt: usize,
         depth: usize,
-    ) -> usize {
+    ) -> io::Result<usize> {
         // Average midpoint of objects
         let midpoint: f64 = Node::midpoint(&objects, split_axis);
 
         // Objects for each side: (lt_objects, gt_objects)
         let split_objects: (Vec<Shape>, Vec<Shape>) = Node::split(&objects, midpoint, split_axis);
 
-        if depth > self.max_depth {
-            // Artificial depth limit
+        if depth > self.max_depth || objects.len() <= self.leaf_size {
+            // Artificial depth and leaf size limits
             self.add_node(bounding_box, objects, split_axis, None, None)
         } else if split_objects.0.len() == objects.len() || split_objects.1.len() == objects.len() {
             // Base case: split has no worthwhile effect - one side still has all objects
@@ -89,6 +140,9 @@ impl KdTree {
                 depth + 1,
             )
         } else {
+            // Release this node's copy before building the children
+            drop(objects);
+
             // Split on longest axis
             let next_split_axis: usize = bounding_box.largest_axis();
 
@@ -106,7 +160,7 @@ impl KdTree {
                 next_split_axis,
                 1,
                 depth + 1,
-            );
+            )?;
 
             let gt_node_id: usize = self.new_node(
                 gt_bounding_box,
@@ -114,7 +168,7 @@ impl KdTree {
                 next_split_axis,
                 1,
                 depth + 1,
-            );
+            )?;
 
             self.add_node(
                 bounding_box,
@@ -133,30 +187,49 @@ impl KdTree {
         split_axis: usize,
         lt_node_id: Option<usize>,
         gt_node_id: Option<usize>,
-    ) -> usize {
+    ) -> io::Result<usize> {
         let node_id: usize = self.next_index;
 
-        self.nodes.push(Node::new(
+        let mut node: Node = Node::new(
             node_id,
             bounding_box,
             objects,
             split_axis,
             lt_node_id,
             gt_node_id,
-        ));
+        );
+
+        // Page leaf geometry out as soon as it's built so the whole tree is never resident
+        if let Some(ref mut pages) = self.pages {
+            if node.is_leaf() && !node.objects.is_empty() {
+                node.page = Some(pages.write(&node.objects)?);
+                node.objects = Vec::new();
+            }
+        }
+
+        self.nodes.push(node);
 
         self.next_index += 1;
 
-        node_id
+        Ok(node_id)
     }
 
     pub fn root_node(&self) -> &Node {
         &self.nodes[self.root_index]
     }
 
-    fn traverse(&self, ray: &Ray, node: &Node) -> Option<Intersection> {
+    fn traverse(&self, ray: &Ray, node: &Node, cost: &mut TraversalCost) -> Option<Intersection> {
+        cost.nodes += 1;
+
         if node.is_leaf() {
             // Leaf node - perform actual object intersection tests
+            if let (Some(page), Some(pages)) = (node.page, self.pages.as_ref()) {
+                let objects: Arc<Vec<Shape>> = pages.load(page)?;
+                cost.intersection_tests += objects.len();
+                return Intersection::closest_intersection(ray, &objects);
+            }
+
+            cost.intersection_tests += node.objects.len();
             return Intersection::closest_intersection(ray, &node.objects);
         }
 
@@ -168,8 +241,8 @@ impl KdTree {
         if lt_intersection && gt_node.bounding_box.intersect_test(ray) {
             // Intersects both sides
 
-            let lt_intersection: Option<Intersection> = self.traverse(ray, lt_node);
-            let gt_intersection: Option<Intersection> = self.traverse(ray, gt_node);
+            let lt_intersection: Option<Intersection> = self.traverse(ray, lt_node, cost);
+            let gt_intersection: Option<Intersection> = self.traverse(ray, gt_node, cost);
 
             if lt_intersection.is_none() {
                 // No object intersections on lt side
@@ -191,14 +264,19 @@ impl KdTree {
             }
         } else if lt_intersection {
             // LT Intersection only
-            self.traverse(ray, lt_node)
+            self.traverse(ray, lt_node, cost)
         } else {
             // GT Intersection only
-            self.traverse(ray, gt_node)
+            self.traverse(ray, gt_node, cost)
         }
     }
 
     pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        self.intersect_counted(ray, &mut TraversalCost::default())
+    }
+
+    // Intersect, adding the nodes visited and objects tested to cost
+    pub fn intersect_counted(&self, ray: &Ray, cost: &mut TraversalCost) -> Option<Intersection> {
         let root_node: &Node = &self.nodes[self.root_index];
 
         if !root_node.bounding_box.intersect_test(ray) {
@@ -206,10 +284,10 @@ impl KdTree {
             return None;
         }
 
-        self.traverse(ray, root_node)
+        self.traverse(ray, root_node, cost)
     }
 
-    pub fn new(objects: &[Shape], max_depth: usize) -> KdTree {
+    pub fn new(objects: &[Shape], max_depth: usize, leaf_size: usize) -> KdTree {
         // Copy objects - do not take ownership
         let mut objects = objects.to_vec();
 
@@ -218,10 +296,154 @@ impl KdTree {
             next_index: 0,
             root_index: 0,
             max_depth,
+            leaf_size,
+            pages: None,
         };
 
-        tree.root_index = tree.new_node(KdTree::scene_bounding_box(&objects), objects, 0, 1, 0);
+        // Only paged trees touch the disk
+        tree.root_index =
+            match tree.new_node(KdTree::scene_bounding_box(&objects), objects, 0, 1, 0) {
+                Ok(root_index) => root_index,
+                Err(_) => unreachable!("In memory trees can't fail to build"),
+            };
 
         tree
     }
+
+    // Build a tree whose leaves live in a temporary file, keeping roughly
+    // memory_limit bytes of them loaded at once
+    pub fn paged(
+        objects: Vec<Shape>,
+        max_depth: usize,
+        leaf_size: usize,
+        memory_limit: usize,
+    ) -> io::Result<KdTree> {
+        let mut spool: ShapeSpool = ShapeSpool::new(memory_limit)?;
+        spool.push(&objects)?;
+        drop(objects);
+
+        KdTree::from_spool(spool, max_depth, leaf_size)
+    }
+
+    // Build a paged tree from shapes on disk. Nodes are split by streaming their shapes into
+    // a spool for each side until they fit in the memory limit, then built as usual
+    pub fn from_spool(spool: ShapeSpool, max_depth: usize, leaf_size: usize) -> io::Result<KdTree> {
+        let ShapeSpool { pages, mut spool } = spool;
+
+        let mut min: Vector3<f64> = Vector3::new(f64::MAX, f64::MAX, f64::MAX);
+        let mut max: Vector3<f64> = Vector3::new(f64::MIN, f64::MIN, f64::MIN);
+        pages.read_spool(&mut spool, &mut |shapes| {
+            let bounding_box: BoundingBox = KdTree::scene_bounding_box(&shapes);
+            for i in 0..3 {
+                min[i] = min[i].min(bounding_box.min[i]);
+                max[i] = max[i].max(bounding_box.max[i]);
+            }
+            Ok(())
+        })?;
+
+        let mut tree = KdTree {
+            nodes: Vec::new(),
+            next_index: 0,
+            root_index: 0,
+            max_depth,
+            leaf_size,
+            pages: Some(pages),
+        };
+
+        tree.root_index = tree.new_spooled_node(BoundingBox::new(min, max), spool, 0, 1, 0)?;
+
+        Ok(tree)
+    }
+
+    // new_node for shapes still on disk
+    fn new_spooled_node(
+        &mut self,
+        bounding_box: BoundingBox,
+        mut spool: Spool,
+        split_axis: usize,
+        attempt: usize,
+        depth: usize,
+    ) -> io::Result<usize> {
+        let pages: &PageStore = self
+            .pages
+            .as_ref()
+            .ok_or_else(|| io::Error::other("Spooled trees are paged"))?;
+        let shapes: usize = spool.shapes();
+        let fits: bool = shapes * mem::size_of::<Shape>() <= pages.memory_limit();
+
+        if fits || depth > self.max_depth || shapes <= self.leaf_size || attempt > 3 {
+            let mut objects: Vec<Shape> = Vec::with_capacity(shapes);
+            pages.read_spool(&mut spool, &mut |chunk| {
+                objects.extend(chunk);
+                Ok(())
+            })?;
+            drop(spool);
+
+            return self.new_node(bounding_box, objects, split_axis, attempt, depth);
+        }
+
+        // Average midpoint of objects
+        let mut total: f64 = 0f64;
+        pages.read_spool(&mut spool, &mut |chunk| {
+            total += chunk.iter().map(|object| object.midpoint()[split_axis]).sum::<f64>();
+            Ok(())
+        })?;
+        let midpoint: f64 = total / shapes as f64;
+
+        // Objects for each side, some on both
+        let (lt_spool, gt_spool): (Spool, Spool) = pages.partition(&mut spool, &|object| {
+            Node::sides(object, midpoint, split_axis)
+        })?;
+
+        if lt_spool.shapes() == shapes || gt_spool.shapes() == shapes {
+            // Try again on a different axis
+            return self.new_spooled_node(
+                bounding_box,
+                spool,
+                (split_axis + 1) % 3,
+                attempt + 1,
+                depth + 1,
+            );
+        }
+        drop(spool);
+
+        // Split on longest axis
+        let next_split_axis: usize = bounding_box.largest_axis();
+
+        let mut lt_max: Vector3<f64> = bounding_box.max;
+        lt_max[split_axis] = midpoint;
+        let lt_bounding_box: BoundingBox = BoundingBox::new(bounding_box.min, lt_max);
+
+        let mut gt_min: Vector3<f64> = bounding_box.min;
+        gt_min[split_axis] = midpoint;
+        let gt_bounding_box: BoundingBox = BoundingBox::new(gt_min, bounding_box.max);
+
+        let lt_node_id: usize =
+            self.new_spooled_node(lt_bounding_box, lt_spool, next_split_axis, 1, depth + 1)?;
+        let gt_node_id: usize =
+            self.new_spooled_node(gt_bounding_box, gt_spool, next_split_axis, 1, depth + 1)?;
+
+        self.add_node(
+            bounding_box,
+            Vec::new(), // No need to save objects in non-leaf node
+            split_axis,
+            Some(lt_node_id),
+            Some(gt_node_id),
+        )
+    }
+
+    // Whether every leaf read during rendering could be loaded from disk
+    pub fn check_pages(&self) -> io::Result<()> {
+        self.pages.as_ref().map_or(Ok(()), |pages| pages.check())
+    }
+
+    // Whether a leaf couldn't be read from disk, so the render won't be usable
+    pub fn paging_failed(&self) -> bool {
+        self.pages.as_ref().is_some_and(|pages| pages.failed())
+    }
+
+    // Size of the paged geometry on disk, if any
+    pub fn paged_bytes(&self) -> Option<usize> {
+        self.pages.as_ref().map(|pages| pages.disk_bytes())
+    }
 }
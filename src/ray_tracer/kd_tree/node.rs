@@ -8,6 +8,8 @@ pub struct Node {
     pub objects: Vec<Shape>,
     pub bounding_box: BoundingBox,
     pub split_axis: usize,
+    // Where the objects of a paged out leaf are stored
+    pub page: Option<usize>,
 }
 
 impl Node {
@@ -25,11 +27,13 @@ impl Node {
         let mut gt_objects: Vec<Shape> = Vec::new();
 
         for shape in objects.iter() {
-            if shape.min_extent()[split_axis] <= midpoint {
+            let (lt, gt): (bool, bool) = Node::sides(shape, midpoint, split_axis);
+
+            if lt {
                 lt_objects.push(*shape);
             }
 
-            if shape.max_extent()[split_axis] > midpoint {
+            if gt {
                 gt_objects.push(*shape);
             }
         }
@@ -37,6 +41,14 @@ impl Node {
         (lt_objects, gt_objects)
     }
 
+    // Whether an object reaches the less than and greater than sides of the split
+    pub fn sides(shape: &Shape, midpoint: f64, split_axis: usize) -> (bool, bool) {
+        (
+            shape.min_extent()[split_axis] <= midpoint,
+            shape.max_extent()[split_axis] > midpoint,
+        )
+    }
+
     pub fn is_leaf(&self) -> bool {
         self.lt_node_id.is_none()
     }
@@ -56,6 +68,7 @@ impl Node {
             objects,
             bounding_box,
             split_axis,
+            page: None,
         }
     }
 }
@@ -0,0 +1,436 @@
+extern crate cgmath;
+extern crate rand;
+
+use self::cgmath::*;
+
+use std::convert::TryFrom;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ray_tracer::object::Shape;
+use ray_tracer::object::material::Material;
+use ray_tracer::object::sphere::Sphere;
+use ray_tracer::object::triangle::Triangle;
+
+// Bytes per shape on disk: kind (u16), material index (u16), object id (u32),
+// nine f32 coordinates and six f32 texture coordinates
+const RECORD_BYTES: usize = 68;
+
+const TRIANGLE: u16 = 0;
+const SPHERE: u16 = 1;
+const TEXTURED_TRIANGLE: u16 = 2;
+
+// Records read from a spool at a time
+const CHUNK_RECORDS: usize = 64 * 1024;
+
+// Names tried before giving up on creating a temporary file
+const MAX_FILE_ATTEMPTS: usize = 16;
+
+// A new file in the shared temporary directory. The name is random and the file must not
+// already exist, so another user can't have it opened through a link they placed there
+fn temporary_file(extension: &str) -> io::Result<(PathBuf, File)> {
+    for _ in 0..MAX_FILE_ATTEMPTS {
+        let path: PathBuf = env::temp_dir().join(format!(
+            "raytracer-{}-{:016x}.{}",
+            process::id(),
+            rand::random::<u64>(),
+            extension
+        ));
+
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "Unable to create a temporary geometry file",
+    ))
+}
+
+struct Page {
+    offset: u64,
+    shapes: usize,
+}
+
+// A leaf's shapes, resident or not
+struct Slot {
+    shapes: Mutex<Option<Arc<Vec<Shape>>>>,
+    referenced: AtomicBool,
+}
+
+struct PageFile {
+    file: File,
+    resident_bytes: usize,
+    // Clock hand for eviction
+    hand: usize,
+    // The first page that couldn't be read, reported once rendering finishes
+    error: Option<(io::ErrorKind, String)>,
+}
+
+// Shapes not yet sorted into leaves, in the page file's record format. Meshes are spooled
+// as they're read and the tree is split from here, so building holds only vertex lists and
+// nodes in memory
+pub struct Spool {
+    path: PathBuf,
+    file: File,
+    shapes: usize,
+}
+
+// K-D tree leaves stored on disk with single precision coordinates and a
+// shared material table, loaded on demand and evicted once the resident
+// shapes exceed the memory limit. Eviction uses the clock algorithm, an
+// approximation of least recently used that needs no lock on a hit. At most 65536
+// distinct materials fit the table.
+pub struct PageStore {
+    path: PathBuf,
+    page_file: Mutex<PageFile>,
+    pages: Vec<Page>,
+    slots: Vec<Slot>,
+    materials: Vec<Material>,
+    memory_limit: usize,
+    // Set with the page file's error, so renders can stop without taking its lock
+    failed: AtomicBool,
+}
+
+impl PageStore {
+    pub fn new(memory_limit: usize) -> io::Result<PageStore> {
+        let (path, file): (PathBuf, File) = temporary_file("pages")?;
+
+        Ok(PageStore {
+            path,
+            page_file: Mutex::new(PageFile {
+                file,
+                resident_bytes: 0,
+                hand: 0,
+                error: None,
+            }),
+            pages: Vec::new(),
+            slots: Vec::new(),
+            materials: Vec::new(),
+            memory_limit,
+            failed: AtomicBool::new(false),
+        })
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    fn material_index(&mut self, material: Material) -> io::Result<u16> {
+        let index: usize = match self.materials.iter().position(|known| *known == material) {
+            Some(index) => index,
+            None => {
+                self.materials.push(material);
+                self.materials.len() - 1
+            }
+        };
+
+        u16::try_from(index).map_err(|_| {
+            io::Error::other(format!(
+                "Paged geometry holds at most {} materials",
+                u32::from(u16::MAX) + 1
+            ))
+        })
+    }
+
+    fn encode(&mut self, shapes: &[Shape]) -> io::Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(shapes.len() * RECORD_BYTES);
+
+        for shape in shapes {
+            let mut coordinates: [f64; 15] = [0f64; 15];
+
+            let (kind, material): (u16, Material) = match *shape {
+                Shape::Triangle(triangle) => {
+                    for (i, vertex) in triangle.vertices().iter().enumerate() {
+                        coordinates[i * 3..i * 3 + 3].copy_from_slice(&[vertex.x, vertex.y, vertex.z]);
+                    }
+
+                    match triangle.texture_coordinates {
+                        Some(texture_coordinates) => {
+                            for (i, uv) in texture_coordinates.iter().enumerate() {
+                                coordinates[9 + i * 2..11 + i * 2].copy_from_slice(&[uv.x, uv.y]);
+                            }
+                            (TEXTURED_TRIANGLE, triangle.material)
+                        }
+                        None => (TRIANGLE, triangle.material),
+                    }
+                }
+                Shape::Sphere(sphere) => {
+                    let origin: Vector3<f64> = sphere.origin();
+                    coordinates[..4].copy_from_slice(&[origin.x, origin.y, origin.z, sphere.radius()]);
+                    (SPHERE, sphere.material)
+                }
+            };
+
+            let material_index: u16 = self.material_index(material)?;
+            let object_id: u32 = u32::try_from(shape.object_id())
+                .map_err(|_| io::Error::other("Too many objects for paged geometry"))?;
+            bytes.extend_from_slice(&kind.to_le_bytes());
+            bytes.extend_from_slice(&material_index.to_le_bytes());
+            bytes.extend_from_slice(&object_id.to_le_bytes());
+            for coordinate in &coordinates {
+                bytes.extend_from_slice(&(*coordinate as f32).to_le_bytes());
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    // Write a leaf's shapes to the end of the file, returning its page number
+    pub fn write(&mut self, shapes: &[Shape]) -> io::Result<usize> {
+        let bytes: Vec<u8> = self.encode(shapes)?;
+
+        let page_file: &mut PageFile = self.page_file.get_mut().unwrap();
+        let offset: u64 = page_file.file.seek(SeekFrom::End(0))?;
+        page_file.file.write_all(&bytes)?;
+
+        self.pages.push(Page {
+            offset,
+            shapes: shapes.len(),
+        });
+        self.slots.push(Slot {
+            shapes: Mutex::new(None),
+            referenced: AtomicBool::new(false),
+        });
+
+        Ok(self.pages.len() - 1)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Vec<Shape> {
+        bytes
+            .chunks_exact(RECORD_BYTES)
+            .map(|record| {
+                let kind: u16 = u16::from_le_bytes([record[0], record[1]]);
+                let material: Material =
+                    self.materials[u16::from_le_bytes([record[2], record[3]]) as usize];
+
+                let object_id: usize =
+                    u32::from_le_bytes([record[4], record[5], record[6], record[7]]) as usize;
+
+                let coordinates: Vec<f64> = record[8..]
+                    .chunks_exact(4)
+                    .map(|value| {
+                        f64::from(f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+                    })
+                    .collect();
+
+                let point = |i: usize| -> Vector3<f64> {
+                    Vector3::new(coordinates[i], coordinates[i + 1], coordinates[i + 2])
+                };
+
+                let uv = |i: usize| -> Vector2<f64> {
+                    Vector2::new(coordinates[i], coordinates[i + 1])
+                };
+
+                let shape: Shape = match kind {
+                    SPHERE => Shape::Sphere(Sphere::new(point(0), coordinates[3], material)),
+                    TEXTURED_TRIANGLE => Shape::Triangle(
+                        Triangle::new(point(0), point(3), point(6), material)
+                            .with_texture_coordinates([uv(9), uv(11), uv(13)]),
+                    ),
+                    _ => Shape::Triangle(Triangle::new(point(0), point(3), point(6), material)),
+                };
+
+                shape.with_object_id(object_id)
+            })
+            .collect()
+    }
+
+    fn page_bytes(&self, page: usize) -> usize {
+        self.pages[page].shapes * mem::size_of::<Shape>()
+    }
+
+    // Drop unreferenced pages until the resident shapes fit the limit
+    fn evict(&self, page_file: &mut PageFile, keep: usize) {
+        let mut sweeps: usize = 0;
+
+        while page_file.resident_bytes > self.memory_limit && sweeps < 2 * self.slots.len() {
+            let page: usize = page_file.hand;
+            page_file.hand = (page_file.hand + 1) % self.slots.len();
+            sweeps += 1;
+
+            let slot: &Slot = &self.slots[page];
+            if page == keep || slot.referenced.swap(false, Ordering::Relaxed) {
+                continue;
+            }
+
+            if slot.shapes.lock().unwrap().take().is_some() {
+                page_file.resident_bytes -= self.page_bytes(page);
+            }
+        }
+    }
+
+    // Shapes for a page, reading them from disk if they were evicted - None if the page
+    // couldn't be read, which check then reports
+    pub fn load(&self, page: usize) -> Option<Arc<Vec<Shape>>> {
+        let slot: &Slot = &self.slots[page];
+        slot.referenced.store(true, Ordering::Relaxed);
+
+        if let Some(ref shapes) = *slot.shapes.lock().unwrap() {
+            return Some(Arc::clone(shapes));
+        }
+
+        let mut page_file = self.page_file.lock().unwrap();
+
+        // Another thread may have loaded it while this one waited
+        if let Some(ref shapes) = *slot.shapes.lock().unwrap() {
+            return Some(Arc::clone(shapes));
+        }
+
+        let mut bytes: Vec<u8> = vec![0u8; self.pages[page].shapes * RECORD_BYTES];
+        let read: io::Result<()> = page_file
+            .file
+            .seek(SeekFrom::Start(self.pages[page].offset))
+            .and_then(|_| page_file.file.read_exact(&mut bytes));
+        if let Err(error) = read {
+            if page_file.error.is_none() {
+                page_file.error =
+                    Some((error.kind(), format!("Geometry page {}: {}", page, error)));
+            }
+            self.failed.store(true, Ordering::Relaxed);
+            return None;
+        }
+
+        let shapes: Arc<Vec<Shape>> = Arc::new(self.decode(&bytes));
+        *slot.shapes.lock().unwrap() = Some(Arc::clone(&shapes));
+        page_file.resident_bytes += self.page_bytes(page);
+
+        self.evict(&mut page_file, page);
+
+        Some(shapes)
+    }
+
+    // Whether a page couldn't be read - rendering stops early, and check reports why
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    // Whether every page loaded so far could be read
+    pub fn check(&self) -> io::Result<()> {
+        match self.page_file.lock().unwrap().error {
+            Some((kind, ref message)) => Err(io::Error::new(kind, message.clone())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn spool(&self) -> io::Result<Spool> {
+        let (path, file): (PathBuf, File) = temporary_file("spool")?;
+
+        Ok(Spool {
+            path,
+            file,
+            shapes: 0,
+        })
+    }
+
+    // Add shapes to the end of a spool
+    pub fn append(&mut self, spool: &mut Spool, shapes: &[Shape]) -> io::Result<()> {
+        let bytes: Vec<u8> = self.encode(shapes)?;
+        spool.append_records(&bytes)
+    }
+
+    // A spool's records, handed to sink a chunk at a time
+    fn read_records(
+        spool: &mut Spool,
+        sink: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        spool.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&spool.file);
+
+        let mut remaining: usize = spool.shapes;
+        while remaining > 0 {
+            let records: usize = remaining.min(CHUNK_RECORDS);
+            let mut bytes: Vec<u8> = vec![0u8; records * RECORD_BYTES];
+            reader.read_exact(&mut bytes)?;
+            sink(&bytes)?;
+            remaining -= records;
+        }
+
+        Ok(())
+    }
+
+    // A spool's shapes, handed to sink a chunk at a time
+    pub fn read_spool(
+        &self,
+        spool: &mut Spool,
+        sink: &mut dyn FnMut(Vec<Shape>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        PageStore::read_records(spool, &mut |bytes| sink(self.decode(bytes)))
+    }
+
+    // Copy each shape in a spool to one or both of two new spools, by which sides of a
+    // split it reaches
+    pub fn partition(
+        &self,
+        spool: &mut Spool,
+        sides: &dyn Fn(&Shape) -> (bool, bool),
+    ) -> io::Result<(Spool, Spool)> {
+        let mut lt_spool: Spool = self.spool()?;
+        let mut gt_spool: Spool = self.spool()?;
+
+        PageStore::read_records(spool, &mut |bytes| {
+            let mut lt_bytes: Vec<u8> = Vec::new();
+            let mut gt_bytes: Vec<u8> = Vec::new();
+
+            for (record, shape) in bytes.chunks_exact(RECORD_BYTES).zip(self.decode(bytes)) {
+                let (lt, gt): (bool, bool) = sides(&shape);
+                if lt {
+                    lt_bytes.extend_from_slice(record);
+                }
+                if gt {
+                    gt_bytes.extend_from_slice(record);
+                }
+            }
+
+            lt_spool.append_records(&lt_bytes)?;
+            gt_spool.append_records(&gt_bytes)
+        })?;
+
+        Ok((lt_spool, gt_spool))
+    }
+
+    // Total size of the page file
+    pub fn disk_bytes(&self) -> usize {
+        self.pages.iter().map(|page| page.shapes * RECORD_BYTES).sum()
+    }
+}
+
+impl Spool {
+    pub fn shapes(&self) -> usize {
+        self.shapes
+    }
+
+    fn append_records(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(bytes)?;
+        self.shapes += bytes.len() / RECORD_BYTES;
+
+        Ok(())
+    }
+}
+
+impl Drop for PageStore {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
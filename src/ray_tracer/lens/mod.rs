@@ -0,0 +1,230 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use super::math;
+
+// Brown-Conrady distortion of normalized image coordinates (x / z, y / z with y down),
+// with the same coefficients as OpenCV and most camera calibration tools
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LensDistortion {
+    // Radial - negative for barrel, positive for pincushion
+    #[serde(default)]
+    pub k1: f64,
+    #[serde(default)]
+    pub k2: f64,
+    #[serde(default)]
+    pub k3: f64,
+    // Tangential - from a lens not quite parallel to the sensor
+    #[serde(default)]
+    pub p1: f64,
+    #[serde(default)]
+    pub p2: f64,
+}
+
+impl LensDistortion {
+    // Where an undistorted point appears in the footage
+    pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let r2: f64 = x * x + y * y;
+        let radial: f64 = 1f64 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+
+        (
+            x * radial + 2f64 * self.p1 * x * y + self.p2 * (r2 + 2f64 * x * x),
+            y * radial + self.p1 * (r2 + 2f64 * y * y) + 2f64 * self.p2 * x * y,
+        )
+    }
+
+    // The undistorted point that appears at x, y - fixed point iteration, as OpenCV's
+    // undistortPoints, which converges for the distortion of real lenses
+    pub fn undistort(&self, x: f64, y: f64) -> (f64, f64) {
+        let (mut ux, mut uy): (f64, f64) = (x, y);
+
+        for _ in 0..20 {
+            let r2: f64 = ux * ux + uy * uy;
+            let radial: f64 = 1f64 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+            let dx: f64 = 2f64 * self.p1 * ux * uy + self.p2 * (r2 + 2f64 * ux * ux);
+            let dy: f64 = self.p1 * (r2 + 2f64 * uy * uy) + 2f64 * self.p2 * ux * uy;
+
+            ux = (x - dx) / radial;
+            uy = (y - dy) / radial;
+        }
+
+        (ux, uy)
+    }
+}
+
+// One surface of a lens prescription, listed from the scene toward the sensor.
+// Millimetres, with a positive radius curving away from the scene
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LensElement {
+    // 0 for a flat surface, e.g. the aperture stop
+    pub radius: f64,
+    // Distance to the next surface, or for the last, to the sensor when focused at infinity
+    pub thickness: f64,
+    // Of the glass between this surface and the next, 1 for air
+    #[serde(default = "air")]
+    pub ior: f64,
+    // Diameter
+    pub aperture: f64,
+}
+
+fn air() -> f64 {
+    1f64
+}
+
+// Rays traced from the sensor out through every element, so renders get the lens'
+// own field of view, distortion, focus falloff and bokeh. Needs a physical camera with
+// unit_length, whose sensor frames the image. Samples the lens blocks are retried a few
+// times and then left black, so its vignetting darkens the corners
+#[derive(Clone, Debug)]
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+    // Distance of each surface's vertex from the sensor, along the axis toward the scene
+    vertices: Vec<f64>,
+    sensor_width: f64,
+    sensor_height: f64,
+    // Millimetres per scene unit
+    unit_length: f64,
+}
+
+// A ray in the lens' space - millimetres, sensor at z = 0 and the scene toward +z
+type LensRay = (Vector3<f64>, Vector3<f64>);
+
+impl LensSystem {
+    // Focused on focal_distance scene units in front of the first element
+    pub fn new(
+        elements: &[LensElement],
+        sensor_width: f64,
+        sensor_height: f64,
+        unit_length: f64,
+        focal_distance: f64,
+    ) -> LensSystem {
+        let mut lens_system: LensSystem = LensSystem {
+            elements: elements.to_vec(),
+            vertices: Vec::new(),
+            sensor_width,
+            sensor_height,
+            unit_length,
+        };
+        lens_system.position(0f64);
+
+        // Moving the whole lens moves the image with it, so bring the image of
+        // the focal distance onto the sensor
+        let image: Option<f64> = lens_system.image_distance(focal_distance * unit_length);
+        lens_system.position(-image.unwrap_or(0f64));
+
+        lens_system
+    }
+
+    fn position(&mut self, shift: f64) {
+        let mut z: f64 = shift;
+        self.vertices = self.elements
+            .iter()
+            .rev()
+            .map(|element| {
+                z += element.thickness;
+                z
+            })
+            .collect();
+        self.vertices.reverse();
+    }
+
+    // Where a ray from a point on the axis, distance in front of the first element,
+    // crosses the axis again behind the lens - paraxial, so close to the axis
+    fn image_distance(&self, distance: f64) -> Option<f64> {
+        let front: f64 = self.vertices[0];
+        let height: f64 = self.elements[0].aperture * 0.01;
+        let origin: Vector3<f64> = Vector3::new(0f64, 0f64, front + distance);
+        let direction: Vector3<f64> = (Vector3::new(height, 0f64, front) - origin).normalize();
+
+        let (origin, direction): LensRay = self.trace_to_sensor((origin, direction))?;
+        if direction.x * origin.x >= 0f64 {
+            // Diverging - no real image
+            return None;
+        }
+
+        Some(origin.z - origin.x / direction.x * direction.z)
+    }
+
+    // The ray leaving the front element for the normalized image point x, y (-0.5 to 0.5,
+    // y down) through pupil, a point on the unit disk mapped over the rear element.
+    // Camera space scene units: x right, y down, z forward, from the front element's vertex
+    pub fn ray(&self, x: f64, y: f64, pupil: (f64, f64)) -> Option<LensRay> {
+        let rear: usize = self.elements.len() - 1;
+        let radius: f64 = self.elements[rear].aperture / 2f64;
+
+        // The lens flips the image, so the top left pixel sees from the bottom right
+        let origin: Vector3<f64> =
+            Vector3::new(-x * self.sensor_width, -y * self.sensor_height, 0f64);
+        let target: Vector3<f64> =
+            Vector3::new(pupil.0 * radius, pupil.1 * radius, self.vertices[rear]);
+
+        let (origin, direction): LensRay =
+            self.trace_to_scene((origin, (target - origin).normalize()))?;
+        let front: Vector3<f64> = Vector3::new(0f64, 0f64, self.vertices[0]);
+
+        Some(((origin - front) / self.unit_length, direction))
+    }
+
+    fn trace_to_scene(&self, ray: LensRay) -> Option<LensRay> {
+        (0..self.elements.len()).rev().try_fold(ray, |ray, i| {
+            let outside: f64 = if i == 0 { 1f64 } else { self.elements[i - 1].ior };
+            self.refract(i, ray, self.elements[i].ior, outside)
+        })
+    }
+
+    fn trace_to_sensor(&self, ray: LensRay) -> Option<LensRay> {
+        (0..self.elements.len()).try_fold(ray, |ray, i| {
+            let outside: f64 = if i == 0 { 1f64 } else { self.elements[i - 1].ior };
+            self.refract(i, ray, outside, self.elements[i].ior)
+        })
+    }
+
+    // Cross surface i from a medium of index n1 into n2, or None if the ray misses
+    // the element, is blocked by the stop or reflects internally
+    fn refract(&self, i: usize, ray: LensRay, n1: f64, n2: f64) -> Option<LensRay> {
+        let (origin, direction): LensRay = ray;
+        let element: LensElement = self.elements[i];
+        let vertex: f64 = self.vertices[i];
+
+        let (point, normal): (Vector3<f64>, Vector3<f64>) = if element.radius == 0f64 {
+            let t: f64 = (vertex - origin.z) / direction.z;
+            (origin + direction * t, Vector3::new(0f64, 0f64, 1f64))
+        } else {
+            // The cap of the sphere on the vertex' side of its center
+            let center: Vector3<f64> = Vector3::new(0f64, 0f64, vertex - element.radius);
+            let offset: Vector3<f64> = origin - center;
+            let b: f64 = offset.dot(direction);
+            let c: f64 = offset.magnitude2() - element.radius * element.radius;
+            let discriminant: f64 = b * b - c;
+            if discriminant < 0f64 {
+                return None;
+            }
+
+            let root: f64 = discriminant.sqrt();
+            let t: f64 = [-b - root, -b + root].iter().cloned().find(|&t| {
+                t > 0f64 && (origin.z + direction.z * t - center.z) * element.radius > 0f64
+            })?;
+            let point: Vector3<f64> = origin + direction * t;
+
+            (point, (point - center).normalize())
+        };
+
+        let half_aperture: f64 = element.aperture / 2f64;
+        if point.x * point.x + point.y * point.y > half_aperture * half_aperture {
+            return None;
+        }
+
+        if n1 == n2 {
+            return Some((point, direction));
+        }
+
+        let facing: Vector3<f64> = if normal.dot(direction) > 0f64 {
+            -normal
+        } else {
+            normal
+        };
+
+        math::refract(direction, facing, n1 / n2).map(|refracted| (point, refracted))
+    }
+}
@@ -0,0 +1,78 @@
+use std::io;
+use std::sync::Arc;
+
+use super::super::color::Radiance;
+#[cfg(not(target_arch = "wasm32"))]
+use super::super::color::DisplayColor;
+
+// Pattern projected by a spotlight, sampled at light-space u, v from 0 to 1
+#[derive(Clone)]
+pub enum Gobo {
+    // Row-major linear colors, top row first
+    Image {
+        width: usize,
+        height: usize,
+        pixels: Arc<Vec<Radiance>>,
+    },
+    // Blend from one color to another across the beam, at angle radians from the u axis
+    Gradient {
+        from: Radiance,
+        to: Radiance,
+        angle: f64,
+    },
+}
+
+impl Gobo {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(filename: &str) -> io::Result<Gobo> {
+        let image = ::image::open(filename)
+            .map_err(|error| io::Error::other(format!("{}: {}", filename, error)))?
+            .to_rgba();
+
+        let pixels: Vec<Radiance> = image
+            .pixels()
+            .map(|pixel| Radiance::from_display(DisplayColor::new(pixel[0], pixel[1], pixel[2])))
+            .collect();
+
+        Ok(Gobo::Image {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: Arc::new(pixels),
+        })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn load(filename: &str) -> io::Result<Gobo> {
+        Err(io::Error::other(format!("{}: image gobos are not supported on wasm", filename)))
+    }
+
+    pub fn sample(&self, u: f64, v: f64) -> Radiance {
+        match *self {
+            Gobo::Image {
+                width,
+                height,
+                ref pixels,
+            } => {
+                // Bilinear filtering between pixel centers, v up
+                let x: f64 = (u * width as f64 - 0.5).clamp(0f64, (width - 1) as f64);
+                let y: f64 = ((1f64 - v) * height as f64 - 0.5).clamp(0f64, (height - 1) as f64);
+                let (x0, y0): (usize, usize) = (x.floor() as usize, y.floor() as usize);
+                let (x1, y1): (usize, usize) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+                let (tx, ty): (f64, f64) = (x - x0 as f64, y - y0 as f64);
+
+                let pixel = |x: usize, y: usize| -> Radiance { pixels[y * width + x] };
+                let top: Radiance = pixel(x0, y0) * (1f64 - tx) + pixel(x1, y0) * tx;
+                let bottom: Radiance = pixel(x0, y1) * (1f64 - tx) + pixel(x1, y1) * tx;
+
+                top * (1f64 - ty) + bottom * ty
+            }
+            Gobo::Gradient { from, to, angle } => {
+                // Position along the gradient direction, 0 - 1 across the beam
+                let t: f64 = ((u - 0.5) * angle.cos() + (v - 0.5) * angle.sin() + 0.5)
+                    .clamp(0f64, 1f64);
+
+                from * (1f64 - t) + to * t
+            }
+        }
+    }
+}
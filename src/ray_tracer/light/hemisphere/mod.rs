@@ -0,0 +1,49 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use super::super::color::{DisplayColor, Radiance};
+
+// Fill light from a sky above and the ground below, replacing each light's flat ambient
+// term - surfaces facing up take the sky color, facing down the ground color, and those
+// in between a blend, so unlit sides still show their shape
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HemisphereLight {
+    pub sky_color: Vec<u8>,
+    pub ground_color: Vec<u8>,
+    #[serde(default = "default_up")]
+    pub up: Vec<f64>,
+    #[serde(default = "default_intensity")]
+    pub intensity: f64,
+}
+
+fn default_up() -> Vec<f64> {
+    vec![0f64, 1f64, 0f64]
+}
+
+fn default_intensity() -> f64 {
+    1f64
+}
+
+impl HemisphereLight {
+    // Light arriving at a surface facing normal
+    pub fn irradiance(&self, normal: Vector3<f64>) -> Radiance {
+        let up: Vector3<f64> = Vector3::new(self.up[0], self.up[1], self.up[2]).normalize();
+        let sky_share: f64 = (normal.dot(up) + 1f64) / 2f64;
+
+        self.blend(sky_share)
+    }
+
+    // Light arriving from every direction at once, e.g. inside a volume
+    pub fn average(&self) -> Radiance {
+        self.blend(0.5)
+    }
+
+    fn blend(&self, sky_share: f64) -> Radiance {
+        let sky: Radiance = Radiance::from_display(DisplayColor::from_slice(&self.sky_color));
+        let ground: Radiance =
+            Radiance::from_display(DisplayColor::from_slice(&self.ground_color));
+
+        (sky * sky_share + ground * (1f64 - sky_share)) * self.intensity
+    }
+}
@@ -1,22 +1,152 @@
 extern crate cgmath;
-extern crate image;
 
 use self::cgmath::*;
 
-use super::color::Color;
+pub mod gobo;
+pub mod hemisphere;
 
+use self::gobo::Gobo;
+use super::color::Radiance;
+use super::math::OrthonormalBasis;
+use super::units::Units;
+
+#[derive(Clone)]
 pub struct Light {
     pub origin: Vector3<f64>,
     pub intensity: f64,
-    pub color: Color,
+    pub color: Radiance,
+    pub shadow: ShadowStyle,
+    // Restricts the light to a cone - None shines in every direction
+    pub spot: Option<Spot>,
+    // Meters per scene unit, for inverse square falloff - None lights every distance equally
+    pub falloff: Option<f64>,
+}
+
+// Artistic shadow controls - the default is a hard, fully dark shadow
+#[derive(Clone, Copy)]
+pub struct ShadowStyle {
+    // Radius of the sphere shadow rays aim into, softening the shadow's edge
+    pub softness: f64,
+    // Filters the light that reaches shadowed points, black blocks it entirely
+    pub color: Radiance,
+    // How much of the light is blocked, from 0 (no shadow) to 1
+    pub intensity: f64,
+}
+
+impl Default for ShadowStyle {
+    fn default() -> ShadowStyle {
+        ShadowStyle {
+            softness: 0f64,
+            color: Radiance::black(),
+            intensity: 1f64,
+        }
+    }
+}
+
+// Spotlight cone, optionally projecting a gobo. Ambient light isn't shaped by the cone
+#[derive(Clone)]
+pub struct Spot {
+    basis: OrthonormalBasis,
+    // Tangent of half the cone angle
+    spread: f64,
+    // Fraction of the cone's radius over which the edge fades out
+    pub penumbra: f64,
+    pub gobo: Option<Gobo>,
+}
+
+impl Spot {
+    // cone_angle is the full angle in radians
+    pub fn new(direction: Vector3<f64>, cone_angle: f64, penumbra: f64, gobo: Option<Gobo>) -> Spot {
+        Spot {
+            basis: OrthonormalBasis::from_normal(direction),
+            spread: (cone_angle / 2f64).tan(),
+            penumbra: penumbra.clamp(0f64, 1f64),
+            gobo,
+        }
+    }
+
+    pub fn direction(&self) -> Vector3<f64> {
+        self.basis.w
+    }
+
+    pub fn cone_angle(&self) -> f64 {
+        2f64 * self.spread.atan()
+    }
+
+    // Light-space u, v of a point relative to the light, from 0 to 1 across
+    // the square bounding the cone - None behind the light
+    pub fn uv(&self, offset: Vector3<f64>) -> Option<Vector2<f64>> {
+        let local: Vector3<f64> = self.basis.world_to_local(offset);
+        if local.z <= 0f64 {
+            return None;
+        }
+
+        let scale: f64 = 2f64 * local.z * self.spread;
+        Some(Vector2::new(local.x / scale + 0.5, local.y / scale + 0.5))
+    }
+
+    // Color filter for light travelling from the spot towards offset
+    pub fn filter(&self, offset: Vector3<f64>) -> Radiance {
+        let uv: Vector2<f64> = match self.uv(offset) {
+            Some(uv) => uv,
+            None => return Radiance::black(),
+        };
+
+        // 0 at the center of the cone, 1 at its edge
+        let radius: f64 = ((uv - Vector2::new(0.5, 0.5)) * 2f64).magnitude();
+        let edge: f64 = if self.penumbra > 0f64 {
+            ((1f64 - radius) / self.penumbra).clamp(0f64, 1f64)
+        } else if radius <= 1f64 {
+            1f64
+        } else {
+            0f64
+        };
+
+        match self.gobo {
+            Some(ref gobo) if edge > 0f64 => gobo.sample(uv.x, uv.y) * edge,
+            _ => Radiance::new(edge, edge, edge),
+        }
+    }
 }
 
 impl Light {
-    pub fn new(origin: Vector3<f64>, intensity: f64, color: Color) -> Light {
+    pub fn new(origin: Vector3<f64>, intensity: f64, color: Radiance) -> Light {
         Light {
             origin,
             intensity,
             color,
+            shadow: ShadowStyle::default(),
+            spot: None,
+            falloff: None,
+        }
+    }
+
+    pub fn with_shadow(mut self, shadow: ShadowStyle) -> Light {
+        self.shadow = shadow;
+        self
+    }
+
+    pub fn with_spot(mut self, spot: Spot) -> Light {
+        self.spot = Some(spot);
+        self
+    }
+
+    // Intensity becomes the light 1 meter away, in a scene measured in units
+    pub fn with_falloff(mut self, units: Units) -> Light {
+        self.falloff = Some(units.meters());
+        self
+    }
+
+    // Color of the light arriving at a point, before shadows
+    pub fn illumination(&self, point: Vector3<f64>) -> Radiance {
+        let mut light: Radiance = self.color * self.intensity;
+        if let Some(meters) = self.falloff {
+            light = light / ((point - self.origin).magnitude2() * meters * meters);
+        }
+
+        match self.spot {
+            Some(ref spot) => light * spot.filter(point - self.origin),
+            None => light,
         }
     }
 }
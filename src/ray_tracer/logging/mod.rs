@@ -0,0 +1,93 @@
+extern crate serde_json;
+
+use std::io::{self, Write};
+use std::time::Instant;
+
+use log::{self, LevelFilter, Log, Metadata, Record};
+
+// Writes records to stderr, as text or as one JSON object per line for log collectors
+struct StderrLogger {
+    level: LevelFilter,
+    json: bool,
+    start: Instant,
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    // Seconds since logging started
+    time: f64,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let time: f64 = self.start.elapsed().as_secs_f64();
+        let line: String = if self.json {
+            serde_json::to_string(&JsonRecord {
+                time,
+                level: record.level().as_str(),
+                target: record.target(),
+                message: record.args().to_string(),
+            }).unwrap()
+        } else {
+            format!("{:>9.3}s {:<5} {}", time, record.level(), record.args())
+        };
+
+        let _ = writeln!(io::stderr(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+// Log to stderr from now on - info and above at verbosity 0, debug (shape counts and stage
+// starts) at 1, everything (render progress) at 2. Library users install their own logger
+pub fn init(verbosity: u8, json: bool) {
+    let level: LevelFilter = match verbosity {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    let logger: StderrLogger = StderrLogger {
+        level,
+        json,
+        start: Instant::now(),
+    };
+
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+// A step of loading or rendering - logs when it starts and, once dropped, how long it took
+pub struct Stage {
+    name: &'static str,
+    start: Instant,
+}
+
+pub fn stage(name: &'static str) -> Stage {
+    debug!("{} started", name);
+
+    Stage {
+        name,
+        start: Instant::now(),
+    }
+}
+
+impl Drop for Stage {
+    fn drop(&mut self) {
+        info!("{} took {:.3}s", self.name, self.start.elapsed().as_secs_f64());
+    }
+}
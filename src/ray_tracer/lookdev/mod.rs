@@ -0,0 +1,103 @@
+extern crate serde_json;
+
+use std::io;
+
+use super::scene::configuration::Configuration;
+use super::scene::configuration::object_definition::ObjectDefinition;
+
+// The same stage for every material, so previews compare with each other - a ball and a
+// smaller sphere on a gray floor before a backdrop. There are no environment maps, so
+// rather than an HDRI it is lit by a soft key, a rim light and a sky and ground fill
+const STAGE: &str = r#"{
+    "threads": 0,
+    "samples": 4,
+    "use_kd_tree": true,
+    "max_kd_tree_depth": 20,
+    "width": 400,
+    "height": 400,
+    "camera_position": [0.0, 1.4, 4.5],
+    "camera_target": [0.2, 0.7, 0.0],
+    "camera_up": [0.0, 1.0, 0.0],
+    "viewport_distance": 1.0,
+    "viewport_width": 0.9,
+    "max_reflections": 5,
+    "reinhard_key_value": 1.5,
+    "reinhard_delta": 0.01,
+    "hemisphere_light": {
+        "sky_color": [170, 190, 220],
+        "ground_color": [100, 92, 84]
+    },
+    "lights": [
+        {
+            "position": [-3.0, 5.0, 4.0],
+            "intensity": 3,
+            "color": [255, 250, 240],
+            "shadow_softness": 0.6
+        },
+        {
+            "position": [4.0, 3.0, -3.0],
+            "intensity": 2,
+            "color": [220, 230, 255],
+            "shadow_softness": 0.6
+        }
+    ],
+    "objects": [
+        {
+            "name": "lookdev floor",
+            "contents": "v -10 0 -4\nv -10 0 10\nv 10 0 10\nv 10 0 -4\nf 1 2 3 4",
+            "color": [90, 90, 90],
+            "reflectance": 0.0,
+            "ambient_coefficient": 0.2,
+            "specular_coefficient": 0.1,
+            "specular_exponent": 10
+        },
+        {
+            "name": "lookdev backdrop",
+            "contents": "v -10 0 -4\nv 10 0 -4\nv 10 10 -4\nv -10 10 -4\nf 1 2 3 4",
+            "color": [70, 75, 85],
+            "reflectance": 0.0,
+            "ambient_coefficient": 0.2,
+            "specular_coefficient": 0.1,
+            "specular_exponent": 10
+        }
+    ]
+}"#;
+
+// Statements for the shapes wearing the material
+const BALLS: &str = "sphere 0 0.8 0 0.8\nsphere 1.25 0.3 0.5 0.3";
+
+// A configuration rendering the material of the object named object_name on the stage
+pub fn preview_configuration(
+    configuration: &Configuration,
+    object_name: &str,
+) -> io::Result<Configuration> {
+    let object: &ObjectDefinition = configuration
+        .find_object(object_name)
+        .map(|index| &configuration.objects[index])
+        .ok_or_else(|| {
+            io::Error::other(format!(
+                "No object named {} - objects are: {}",
+                object_name,
+                configuration.object_names().join(", ")
+            ))
+        })?;
+
+    let mut preview: Configuration = serde_json::from_str(STAGE)?;
+    preview.threads = configuration.threads;
+    preview.low_priority = configuration.low_priority;
+
+    // Keep the material, volume settings included, and swap the geometry for the balls
+    let mut balls: ObjectDefinition = object.clone();
+    balls.name = Some(String::from(object_name));
+    balls.filename = String::new();
+    balls.contents = Some(String::from(BALLS));
+    balls.generator = None;
+    balls.units = None;
+    balls.detail_levels = Vec::new();
+    balls.visible_to_camera = true;
+    balls.visible_in_reflections = true;
+    balls.casts_shadows = true;
+    preview.objects.push(balls);
+
+    Ok(preview)
+}
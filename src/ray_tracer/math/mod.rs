@@ -0,0 +1,120 @@
+extern crate cgmath;
+extern crate rand;
+
+use self::cgmath::*;
+use self::rand::{Rng, XorShiftRng};
+
+use std::f64;
+use std::f64::consts::PI;
+
+// Orthonormal basis with w aligned to a given normal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrthonormalBasis {
+    pub u: Vector3<f64>,
+    pub v: Vector3<f64>,
+    pub w: Vector3<f64>,
+}
+
+impl OrthonormalBasis {
+    // Branchless construction from Duff et al., "Building an Orthonormal Basis, Revisited"
+    pub fn from_normal(normal: Vector3<f64>) -> OrthonormalBasis {
+        let w: Vector3<f64> = normal.normalize();
+        let sign: f64 = 1f64.copysign(w.z);
+        let a: f64 = -1f64 / (sign + w.z);
+        let b: f64 = w.x * w.y * a;
+
+        OrthonormalBasis {
+            u: Vector3::new(1f64 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vector3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        }
+    }
+
+    // Local (u, v, w) coordinates to world space
+    pub fn local_to_world(&self, local: Vector3<f64>) -> Vector3<f64> {
+        self.u * local.x + self.v * local.y + self.w * local.z
+    }
+
+    // World space to local (u, v, w) coordinates
+    pub fn world_to_local(&self, world: Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(world.dot(self.u), world.dot(self.v), world.dot(self.w))
+    }
+}
+
+// Fast generator for sampling
+// wasm has no OS entropy source, so it always starts from the same fixed seed
+#[cfg(not(target_arch = "wasm32"))]
+pub fn new_rng() -> XorShiftRng {
+    rand::weak_rng()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn new_rng() -> XorShiftRng {
+    seeded_rng()
+}
+
+// Same sequence on every run - for reproducible renders
+pub fn seeded_rng() -> XorShiftRng {
+    XorShiftRng::new_unseeded()
+}
+
+// Concentric mapping of the unit square onto the unit disk (Shirley & Chiu)
+pub fn uniform_disk<R: Rng>(rng: &mut R) -> (f64, f64) {
+    let sx: f64 = 2f64 * rng.gen::<f64>() - 1f64;
+    let sy: f64 = 2f64 * rng.gen::<f64>() - 1f64;
+
+    if sx == 0f64 && sy == 0f64 {
+        return (0f64, 0f64);
+    }
+
+    let (radius, theta): (f64, f64) = if sx.abs() > sy.abs() {
+        (sx, (PI / 4f64) * (sy / sx))
+    } else {
+        (sy, (PI / 2f64) - (PI / 4f64) * (sx / sy))
+    };
+
+    (radius * theta.cos(), radius * theta.sin())
+}
+
+// Uniformly distributed direction on the unit sphere
+pub fn uniform_sphere<R: Rng>(rng: &mut R) -> Vector3<f64> {
+    let z: f64 = 1f64 - 2f64 * rng.gen::<f64>();
+    let r: f64 = f64::max(0f64, 1f64 - z * z).sqrt();
+    let phi: f64 = 2f64 * PI * rng.gen::<f64>();
+
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Cosine-weighted direction on the +z hemisphere - pdf is cos(theta) / PI
+pub fn cosine_weighted_hemisphere<R: Rng>(rng: &mut R) -> Vector3<f64> {
+    let (x, y): (f64, f64) = uniform_disk(rng);
+    let z: f64 = f64::max(0f64, 1f64 - x * x - y * y).sqrt();
+
+    Vector3::new(x, y, z)
+}
+
+// Cosine-weighted direction on the hemisphere around the given normal
+pub fn cosine_weighted_direction<R: Rng>(rng: &mut R, normal: Vector3<f64>) -> Vector3<f64> {
+    OrthonormalBasis::from_normal(normal).local_to_world(cosine_weighted_hemisphere(rng))
+}
+
+// Mirror the direction about the normal
+pub fn reflect(direction: Vector3<f64>, normal: Vector3<f64>) -> Vector3<f64> {
+    direction - 2f64 * normal * direction.dot(normal)
+}
+
+// Refract the direction through a surface with relative index of refraction eta (n1 / n2)
+// The normal must face against the incoming direction
+// Returns None on total internal reflection
+pub fn refract(direction: Vector3<f64>, normal: Vector3<f64>, eta: f64) -> Option<Vector3<f64>> {
+    let cos_i: f64 = -direction.dot(normal);
+    let sin2_t: f64 = eta * eta * f64::max(0f64, 1f64 - cos_i * cos_i);
+
+    if sin2_t > 1f64 {
+        return None;
+    }
+
+    let cos_t: f64 = (1f64 - sin2_t).sqrt();
+
+    Some(direction * eta + normal * (eta * cos_i - cos_t))
+}
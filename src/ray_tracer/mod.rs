@@ -1,41 +1,154 @@
-mod camera;
-mod color;
-mod intersection;
-mod kd_tree;
-mod light;
-mod object;
-mod ray;
+pub mod backend;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bake;
+pub mod budget;
+pub mod camera;
+pub mod clipping;
+pub mod color;
+pub mod contact_sheet;
+pub mod cryptomatte;
+pub mod debug_view;
+pub mod deep;
+pub mod description;
+pub mod diagnostics;
+pub mod edge_overlay;
+#[cfg(not(target_arch = "wasm32"))]
+mod distributed;
+pub mod exr;
+pub mod ffi;
+pub mod generator;
+pub mod intersection;
+pub mod kd_tree;
+pub mod lens;
+pub mod logging;
+pub mod lookdev;
+pub mod light;
+pub mod math;
+pub mod noise;
+pub mod object;
+pub mod pause;
+pub mod pixel_buffer;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod ray;
 mod reader;
-mod scene;
-mod tone;
+pub mod scene;
+pub mod stereo;
+#[cfg(not(target_arch = "wasm32"))]
+mod server;
+pub mod tile;
+pub mod tone;
+pub mod tuning;
+pub mod units;
+pub mod visibility;
+pub mod volume;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+#[cfg(not(target_arch = "wasm32"))]
+mod watch;
 
-use self::color::Color;
+use self::backend::Backend;
+use self::budget::RenderBudget;
+use self::color::Radiance;
+use self::cryptomatte::Cryptomatte;
+use self::deep::DeepBuffer;
+use self::description::SceneDescription;
+use self::diagnostics::PixelStatistics;
+use self::exr::{DeepExrImage, ExrImage, TiledExrWriter};
 use self::scene::configuration::Configuration;
-use self::kd_tree::KdTree;
+use self::kd_tree::{KdTree, ShapeSpool};
+use self::light::Light;
+use self::logging::Stage;
 use self::object::*;
+use self::pause::PauseControl;
 use self::scene::Scene;
+use self::stereo::Eye;
+use self::tile::Tile;
+use self::tone::AutoExposure;
+use self::tuning::{RenderProfile, RenderRecord, TraversalStatistics, TuningSettings};
+use self::volume::Volume;
 
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
 use std::thread;
-use std::path::Path;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
-use image::{ImageBuffer, Rgba};
+#[cfg(not(target_arch = "wasm32"))]
+use image::{ColorType, ImageBuffer, Rgba};
+#[cfg(not(target_arch = "wasm32"))]
+use image::png::PNGEncoder;
 
-pub fn save_image(filename: &str, color_buffer: &[Vec<Color>], width: usize, height: usize) {
+#[cfg(not(target_arch = "wasm32"))]
+pub fn image_buffer(
+    color_buffer: &[Vec<Radiance>],
+    width: usize,
+    height: usize,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let mut image_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> =
         ImageBuffer::new(width as u32, height as u32);
 
     for x in 0..width {
         for y in 0..height {
-            image_buffer.put_pixel(x as u32, y as u32, color_buffer[x][y].to_rgba());
+            image_buffer.put_pixel(x as u32, y as u32, color_buffer[x][y].to_display().to_rgba());
         }
     }
 
-    image_buffer.save(Path::new(filename)).unwrap();
+    image_buffer
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_image(
+    filename: &str,
+    color_buffer: &[Vec<Radiance>],
+    width: usize,
+    height: usize,
+) -> io::Result<()> {
+    image_buffer(color_buffer, width, height)
+        .save(Path::new(filename))
+        .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", filename, error)))
+}
+
+// PNG file contents without touching the filesystem
+#[cfg(not(target_arch = "wasm32"))]
+pub fn encode_png(
+    color_buffer: &[Vec<Radiance>],
+    width: usize,
+    height: usize,
+) -> io::Result<Vec<u8>> {
+    let mut png: Vec<u8> = Vec::new();
+    PNGEncoder::new(&mut png).encode(
+        &image_buffer(color_buffer, width, height),
+        width as u32,
+        height as u32,
+        ColorType::RGBA(8),
+    )?;
+
+    Ok(png)
+}
+
+// Tone mapped, row-major RGBA bytes - e.g. for a canvas
+pub fn rgba_bytes(color_buffer: &[Vec<Radiance>], width: usize, height: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(width * height * 4);
+
+    for y in 0..height {
+        for column in color_buffer.iter().take(width) {
+            let color = column[y].to_display();
+            bytes.extend_from_slice(&[color.r, color.g, color.b, 255]);
+        }
+    }
+
+    bytes
 }
 
 pub fn combine_scenes(
-    color_buffer: &mut Vec<Vec<Color>>,
+    color_buffer: &mut Vec<Vec<Radiance>>,
     scenes: Vec<(usize, Scene)>,
     threads: usize,
 ) {
@@ -46,50 +159,513 @@ pub fn combine_scenes(
     }
 }
 
-pub fn draw(config_file: &str, out_file: &str) {
-    let configuration: Configuration = Configuration::read_configuration(config_file);
+// Per-pixel outputs recorded alongside the image, when configured
+#[derive(Default)]
+pub struct RenderPasses {
+    pub statistics: Option<PixelStatistics>,
+    pub cryptomatte: Option<Cryptomatte>,
+    pub deep: Option<DeepBuffer>,
+    // Always recorded
+    pub traversal: TraversalStatistics,
+}
 
-    let threads: usize = configuration.threads;
-    let width: usize = configuration.width;
-    let height: usize = configuration.height;
+pub fn combine_statistics(
+    configuration: &Configuration,
+    scenes: &[(usize, Scene)],
+    threads: usize,
+) -> Option<PixelStatistics> {
+    if !configuration.diagnostics {
+        return None;
+    }
 
-    let mut thread_handles: Vec<thread::JoinHandle<_>> = Vec::with_capacity(threads);
+    let mut statistics: PixelStatistics =
+        PixelStatistics::new(configuration.width, configuration.height);
+    for &(thread_number, ref scene) in scenes {
+        if let Some(scene_statistics) = scene.statistics() {
+            statistics.merge(scene_statistics, scene.draw_iterator(threads, thread_number));
+        }
+    }
+
+    Some(statistics)
+}
+
+pub fn combine_traversal(scenes: &[(usize, Scene)]) -> TraversalStatistics {
+    let mut traversal: TraversalStatistics = TraversalStatistics::default();
+    for (_, scene) in scenes {
+        traversal.add(&scene.traversal_statistics());
+    }
+
+    traversal
+}
 
-    /*  Initialize KD tree */
+pub fn combine_cryptomattes(
+    configuration: &Configuration,
+    scenes: &[(usize, Scene)],
+    threads: usize,
+) -> Option<Cryptomatte> {
+    if !configuration.cryptomatte {
+        return None;
+    }
+
+    let mut cryptomatte: Cryptomatte = Cryptomatte::new(configuration.width, configuration.height);
+    for &(thread_number, ref scene) in scenes {
+        if let Some(scene_cryptomatte) = scene.cryptomatte() {
+            cryptomatte.merge(scene_cryptomatte, scene.draw_iterator(threads, thread_number));
+        }
+    }
+
+    Some(cryptomatte)
+}
+
+pub fn combine_deep(
+    configuration: &Configuration,
+    scenes: &[(usize, Scene)],
+    threads: usize,
+) -> Option<DeepBuffer> {
+    if !configuration.deep {
+        return None;
+    }
+
+    let mut deep: DeepBuffer = DeepBuffer::new(configuration.width, configuration.height);
+    for &(thread_number, ref scene) in scenes {
+        if let Some(scene_deep) = scene.deep() {
+            deep.merge(scene_deep, scene.draw_iterator(threads, thread_number));
+        }
+    }
+
+    Some(deep)
+}
+
+// overrides are key path assignments, as for Configuration::with_overrides
+pub fn describe(config_file: &str, overrides: &[String]) -> io::Result<SceneDescription> {
+    SceneDescription::new(&Configuration::parse_configuration(config_file)?.with_overrides(overrides)?)
+}
+
+// Every surface shape from the configured objects, tagged with the object's
+// index - volumes are loaded by load_volumes instead
+pub fn load_shapes(configuration: &Configuration) -> io::Result<Vec<Shape>> {
     let mut shapes: Vec<Shape> = Vec::new();
+    stream_shapes(configuration, &mut |chunk| {
+        shapes.extend(chunk);
+        Ok(())
+    })?;
+
+    Ok(shapes)
+}
+
+// The shapes load_shapes returns, handed to sink a chunk at a time
+fn stream_shapes(
+    configuration: &Configuration,
+    sink: &mut dyn FnMut(Vec<Shape>) -> io::Result<()>,
+) -> io::Result<()> {
+    let _stage: Stage = logging::stage("Loading objects");
+
+    let mut total: usize = 0;
+    for (object_id, object_definition) in configuration.objects.iter().enumerate() {
+        if object_definition.volume.is_some() {
+            continue;
+        }
+
+        let mut count: usize = 0;
+        object_definition.stream_detailed_shapes(
+            configuration.units,
+            |bounding_box| configuration.projected_pixels(bounding_box),
+            &mut |chunk: Vec<Shape>| {
+                count += chunk.len();
+                sink(
+                    chunk
+                        .into_iter()
+                        .map(|shape| shape.with_object_id(object_id))
+                        .collect(),
+                )
+            },
+        )?;
+        debug!("{}: {} shapes", object_definition.name(), count);
+        total += count;
+    }
+
+    info!("Loaded {} shapes from {} objects", total, configuration.objects.len());
+    Ok(())
+}
+
+// Fog from every object configured as a volume
+pub fn load_volumes(configuration: &Configuration) -> io::Result<Vec<Volume>> {
+    let mut volumes: Vec<Volume> = Vec::new();
     for object_definition in &configuration.objects {
-        shapes.append(&mut (object_definition.read_shapes()));
+        if let Some(volume) = object_definition.as_volume(configuration.units)? {
+            volumes.push(volume);
+        }
+    }
+
+    Ok(volumes)
+}
+
+// Every configured light, with spotlight gobo images decoded once for all scenes
+pub fn load_lights(configuration: &Configuration) -> io::Result<Vec<Light>> {
+    configuration
+        .lights
+        .iter()
+        .map(|light_definition| light_definition.as_light(configuration.units))
+        .collect()
+}
+
+// Load every configured object into a KD tree - with a memory limit, shapes are written
+// to disk as they are read, so the scene is never loaded whole
+pub fn build_kd_tree(configuration: &Configuration) -> io::Result<KdTree> {
+    match configuration.memory_limit_mb {
+        Some(memory_limit_mb) => {
+            let mut spool: ShapeSpool = ShapeSpool::new(memory_limit_mb * 1024 * 1024)?;
+            stream_shapes(configuration, &mut |chunk| spool.push(&chunk))?;
+
+            let _stage: Stage = logging::stage("Building k-d tree");
+            KdTree::from_spool(
+                spool,
+                configuration.max_kd_tree_depth,
+                configuration.kd_tree_leaf_size,
+            )
+        }
+        None => kd_tree_from_shapes(load_shapes(configuration)?, configuration),
+    }
+}
+
+// Leaves are paged out to disk when the configuration sets a memory limit
+pub fn kd_tree_from_shapes(
+    shapes: Vec<Shape>,
+    configuration: &Configuration,
+) -> io::Result<KdTree> {
+    let _stage: Stage = logging::stage("Building k-d tree");
+    match configuration.memory_limit_mb {
+        Some(memory_limit_mb) => KdTree::paged(
+            shapes,
+            configuration.max_kd_tree_depth,
+            configuration.kd_tree_leaf_size,
+            memory_limit_mb * 1024 * 1024,
+        ),
+        None => Ok(KdTree::new(
+            &shapes,
+            configuration.max_kd_tree_depth,
+            configuration.kd_tree_leaf_size,
+        )),
     }
+}
+
+// Trace the configured scene without tone mapping
+// progress is incremented once per completed pixel
+pub fn trace(
+    configuration: &Configuration,
+    progress: &Arc<AtomicUsize>,
+) -> io::Result<Vec<Vec<Radiance>>> {
+    trace_with(configuration, progress, Backend::Cpu)
+}
+
+// Trace on the given backend - the GPU path falls back to the CPU when no adapter is available
+pub fn trace_with(
+    configuration: &Configuration,
+    progress: &Arc<AtomicUsize>,
+    backend: Backend,
+) -> io::Result<Vec<Vec<Radiance>>> {
+    trace_with_passes(configuration, progress, backend).map(|(color_buffer, _)| color_buffer)
+}
+
+// Also returns per-pixel statistics, ID mattes and deep samples when the configuration enables them
+// Only the CPU backend records them
+pub fn trace_with_passes(
+    configuration: &Configuration,
+    progress: &Arc<AtomicUsize>,
+    backend: Backend,
+) -> io::Result<(Vec<Vec<Radiance>>, RenderPasses)> {
+    let kd_tree: KdTree = if backend == Backend::Gpu {
+        let shapes: Vec<Shape> = load_shapes(configuration)?;
+
+        #[cfg(feature = "gpu")]
+        match backend::gpu::trace(configuration, &shapes, progress) {
+            Ok(color_buffer) => return Ok((color_buffer, RenderPasses::default())),
+            Err(error) => warn!("GPU backend unavailable ({}), rendering on the CPU", error),
+        }
+
+        #[cfg(not(feature = "gpu"))]
+        warn!("Built without the gpu feature, rendering on the CPU");
+
+        kd_tree_from_shapes(shapes, configuration)?
+    } else {
+        build_kd_tree(configuration)?
+    };
 
-    let kd_tree: KdTree = KdTree::new(&shapes, configuration.max_kd_tree_depth);
     let arc_tree: Arc<KdTree> = Arc::new(kd_tree);
+    let volumes: Arc<Vec<Volume>> = Arc::new(load_volumes(configuration)?);
+    let lights: Arc<Vec<Light>> = Arc::new(load_lights(configuration)?);
+
+    // Both eyes share the tree, volumes and lights
+    match configuration.stereo {
+        Some(ref stereo) => {
+            let (left, left_passes) = trace_view(
+                &stereo.eye_configuration(configuration, Eye::Left),
+                &arc_tree,
+                &volumes,
+                &lights,
+                progress,
+            )?;
+            let (right, right_passes) = trace_view(
+                &stereo.eye_configuration(configuration, Eye::Right),
+                &arc_tree,
+                &volumes,
+                &lights,
+                progress,
+            )?;
+
+            Ok((stereo.combine(left, right), stereo.combine_passes(left_passes, right_passes)))
+        }
+        None => trace_view(configuration, &arc_tree, &volumes, &lights, progress),
+    }
+}
+
+// Trace the configured camera's view of an existing tree
+fn trace_view(
+    configuration: &Configuration,
+    arc_tree: &Arc<KdTree>,
+    volumes: &Arc<Vec<Volume>>,
+    lights: &Arc<Vec<Light>>,
+    progress: &Arc<AtomicUsize>,
+) -> io::Result<(Vec<Vec<Radiance>>, RenderPasses)> {
+    let scenes: Vec<(usize, Scene)> =
+        draw_scenes(configuration, arc_tree, volumes, lights, progress)?;
+    arc_tree.check_pages()?;
+    let threads: usize = scenes.len();
+
+    let passes: RenderPasses = RenderPasses {
+        statistics: combine_statistics(configuration, &scenes, threads),
+        cryptomatte: combine_cryptomattes(configuration, &scenes, threads),
+        deep: combine_deep(configuration, &scenes, threads),
+        traversal: combine_traversal(&scenes),
+    };
+
+    let mut color_buffer: Vec<Vec<Radiance>> =
+        vec![vec![Radiance::black(); configuration.height]; configuration.width];
+    combine_scenes(&mut color_buffer, scenes, threads);
+
+    Ok((color_buffer, passes))
+}
+
+// Draw each thread's share of the image in parallel
+#[cfg(not(target_arch = "wasm32"))]
+fn draw_scenes(
+    configuration: &Configuration,
+    arc_tree: &Arc<KdTree>,
+    volumes: &Arc<Vec<Volume>>,
+    lights: &Arc<Vec<Light>>,
+    progress: &Arc<AtomicUsize>,
+) -> io::Result<Vec<(usize, Scene)>> {
+    let _stage: Stage = logging::stage("Rendering");
+    let threads: usize = configuration.thread_count();
+    let low_priority: bool = configuration.low_priority;
+    info!(
+        "Rendering {}x{} at {} samples per pixel on {} threads",
+        configuration.width,
+        configuration.height,
+        configuration.samples,
+        threads
+    );
+    let budget: Option<Arc<RenderBudget>> = configuration.render_budget()?.map(Arc::new);
+    let pause: Option<Arc<PauseControl>> = pause_control(configuration, threads).map(Arc::new);
+
+    let mut thread_handles: Vec<thread::JoinHandle<_>> = Vec::with_capacity(threads);
 
     for i in 0..threads {
-        let mut scene: Scene = Scene::new(&configuration, Arc::clone(&arc_tree));
+        let mut scene: Scene =
+            Scene::new(
+                configuration,
+                Arc::clone(arc_tree),
+                Arc::clone(volumes),
+                Arc::clone(lights),
+            )?;
+        scene.set_progress(Arc::clone(progress));
+        let budget: Option<Arc<RenderBudget>> = budget.clone();
+        if let Some(ref pause) = pause {
+            scene.set_pause(Arc::clone(pause));
+        }
 
         thread_handles.push(thread::spawn(move || {
-            scene.partial_draw(threads, i);
+            if low_priority {
+                lower_thread_priority();
+            }
+
+            scene.partial_draw(threads, i, budget.as_deref());
+            if let Some(ref budget) = budget {
+                scene.refine(threads, i, budget);
+            }
+            scene.finish_pause(threads, i);
             (i, scene)
         }));
     }
 
-    // Collect results from each thread into one color buffer
+    // Collect results from each thread
     let mut scenes: Vec<(usize, Scene)> = Vec::with_capacity(threads);
     for thread_handle in thread_handles {
         scenes.push(thread_handle.join().unwrap());
     }
 
-    let mut color_buffer: Vec<Vec<Color>> = vec![vec![Color::new(0f64, 0f64, 0f64); height]; width];
-    combine_scenes(&mut color_buffer, scenes, threads);
+    if let Some(budget) = budget {
+        let pixels: usize = configuration.width * configuration.height;
+        info!(
+            "Refined with {:.1} more samples per pixel",
+            budget.taken() as f64 / pixels as f64
+        );
+    }
+
+    Ok(scenes)
+}
 
-    // Tone correction
-    tone::reinhard_tone_correction(
-        &mut color_buffer,
-        configuration.width as usize,
-        configuration.height as usize,
+// Snapshots of a paused render - metered on the lit pixels, so the undrawn black ones
+// don't brighten the rest
+#[cfg(not(target_arch = "wasm32"))]
+fn pause_control(configuration: &Configuration, threads: usize) -> Option<PauseControl> {
+    let mut configuration: Configuration = configuration.clone();
+    if configuration.auto_exposure.is_none() {
+        configuration.auto_exposure = Some(AutoExposure::Average);
+    }
+    let (width, height): (usize, usize) = (configuration.width, configuration.height);
+
+    PauseControl::new(
+        width,
+        height,
+        threads,
+        Box::new(move |color_buffer: &[Vec<Radiance>], file: &str| {
+            let mut color_buffer: Vec<Vec<Radiance>> = color_buffer.to_vec();
+            tone_map(&configuration, &mut color_buffer);
+            save_image(file, &color_buffer, width, height)
+        }),
+    )
+}
+
+// Be nice to interactive programs - best effort, failures leave the priority unchanged
+// Sets an absolute niceness rather than adding to it, so calling it from every render thread
+// lowers the priority once - it applies per thread on Linux, per process elsewhere
+#[cfg(unix)]
+pub fn lower_thread_priority() {
+    const LOW_PRIORITY: libc::c_int = 10;
+
+    unsafe {
+        if libc::getpriority(libc::PRIO_PROCESS as _, 0) < LOW_PRIORITY {
+            libc::setpriority(libc::PRIO_PROCESS as _, 0, LOW_PRIORITY);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn lower_thread_priority() {}
+
+// No threads on wasm - draw the whole image on the calling thread
+#[cfg(target_arch = "wasm32")]
+fn draw_scenes(
+    configuration: &Configuration,
+    arc_tree: &Arc<KdTree>,
+    volumes: &Arc<Vec<Volume>>,
+    lights: &Arc<Vec<Light>>,
+    progress: &Arc<AtomicUsize>,
+) -> io::Result<Vec<(usize, Scene)>> {
+    let mut scene: Scene = Scene::new(
+        configuration,
+        Arc::clone(arc_tree),
+        Arc::clone(volumes),
+        Arc::clone(lights),
+    )?;
+    scene.set_progress(Arc::clone(progress));
+    scene.partial_draw(1, 0, None);
+
+    Ok(vec![(0, scene)])
+}
+
+// Debug views replace tone mapping with their own scaling
+pub fn tone_map(configuration: &Configuration, color_buffer: &mut Vec<Vec<Radiance>>) {
+    if let Some(debug_view) = configuration.debug_view {
+        debug_view.finish(color_buffer);
+        return;
+    }
+
+    tone::tone_correction(
+        color_buffer,
+        configuration.width,
+        configuration.height,
         configuration.reinhard_key_value,
         configuration.reinhard_delta,
+        configuration.auto_exposure,
+        configuration.exposure(),
+    );
+}
+
+// Trace the configured scene and return the tone mapped color buffer
+pub fn render(configuration: &Configuration) -> io::Result<Vec<Vec<Radiance>>> {
+    let mut color_buffer: Vec<Vec<Radiance>> =
+        trace(configuration, &Arc::new(AtomicUsize::new(0)))?;
+    tone_map(configuration, &mut color_buffer);
+
+    Ok(color_buffer)
+}
+
+// Render the configuration into tone mapped, row-major RGBA bytes
+pub fn render_rgba(configuration: &Configuration) -> io::Result<Vec<u8>> {
+    Ok(rgba_bytes(
+        &render(configuration)?,
+        configuration.width,
+        configuration.height,
+    ))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn draw(configuration: &Configuration, out_file: &str, backend: Backend) -> io::Result<()> {
+    // EXR files mark overscan pixels as outside the display window
+    let overscan: (usize, usize) = configuration.overscan_pixels();
+    let mut configuration: Configuration = configuration.with_overscan();
+    let tuned: Option<(RenderProfile, TuningSettings)> = auto_tune(&mut configuration, out_file, None);
+    let configuration: &Configuration = &configuration;
+    pause::install(&sidecar_filename(out_file, "snapshot.png"));
+
+    let start: Instant = Instant::now();
+    let (mut color_buffer, passes) =
+        trace_with_passes(configuration, &Arc::new(AtomicUsize::new(0)), backend)?;
+    record_profile(
+        tuned,
+        configuration,
+        out_file,
+        start.elapsed().as_secs_f64(),
+        passes.traversal,
     );
+    let _stage: Stage = logging::stage("Writing output");
+
+    match passes.statistics {
+        Some(statistics) => save_diagnostics(configuration, out_file, &statistics)?,
+        None if configuration.diagnostics => {
+            warn!("Diagnostics are only recorded by the CPU backend")
+        }
+        None => {}
+    }
+
+    match passes.deep {
+        Some(ref deep) => save_deep(configuration, out_file, deep, overscan)?,
+        None if configuration.deep => warn!("Deep output is only recorded by the CPU backend"),
+        None => {}
+    }
+
+    if out_file.ends_with(".exr") {
+        // High dynamic range output - skip tone mapping
+        let mut image: ExrImage =
+            ExrImage::from_radiance(&color_buffer, configuration.width, configuration.height);
+        image.set_overscan(overscan);
+        if let Some(ref cryptomatte) = passes.cryptomatte {
+            add_cryptomatte_layers(configuration, cryptomatte, &mut image);
+        }
+        return image.save(out_file);
+    }
+
+    match passes.cryptomatte {
+        Some(ref cryptomatte) => save_cryptomatte(configuration, out_file, cryptomatte, overscan)?,
+        None if configuration.cryptomatte => {
+            warn!("ID mattes are only recorded by the CPU backend")
+        }
+        None => {}
+    }
+
+    tone_map(configuration, &mut color_buffer);
 
     // Save the image
     save_image(
@@ -97,5 +673,374 @@ pub fn draw(config_file: &str, out_file: &str) {
         &color_buffer,
         configuration.width,
         configuration.height,
+    )
+}
+
+// Render straight into a tiled EXR a tile at a time, so images larger than memory can be drawn
+// Only tiles being rendered or waiting to be written are held
+#[cfg(not(target_arch = "wasm32"))]
+pub fn draw_tiled(configuration: &Configuration, out_file: &str, tile_size: usize) -> io::Result<()> {
+    if configuration.stereo.is_some() {
+        return Err(io::Error::other("Stereo images can't be rendered as tiles"));
+    }
+    if configuration.diagnostics || configuration.cryptomatte || configuration.deep {
+        warn!("Diagnostics, ID mattes and deep output are not recorded for tiled output");
+    }
+    if configuration.time_limit.is_some() || configuration.sample_budget.is_some() {
+        warn!("Tiled output is rendered at the configured samples, ignoring render budgets");
+    }
+
+    let overscan: (usize, usize) = configuration.overscan_pixels();
+    let mut configuration: Configuration = configuration.with_overscan();
+    let tuned: Option<(RenderProfile, TuningSettings)> =
+        auto_tune(&mut configuration, out_file, Some(tile_size));
+    let tile_size: usize = tuned
+        .as_ref()
+        .and_then(|tuned| tuned.1.tile_size)
+        .unwrap_or(tile_size);
+    configuration.diagnostics = false;
+    configuration.cryptomatte = false;
+    configuration.deep = false;
+
+    let tiles: Arc<Vec<Tile>> =
+        Arc::new(Tile::split(configuration.width, configuration.height, tile_size));
+    let next_tile: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+    let start: Instant = Instant::now();
+    let arc_tree: Arc<KdTree> = Arc::new(build_kd_tree(&configuration)?);
+    let volumes: Arc<Vec<Volume>> = Arc::new(load_volumes(&configuration)?);
+    let lights: Arc<Vec<Light>> = Arc::new(load_lights(&configuration)?);
+    let threads: usize = configuration.thread_count();
+
+    // Bounded, so rendering waits for the file rather than filling memory
+    let (sender, receiver) = mpsc::sync_channel::<(Tile, Vec<Radiance>)>(threads);
+
+    let mut thread_handles: Vec<thread::JoinHandle<TraversalStatistics>> =
+        Vec::with_capacity(threads);
+    for _ in 0..threads {
+        let mut scene: Scene = Scene::new(
+            &configuration,
+            Arc::clone(&arc_tree),
+            Arc::clone(&volumes),
+            Arc::clone(&lights),
+        )?;
+        let tiles: Arc<Vec<Tile>> = Arc::clone(&tiles);
+        let next_tile: Arc<AtomicUsize> = Arc::clone(&next_tile);
+        let sender: mpsc::SyncSender<(Tile, Vec<Radiance>)> = sender.clone();
+        let low_priority: bool = configuration.low_priority;
+
+        thread_handles.push(thread::spawn(move || {
+            if low_priority {
+                lower_thread_priority();
+            }
+
+            while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                if scene.paging_failed() {
+                    break;
+                }
+                if sender.send((*tile, scene.render_tile(tile))).is_err() {
+                    break;
+                }
+            }
+
+            scene.traversal_statistics()
+        }));
+    }
+    drop(sender);
+
+    let mut writer: TiledExrWriter = TiledExrWriter::create(
+        out_file,
+        configuration.width,
+        configuration.height,
+        tile_size,
+        overscan,
+    )?;
+    for (written, (tile, pixels)) in receiver.iter().enumerate() {
+        writer.write_tile(&tile, &pixels)?;
+
+        if (written + 1) % tiles.len().div_ceil(10) == 0 {
+            debug!("{} of {} tiles", written + 1, tiles.len());
+        }
+    }
+
+    let mut traversal: TraversalStatistics = TraversalStatistics::default();
+    for thread_handle in thread_handles {
+        traversal.add(&thread_handle.join().unwrap());
+    }
+    arc_tree.check_pages()?;
+    record_profile(
+        tuned,
+        &configuration,
+        out_file,
+        start.elapsed().as_secs_f64(),
+        traversal,
+    );
+
+    writer.finish()
+}
+
+// False color samples-per-pixel and time-per-pixel images next to the output file
+#[cfg(not(target_arch = "wasm32"))]
+fn save_diagnostics(
+    configuration: &Configuration,
+    out_file: &str,
+    statistics: &PixelStatistics,
+) -> io::Result<()> {
+    let samples_file: String = diagnostics::diagnostic_filename(out_file, "samples");
+    let time_file: String = diagnostics::diagnostic_filename(out_file, "time");
+
+    save_image(
+        &samples_file,
+        &statistics.samples_heatmap(),
+        configuration.width,
+        configuration.height,
+    )?;
+    save_image(
+        &time_file,
+        &statistics.time_heatmap(),
+        configuration.width,
+        configuration.height,
+    )?;
+
+    info!("{}", statistics);
+    info!("Diagnostics written to {} and {}", samples_file, time_file);
+    Ok(())
+}
+
+// With auto_tune, apply settings picked from the output's render profile - returned with
+// the profile for record_profile
+#[cfg(not(target_arch = "wasm32"))]
+fn auto_tune(
+    configuration: &mut Configuration,
+    out_file: &str,
+    tile_size: Option<usize>,
+) -> Option<(RenderProfile, TuningSettings)> {
+    if !configuration.auto_tune {
+        return None;
+    }
+
+    let profile: RenderProfile = RenderProfile::load(
+        &sidecar_filename(out_file, "profile.json"),
+        &tuning::scene_key(configuration),
+    );
+    let settings: TuningSettings =
+        profile.next_settings(TuningSettings::new(configuration, tile_size));
+    settings.apply(configuration);
+
+    info!(
+        "Auto-tuned from {} earlier renders: k-d tree depth {}, leaf size {}{}",
+        profile.renders.len(),
+        settings.max_kd_tree_depth,
+        settings.kd_tree_leaf_size,
+        settings
+            .tile_size
+            .map(|tile_size| format!(", tile size {}", tile_size))
+            .unwrap_or_default()
     );
+
+    let samples: usize = profile.first_pass_samples(configuration);
+    if samples < configuration.samples {
+        info!(
+            "Auto-tuned first pass to {} samples per pixel, leaving the time limit room to refine",
+            samples
+        );
+        configuration.samples = samples;
+    }
+
+    Some((profile, settings))
+}
+
+// Add a render to the profile its settings came from
+#[cfg(not(target_arch = "wasm32"))]
+fn record_profile(
+    tuned: Option<(RenderProfile, TuningSettings)>,
+    configuration: &Configuration,
+    out_file: &str,
+    seconds: f64,
+    traversal: TraversalStatistics,
+) {
+    let (mut profile, settings) = match tuned {
+        Some(tuned) => tuned,
+        None => return,
+    };
+
+    // Only the CPU backend counts its work
+    if traversal.traversals == 0 {
+        return;
+    }
+
+    debug!(
+        "{} traversals visiting {:.1} nodes and testing {:.1} objects each, {:.2}µs per traversal",
+        traversal.traversals,
+        traversal.nodes as f64 / traversal.traversals as f64,
+        traversal.intersection_tests as f64 / traversal.traversals as f64,
+        traversal.seconds * 1e6 / traversal.traversals as f64
+    );
+
+    profile.record(RenderRecord {
+        settings,
+        seconds,
+        threads: configuration.thread_count(),
+        statistics: traversal,
+    });
+
+    let profile_file: String = sidecar_filename(out_file, "profile.json");
+    if let Err(error) = profile.save(&profile_file) {
+        warn!("{}: {}", profile_file, error);
+    }
+}
+
+// img/scene.png -> img/scene.<suffix>
+fn sidecar_filename(out_file: &str, suffix: &str) -> String {
+    let path: &Path = Path::new(out_file);
+    let stem: &str = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("scene");
+
+    path.with_file_name(format!("{}.{}", stem, suffix))
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Object and material ID layers
+fn add_cryptomatte_layers(
+    configuration: &Configuration,
+    cryptomatte: &Cryptomatte,
+    image: &mut ExrImage,
+) {
+    let object_groups: Vec<usize> = (0..configuration.objects.len()).collect();
+    let (material_groups, material_names) = configuration.material_groups();
+
+    cryptomatte.add_layer(image, "CryptoObject", &configuration.object_names(), &object_groups);
+    cryptomatte.add_layer(image, "CryptoMaterial", &material_names, &material_groups);
+}
+
+// ID mattes for an 8-bit image go in a separate EXR - img/scene.png -> img/scene.cryptomatte.exr
+#[cfg(not(target_arch = "wasm32"))]
+fn save_cryptomatte(
+    configuration: &Configuration,
+    out_file: &str,
+    cryptomatte: &Cryptomatte,
+    overscan: (usize, usize),
+) -> io::Result<()> {
+    let matte_file: String = sidecar_filename(out_file, "cryptomatte.exr");
+
+    let mut image: ExrImage = ExrImage::new(configuration.width, configuration.height);
+    image.set_overscan(overscan);
+    add_cryptomatte_layers(configuration, cryptomatte, &mut image);
+    image.save(&matte_file)?;
+
+    info!("ID mattes written to {}", matte_file);
+    Ok(())
+}
+
+// Deep samples go in a separate EXR - img/scene.png -> img/scene.deep.exr
+#[cfg(not(target_arch = "wasm32"))]
+fn save_deep(
+    configuration: &Configuration,
+    out_file: &str,
+    deep: &DeepBuffer,
+    overscan: (usize, usize),
+) -> io::Result<()> {
+    let deep_file: String = sidecar_filename(out_file, "deep.exr");
+
+    let mut image: DeepExrImage = deep.to_exr(configuration.width, configuration.height);
+    image.set_overscan(overscan);
+    image.save(&deep_file)?;
+
+    info!("Deep samples written to {}", deep_file);
+    Ok(())
+}
+
+// Focus the configuration file's camera on whatever is at pixel x, y and save it,
+// returning the new focal distance. Coordinates are in a preview's pixels if a
+// preview scale is given, of the view with the overrides applied.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn focus_at(
+    config_file: &str,
+    overrides: &[String],
+    x: usize,
+    y: usize,
+    preview_scale: Option<f64>,
+) -> io::Result<f64> {
+    let mut configuration: Configuration =
+        Configuration::parse_configuration(config_file)?.with_overrides(overrides)?;
+    if let Some(preview_scale) = preview_scale {
+        configuration.scale_resolution(preview_scale);
+    }
+
+    if x >= configuration.width || y >= configuration.height {
+        return Err(io::Error::other(format!(
+            "{}, {} is outside the {}x{} image",
+            x, y, configuration.width, configuration.height
+        )));
+    }
+
+    let scene: Scene = Scene::new(
+        &configuration,
+        Arc::new(build_kd_tree(&configuration)?),
+        Arc::new(load_volumes(&configuration)?),
+        Arc::new(load_lights(&configuration)?),
+    )?;
+    let focal_distance: f64 = scene
+        .focus_distance(x, y)
+        .ok_or_else(|| io::Error::other(format!("Nothing to focus on at {}, {}", x, y)))?;
+
+    Configuration::write_setting(config_file, "focal_distance", focal_distance)?;
+
+    Ok(focal_distance)
+}
+
+// Re-render a reduced resolution preview whenever the configuration file changes
+#[cfg(not(target_arch = "wasm32"))]
+pub fn watch(
+    config_file: &str,
+    overrides: &[String],
+    out_file: &str,
+    preview_scale: f64,
+) -> io::Result<()> {
+    watch::Watcher::new(config_file, overrides, out_file, preview_scale)?.run()
+}
+
+// Accept render jobs over HTTP
+#[cfg(not(target_arch = "wasm32"))]
+pub fn serve(address: &str, root: &str) -> io::Result<()> {
+    server::Server::new(PathBuf::from(root)).run(address)
+}
+
+// Hand out tiles of the configured scene to remote workers and collect the results
+#[cfg(not(target_arch = "wasm32"))]
+pub fn coordinate(
+    address: &str,
+    config_file: &str,
+    out_file: &str,
+    tile_size: usize,
+) -> io::Result<()> {
+    let configuration: Configuration = Configuration::parse_configuration(config_file)?;
+    // Workers trace the configured frame from the configured camera, one tile at a time
+    if configuration.stereo.is_some() {
+        return Err(io::Error::other("Stereo images can't be rendered by workers"));
+    }
+    if configuration.overscan > 0f64 {
+        warn!("Distributed renders ignore overscan, rendering the configured frame");
+    }
+
+    let mut color_buffer: Vec<Vec<Radiance>> =
+        distributed::Coordinator::new(config_file, tile_size)?.run(address)?;
+
+    if out_file.ends_with(".exr") {
+        return ExrImage::from_radiance(&color_buffer, configuration.width, configuration.height)
+            .save(out_file);
+    }
+
+    tone_map(&configuration, &mut color_buffer);
+    save_image(
+        out_file,
+        &color_buffer,
+        configuration.width,
+        configuration.height,
+    )
+}
+
+// Render tiles for the coordinator at the given address until it runs out
+#[cfg(not(target_arch = "wasm32"))]
+pub fn work(address: &str) -> io::Result<()> {
+    distributed::worker::work(address)
 }
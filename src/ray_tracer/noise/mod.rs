@@ -0,0 +1,83 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+// Gradient (Perlin style) noise with gradients picked by an integer hash of
+// the lattice point, so no permutation table is needed. Roughly -1 - 1.
+pub fn gradient_noise(point: Vector3<f64>) -> f64 {
+    let cell: Vector3<f64> = Vector3::new(point.x.floor(), point.y.floor(), point.z.floor());
+    let local: Vector3<f64> = point - cell;
+    let (x, y, z): (i64, i64, i64) = (cell.x as i64, cell.y as i64, cell.z as i64);
+
+    let u: Vector3<f64> = Vector3::new(fade(local.x), fade(local.y), fade(local.z));
+
+    let corner = |dx: i64, dy: i64, dz: i64| -> f64 {
+        gradient(hash(x + dx, y + dy, z + dz))
+            .dot(local - Vector3::new(dx as f64, dy as f64, dz as f64))
+    };
+
+    let x00: f64 = lerp(corner(0, 0, 0), corner(1, 0, 0), u.x);
+    let x10: f64 = lerp(corner(0, 1, 0), corner(1, 1, 0), u.x);
+    let x01: f64 = lerp(corner(0, 0, 1), corner(1, 0, 1), u.x);
+    let x11: f64 = lerp(corner(0, 1, 1), corner(1, 1, 1), u.x);
+
+    lerp(lerp(x00, x10, u.y), lerp(x01, x11, u.y), u.z)
+}
+
+// Fractal sum of octaves, each at double the frequency and half the amplitude
+pub fn fbm(point: Vector3<f64>, octaves: usize) -> f64 {
+    let mut sum: f64 = 0f64;
+    let mut amplitude: f64 = 1f64;
+    let mut frequency: f64 = 1f64;
+    let mut total_amplitude: f64 = 0f64;
+
+    for _ in 0..octaves {
+        sum += gradient_noise(point * frequency) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2f64;
+    }
+
+    if total_amplitude > 0f64 {
+        sum / total_amplitude
+    } else {
+        0f64
+    }
+}
+
+// 6t^5 - 15t^4 + 10t^3
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6f64 - 15f64) + 10f64)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn hash(x: i64, y: i64, z: i64) -> u64 {
+    let mut h: u64 = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^
+        (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F) ^
+        (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    h
+}
+
+// One of the twelve cube edge directions
+fn gradient(hash: u64) -> Vector3<f64> {
+    match hash % 12 {
+        0 => Vector3::new(1f64, 1f64, 0f64),
+        1 => Vector3::new(-1f64, 1f64, 0f64),
+        2 => Vector3::new(1f64, -1f64, 0f64),
+        3 => Vector3::new(-1f64, -1f64, 0f64),
+        4 => Vector3::new(1f64, 0f64, 1f64),
+        5 => Vector3::new(-1f64, 0f64, 1f64),
+        6 => Vector3::new(1f64, 0f64, -1f64),
+        7 => Vector3::new(-1f64, 0f64, -1f64),
+        8 => Vector3::new(0f64, 1f64, 1f64),
+        9 => Vector3::new(0f64, -1f64, 1f64),
+        10 => Vector3::new(0f64, 1f64, -1f64),
+        _ => Vector3::new(0f64, -1f64, -1f64),
+    }
+}
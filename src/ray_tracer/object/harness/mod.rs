@@ -0,0 +1,389 @@
+extern crate cgmath;
+extern crate rand;
+
+use self::cgmath::*;
+use self::rand::Rng;
+
+use ray_tracer::color::Radiance;
+use ray_tracer::math;
+use ray_tracer::ray::Ray;
+use super::Shape;
+use super::material::Material;
+use super::sphere::Sphere;
+use super::triangle::Triangle;
+
+// Generators of random rays and shapes, and the invariants every intersection routine
+// must keep - checked over seeded cases by the unit tests, and over arbitrary input by
+// fuzzers through fuzz()
+
+// Fuzzed coordinates beyond this are skipped - the checks' tolerances assume f64 has
+// precision to spare at the scale of the scene
+const MAX_COORDINATE: f64 = 1e6;
+
+// Relative error allowed in distances and positions
+const TOLERANCE: f64 = 1e-8;
+
+// How far outside the edges a triangle hit may land, in barycentric weight
+const BARYCENTRIC_TOLERANCE: f64 = 1e-6;
+
+pub fn random_point<R: Rng>(rng: &mut R, scale: f64) -> Vector3<f64> {
+    Vector3::new(
+        (2f64 * rng.gen::<f64>() - 1f64) * scale,
+        (2f64 * rng.gen::<f64>() - 1f64) * scale,
+        (2f64 * rng.gen::<f64>() - 1f64) * scale,
+    )
+}
+
+// A normalized ray starting within scale of the origin
+pub fn random_ray<R: Rng>(rng: &mut R, scale: f64) -> Ray {
+    Ray::new(random_point(rng, scale), math::uniform_sphere(rng))
+}
+
+pub fn random_triangle<R: Rng>(rng: &mut R, scale: f64) -> Triangle {
+    loop {
+        let triangle: Triangle = Triangle::new(
+            random_point(rng, scale),
+            random_point(rng, scale),
+            random_point(rng, scale),
+            material(),
+        );
+
+        if well_conditioned(&Shape::Triangle(triangle)) {
+            return triangle;
+        }
+    }
+}
+
+pub fn random_sphere<R: Rng>(rng: &mut R, scale: f64) -> Sphere {
+    let radius: f64 = (0.01 + 0.99 * rng.gen::<f64>()) * scale;
+    Sphere::new(random_point(rng, scale), radius, material())
+}
+
+pub fn random_shape<R: Rng>(rng: &mut R, scale: f64) -> Shape {
+    if rng.gen::<bool>() {
+        Shape::Triangle(random_triangle(rng, scale))
+    } else {
+        Shape::Sphere(random_sphere(rng, scale))
+    }
+}
+
+fn material() -> Material {
+    Material::new(Radiance::new(1f64, 1f64, 1f64), 0f64, 0f64, 0f64, 1f64)
+}
+
+// Whether the checks can hold the shape to their tolerances - slivers and needles lose
+// too much precision in their normals and barycentric weights
+pub fn well_conditioned(shape: &Shape) -> bool {
+    match *shape {
+        Shape::Triangle(triangle) => {
+            let [p1, p2, p3]: [Vector3<f64>; 3] = triangle.vertices();
+            let longest: f64 = (p2 - p1)
+                .magnitude2()
+                .max((p3 - p2).magnitude2())
+                .max((p1 - p3).magnitude2());
+            let area: f64 = (p2 - p1).cross(p3 - p1).magnitude();
+
+            longest > 0f64 && area / longest > 1e-3
+        }
+        Shape::Sphere(sphere) => sphere.radius() > 0f64,
+    }
+}
+
+// A point on the surface, kept away from triangle edges
+pub fn surface_point<R: Rng>(shape: &Shape, rng: &mut R) -> Vector3<f64> {
+    match *shape {
+        Shape::Triangle(triangle) => {
+            let [p1, p2, p3]: [Vector3<f64>; 3] = triangle.vertices();
+            let root: f64 = rng.gen::<f64>().sqrt();
+            let v: f64 = rng.gen::<f64>();
+            let weights: Vector3<f64> = Vector3::new(1f64 - root, root * (1f64 - v), root * v)
+                * 0.98 + Vector3::new(0.02, 0.02, 0.02) / 3f64;
+
+            p1 * weights.x + p2 * weights.y + p3 * weights.z
+        }
+        Shape::Sphere(sphere) => sphere.origin() + math::uniform_sphere(rng) * sphere.radius(),
+    }
+}
+
+// A ray from outside the shape aimed at one of its surface points, which must be the
+// first hit, and the distance to that point
+pub fn aimed_ray<R: Rng>(shape: &Shape, rng: &mut R, scale: f64) -> (Ray, f64) {
+    let target: Vector3<f64> = surface_point(shape, rng);
+    let normal: Vector3<f64> = shape.geometric_normal(target);
+
+    loop {
+        let mut away: Vector3<f64> = math::uniform_sphere(rng);
+        if let Shape::Sphere(_) = *shape {
+            // From the side of the tangent plane facing out, so nothing is in the way
+            if away.dot(normal) < 0f64 {
+                away = -away;
+            }
+        }
+
+        // Grazing rays are left to check_hit
+        if away.dot(normal).abs() < 0.05 {
+            continue;
+        }
+
+        let distance: f64 = (0.1 + 1.9 * rng.gen::<f64>()) * scale;
+        let origin: Vector3<f64> = target + away * distance;
+
+        return (Ray::from_points(origin, target), distance);
+    }
+}
+
+fn tolerance(ray: &Ray, t: f64, shape: &Shape) -> f64 {
+    let size: f64 = match *shape {
+        Shape::Triangle(triangle) => triangle
+            .vertices()
+            .iter()
+            .map(|vertex| vertex.magnitude())
+            .fold(0f64, f64::max),
+        Shape::Sphere(sphere) => sphere.origin().magnitude() + sphere.radius(),
+    };
+
+    TOLERANCE * (1f64 + ray.origin.magnitude() + t.abs() + size)
+}
+
+// Any hit is in front of the ray, agrees with intersect() and lies on the surface
+pub fn check_hit(shape: &Shape, ray: &Ray) -> Result<(), String> {
+    let t: f64 = match (shape.intersection_distance(ray), shape.intersect(ray)) {
+        (None, None) => return Ok(()),
+        (Some(t), Some(point)) => {
+            let expected: Vector3<f64> = ray.origin + ray.direction * t;
+            if (point - expected).magnitude() > tolerance(ray, t, shape) {
+                return Err(format!("hit {:?} is not at t = {} along the ray", point, t));
+            }
+            t
+        }
+        (distance, point) => {
+            return Err(format!("distance {:?} disagrees with point {:?}", distance, point));
+        }
+    };
+
+    if !t.is_finite() || t < 0f64 {
+        return Err(format!("hit at t = {}, behind the ray", t));
+    }
+
+    let point: Vector3<f64> = ray.origin + ray.direction * t;
+    let limit: f64 = tolerance(ray, t, shape);
+
+    match *shape {
+        Shape::Triangle(triangle) => {
+            let [p1, _, _]: [Vector3<f64>; 3] = triangle.vertices();
+            let height: f64 = (point - p1).dot(triangle.normal).abs();
+            if height > limit {
+                return Err(format!("hit {:?} is {} off the triangle's plane", point, height));
+            }
+
+            let weights: Vector3<f64> = triangle.barycentric(point);
+            if weights.x.min(weights.y).min(weights.z) < -BARYCENTRIC_TOLERANCE {
+                return Err(format!("hit {:?} has weights {:?}, outside", point, weights));
+            }
+        }
+        Shape::Sphere(sphere) => {
+            let error: f64 = ((point - sphere.origin()).magnitude() - sphere.radius()).abs();
+            if error > limit {
+                return Err(format!("hit {:?} is {} off the sphere", point, error));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Moving the origin along the ray by a fraction of the way to the hit, forward or back,
+// moves the hit the same distance - and scaling a triangle ray's direction scales t
+pub fn check_monotonic(shape: &Shape, ray: &Ray, fraction: f64) -> Result<(), String> {
+    let t: f64 = match shape.intersection_distance(ray) {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+    let step: f64 = t * fraction;
+
+    for &(shift, expected) in &[(step, t - step), (-step, t + step)] {
+        let moved: Ray = Ray::new(ray.origin + ray.direction * shift, ray.direction);
+        match shape.intersection_distance(&moved) {
+            Some(moved_t) if (moved_t - expected).abs() <= tolerance(ray, t + step, shape) => {}
+            moved_t => {
+                return Err(format!(
+                    "origin moved {} from t = {} hits at {:?}, not {}",
+                    shift,
+                    t,
+                    moved_t,
+                    expected
+                ))
+            }
+        }
+    }
+
+    if let Shape::Triangle(_) = *shape {
+        let scaled: Ray = Ray::new(ray.origin, ray.direction * 2f64);
+        match shape.intersection_distance(&scaled) {
+            Some(scaled_t) if (scaled_t * 2f64 - t).abs() <= tolerance(ray, t, shape) => {}
+            scaled_t => {
+                return Err(format!(
+                    "doubled direction hits at {:?}, not half of t = {}",
+                    scaled_t,
+                    t
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// A ray aimed at a surface point hits it, at its distance
+pub fn check_aimed(shape: &Shape, ray: &Ray, distance: f64) -> Result<(), String> {
+    match shape.intersection_distance(ray) {
+        Some(t) if (t - distance).abs() <= tolerance(ray, distance, shape) => check_hit(shape, ray),
+        t => Err(format!("ray aimed {} away hits at {:?}", distance, t)),
+    }
+}
+
+// Entry point for fuzzers, e.g. a cargo-fuzz target calling fuzz(data).unwrap().
+// Decodes a shape, a ray and a step from little endian f64s and checks every invariant;
+// input too short, out of range or degenerate is accepted without checks
+pub fn fuzz(data: &[u8]) -> Result<(), String> {
+    let (kind, data): (&u8, &[u8]) = match data.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+
+    let values: Vec<f64> = data.chunks(8)
+        .filter(|chunk| chunk.len() == 8)
+        .map(|chunk| {
+            let bits: u64 = chunk
+                .iter()
+                .rev()
+                .fold(0u64, |bits, &byte| bits << 8 | u64::from(byte));
+            f64::from_bits(bits)
+        })
+        .collect();
+
+    let shape_length: usize = if kind % 2 == 0 { 9 } else { 4 };
+    if values.len() < shape_length + 7
+        || values
+            .iter()
+            .any(|value| !value.is_finite() || value.abs() > MAX_COORDINATE)
+    {
+        return Ok(());
+    }
+
+    let point = |i: usize| Vector3::new(values[i], values[i + 1], values[i + 2]);
+    let shape: Shape = if kind % 2 == 0 {
+        Shape::Triangle(Triangle::new(point(0), point(3), point(6), material()))
+    } else {
+        Shape::Sphere(Sphere::new(point(0), values[3].abs(), material()))
+    };
+
+    let direction: Vector3<f64> = point(shape_length + 3);
+    if !well_conditioned(&shape) || direction.magnitude() < 1e-6 {
+        return Ok(());
+    }
+
+    let ray: Ray = Ray::new(point(shape_length), direction.normalize());
+    let fraction: f64 = values[shape_length + 6].abs().fract();
+
+    check_hit(&shape, &ray)?;
+    check_monotonic(&shape, &ray, fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use self::rand::XorShiftRng;
+
+    const CASES: usize = 10_000;
+    const SCALE: f64 = 10f64;
+
+    fn run<F: FnMut(&mut XorShiftRng) -> Result<(), String>>(mut check: F) {
+        let mut rng: XorShiftRng = math::seeded_rng();
+        for case in 0..CASES {
+            if let Err(error) = check(&mut rng) {
+                panic!("case {}: {}", case, error);
+            }
+        }
+    }
+
+    #[test]
+    fn hits_lie_on_the_surface() {
+        run(|rng| {
+            let shape: Shape = random_shape(rng, SCALE);
+            check_hit(&shape, &random_ray(rng, SCALE * 2f64))
+        });
+    }
+
+    #[test]
+    fn hits_move_with_the_origin() {
+        run(|rng| {
+            let shape: Shape = random_shape(rng, SCALE);
+            let (ray, _): (Ray, f64) = aimed_ray(&shape, rng, SCALE);
+            let fraction: f64 = rng.gen::<f64>();
+            check_monotonic(&shape, &ray, fraction)?;
+            check_monotonic(&shape, &random_ray(rng, SCALE * 2f64), fraction)
+        });
+    }
+
+    #[test]
+    fn aimed_rays_hit() {
+        run(|rng| {
+            let shape: Shape = random_shape(rng, SCALE);
+            let (ray, distance): (Ray, f64) = aimed_ray(&shape, rng, SCALE);
+            check_aimed(&shape, &ray, distance)
+        });
+    }
+
+    #[test]
+    fn rays_starting_inside_a_sphere_miss() {
+        let sphere: Shape = Shape::Sphere(Sphere::new(Vector3::zero(), 1f64, material()));
+        let mut rng: XorShiftRng = math::seeded_rng();
+        for _ in 0..CASES {
+            let ray: Ray = Ray::new(random_point(&mut rng, 0.5), math::uniform_sphere(&mut rng));
+            assert_eq!(sphere.intersection_distance(&ray), None);
+        }
+    }
+
+    #[test]
+    fn rays_hit_either_side_of_a_triangle() {
+        let triangle: Shape = Shape::Triangle(Triangle::new(
+            Vector3::new(-1f64, -1f64, 0f64),
+            Vector3::new(1f64, -1f64, 0f64),
+            Vector3::new(0f64, 1f64, 0f64),
+            material(),
+        ));
+
+        for &z in &[-4f64, 4f64] {
+            let ray: Ray = Ray::from_points(Vector3::new(0f64, 0f64, z), Vector3::zero());
+            assert_eq!(triangle.intersection_distance(&ray), Some(4f64));
+        }
+
+        let away: Ray = Ray::new(Vector3::new(0f64, 0f64, 4f64), Vector3::unit_z());
+        assert_eq!(triangle.intersection_distance(&away), None);
+    }
+
+    #[test]
+    fn fuzz_accepts_random_input() {
+        let mut rng: XorShiftRng = math::seeded_rng();
+        for case in 0..CASES {
+            let length: usize = rng.gen_range(0, 160);
+            let mut data: Vec<u8> = (0..length).map(|_| rng.gen::<u8>()).collect();
+
+            // Mostly in range, so the checks run
+            for chunk in data[1.min(length)..].chunks_mut(8) {
+                if chunk.len() == 8 && rng.gen::<f64>() < 0.9 {
+                    let value: f64 = (2f64 * rng.gen::<f64>() - 1f64) * SCALE;
+                    let bits: u64 = value.to_bits();
+                    for (i, byte) in chunk.iter_mut().enumerate() {
+                        *byte = (bits >> (8 * i)) as u8;
+                    }
+                }
+            }
+
+            if let Err(error) = fuzz(&data) {
+                panic!("case {}: {}", case, error);
+            }
+        }
+    }
+}
@@ -1,8 +1,8 @@
-use ray_tracer::color::Color;
+use ray_tracer::color::Radiance;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Material {
-    pub color: Color,
+    pub color: Radiance,
     pub normal: f64,
     pub reflectance: f64,
     pub ambient_coefficient: f64,
@@ -13,7 +13,7 @@ pub struct Material {
 
 impl Material {
     pub fn new(
-        color: Color,
+        color: Radiance,
         reflectance: f64,
         ambient_coefficient: f64,
         specular_coefficient: f64,
@@ -2,6 +2,7 @@ extern crate cgmath;
 
 use self::cgmath::*;
 
+pub mod harness;
 pub mod material;
 pub mod sphere;
 pub mod triangle;
@@ -28,6 +29,57 @@ impl Shape {
         }
     }
 
+    // Normal as modeled, ignoring which side the ray hit - shows winding problems
+    pub fn geometric_normal(&self, intersection: Vector3<f64>) -> Vector3<f64> {
+        match *self {
+            Shape::Triangle(triangle) => triangle.normal,
+            Shape::Sphere(sphere) => (intersection - sphere.origin()).normalize(),
+        }
+    }
+
+    pub fn uv(&self, intersection: Vector3<f64>) -> Vector2<f64> {
+        match *self {
+            Shape::Triangle(triangle) => triangle.uv(intersection),
+            Shape::Sphere(sphere) => sphere.uv(intersection),
+        }
+    }
+
+    // Distance to the nearest edge - spheres have none
+    pub fn edge_distance(&self, intersection: Vector3<f64>) -> Option<f64> {
+        match *self {
+            Shape::Triangle(triangle) => Some(triangle.edge_distance(intersection)),
+            Shape::Sphere(_) => None,
+        }
+    }
+
+    pub fn object_id(&self) -> usize {
+        match *self {
+            Shape::Triangle(triangle) => triangle.object_id,
+            Shape::Sphere(sphere) => sphere.object_id,
+        }
+    }
+
+    // Scaled about the origin, e.g. from the units a model was made in to the scene's
+    pub fn scaled(self, factor: f64) -> Shape {
+        match self {
+            Shape::Triangle(triangle) => Shape::Triangle(triangle.scaled(factor)),
+            Shape::Sphere(sphere) => Shape::Sphere(sphere.scaled(factor)),
+        }
+    }
+
+    pub fn with_object_id(self, object_id: usize) -> Shape {
+        match self {
+            Shape::Triangle(mut triangle) => {
+                triangle.object_id = object_id;
+                Shape::Triangle(triangle)
+            }
+            Shape::Sphere(mut sphere) => {
+                sphere.object_id = object_id;
+                Shape::Sphere(sphere)
+            }
+        }
+    }
+
     pub fn intersect(&self, ray: &Ray) -> Option<Vector3<f64>> {
         match *self {
             Shape::Triangle(triangle) => triangle.intersect(ray),
@@ -35,6 +87,14 @@ impl Shape {
         }
     }
 
+    // Distance along the ray to the hit, without constructing the point
+    pub fn intersection_distance(&self, ray: &Ray) -> Option<f64> {
+        match *self {
+            Shape::Triangle(triangle) => triangle.intersection_distance(ray),
+            Shape::Sphere(sphere) => sphere.intersection_distance(ray),
+        }
+    }
+
     pub fn material(&self) -> material::Material {
         match *self {
             Shape::Triangle(triangle) => triangle.material,
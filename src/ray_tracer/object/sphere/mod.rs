@@ -11,6 +11,8 @@ pub struct Sphere {
     origin: Vector3<f64>,
     radius: f64,
     pub material: Material,
+    // Index of the configured object this sphere came from
+    pub object_id: usize,
 }
 
 impl Sphere {
@@ -19,9 +21,18 @@ impl Sphere {
             origin,
             radius,
             material,
+            object_id: 0,
         }
     }
 
+    pub fn origin(&self) -> Vector3<f64> {
+        self.origin
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
     pub fn normal(
         &self,
         intersection: Vector3<f64>,
@@ -30,7 +41,18 @@ impl Sphere {
         (intersection - self.origin).normalize()
     }
 
+    pub fn scaled(mut self, factor: f64) -> Sphere {
+        self.origin *= factor;
+        self.radius *= factor;
+        self
+    }
+
     pub fn intersect(&self, ray: &Ray) -> Option<Vector3<f64>> {
+        self.intersection_distance(ray).map(|t| ray.origin + ray.direction * t)
+    }
+
+    // Distance along a normalized ray to the near side - rays starting inside miss
+    pub fn intersection_distance(&self, ray: &Ray) -> Option<f64> {
         let diff = self.origin - ray.origin;
 
         let tca: f64 = diff.dot(ray.direction);
@@ -55,11 +77,17 @@ impl Sphere {
             return None;
         }
 
-        Some(Vector3::new(
-            ray.origin.x + ray.direction.x * t,
-            ray.origin.y + ray.direction.y * t,
-            ray.origin.z + ray.direction.z * t,
-        ))
+        Some(t)
+    }
+
+    // Longitude and latitude, both from 0 to 1
+    pub fn uv(&self, point: Vector3<f64>) -> Vector2<f64> {
+        let direction: Vector3<f64> = (point - self.origin).normalize();
+
+        Vector2::new(
+            0.5 + direction.z.atan2(direction.x) / (2f64 * f64::consts::PI),
+            0.5 - direction.y.clamp(-1f64, 1f64).asin() / f64::consts::PI,
+        )
     }
 
     pub fn min_extent(&self) -> Vector3<f64> {
@@ -5,6 +5,10 @@ use self::cgmath::*;
 use ray_tracer::ray::Ray;
 use super::material::Material;
 
+// Squared determinant, relative to the ray's and edges' squared lengths, below which a ray is
+// taken as parallel to the triangle
+const PARALLEL_TOLERANCE: f64 = 1e-22;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Triangle {
     p1: Vector3<f64>,
@@ -12,6 +16,10 @@ pub struct Triangle {
     p3: Vector3<f64>,
     pub material: Material,
     pub normal: Vector3<f64>,
+    // Per-vertex texture coordinates from the .obj file
+    pub texture_coordinates: Option<[Vector2<f64>; 3]>,
+    // Index of the configured object this triangle came from
+    pub object_id: usize,
 }
 
 impl Triangle {
@@ -27,9 +35,57 @@ impl Triangle {
             p3,
             material,
             normal: (p2 - p1).cross(p3 - p1).normalize(),
+            texture_coordinates: None,
+            object_id: 0,
         }
     }
 
+    pub fn with_texture_coordinates(mut self, texture_coordinates: [Vector2<f64>; 3]) -> Triangle {
+        self.texture_coordinates = Some(texture_coordinates);
+        self
+    }
+
+    // Weights of p1, p2 and p3 for a point on the triangle
+    pub fn barycentric(&self, point: Vector3<f64>) -> Vector3<f64> {
+        let v1v2: Vector3<f64> = self.p2 - self.p1;
+        let v1v3: Vector3<f64> = self.p3 - self.p1;
+        let v1p: Vector3<f64> = point - self.p1;
+
+        let d00: f64 = v1v2.dot(v1v2);
+        let d01: f64 = v1v2.dot(v1v3);
+        let d11: f64 = v1v3.dot(v1v3);
+        let d20: f64 = v1p.dot(v1v2);
+        let d21: f64 = v1p.dot(v1v3);
+        let denominator: f64 = d00 * d11 - d01 * d01;
+
+        let v: f64 = (d11 * d20 - d01 * d21) / denominator;
+        let w: f64 = (d00 * d21 - d01 * d20) / denominator;
+
+        Vector3::new(1f64 - v - w, v, w)
+    }
+
+    // Interpolated texture coordinates - the barycentric coordinates if the mesh has none
+    pub fn uv(&self, point: Vector3<f64>) -> Vector2<f64> {
+        let weights: Vector3<f64> = self.barycentric(point);
+
+        match self.texture_coordinates {
+            Some([uv1, uv2, uv3]) => uv1 * weights.x + uv2 * weights.y + uv3 * weights.z,
+            None => Vector2::new(weights.y, weights.z),
+        }
+    }
+
+    // Distance from a point on the triangle to its nearest edge
+    pub fn edge_distance(&self, point: Vector3<f64>) -> f64 {
+        [(self.p1, self.p2), (self.p2, self.p3), (self.p3, self.p1)]
+            .iter()
+            .map(|&(a, b)| (point - a).cross((b - a).normalize()).magnitude())
+            .fold(f64::MAX, f64::min)
+    }
+
+    pub fn vertices(&self) -> [Vector3<f64>; 3] {
+        [self.p1, self.p2, self.p3]
+    }
+
     pub fn normal(
         &self,
         intersection: Vector3<f64>,
@@ -42,16 +98,33 @@ impl Triangle {
         }
     }
 
+    // Keeps the normal, material and texture coordinates
+    pub fn scaled(mut self, factor: f64) -> Triangle {
+        self.p1 *= factor;
+        self.p2 *= factor;
+        self.p3 *= factor;
+        self
+    }
+
     pub fn intersect(&self, ray: &Ray) -> Option<Vector3<f64>> {
-        let eps: f64 = 0.000_000_000_01;
+        self.intersection_distance(ray).map(|t| ray.origin + ray.direction * t)
+    }
 
+    // Distance along the ray to the hit, in units of its direction - Möller-Trumbore
+    pub fn intersection_distance(&self, ray: &Ray) -> Option<f64> {
         let v1v2: Vector3<f64> = self.p2 - self.p1;
         let v1v3: Vector3<f64> = self.p3 - self.p1;
 
         let h: Vector3<f64> = ray.direction.cross(v1v3);
         let a: f64 = v1v2.dot(h);
 
-        if a.abs() < eps {
+        // Parallel to the plane - compared with the lengths involved, squared to avoid roots,
+        // so the same rays are rejected whatever the scene's units
+        if a * a <= PARALLEL_TOLERANCE
+            * ray.direction.magnitude2()
+            * v1v2.magnitude2()
+            * v1v3.magnitude2()
+        {
             return None;
         }
 
@@ -74,12 +147,9 @@ impl Triangle {
         // t: Distance along ray to intersection
         let t: f64 = f * v1v3.dot(q);
 
-        if t > eps {
-            return Some(Vector3::new(
-                ray.origin.x + ray.direction.x * t,
-                ray.origin.y + ray.direction.y * t,
-                ray.origin.z + ray.direction.z * t,
-            ));
+        // Secondary rays start surface_bias off the surface, so no distance tolerance is needed
+        if t > 0f64 {
+            return Some(t);
         }
 
         None
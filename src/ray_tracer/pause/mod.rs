@@ -0,0 +1,155 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::color::Radiance;
+use super::pixel_buffer::PixelBuffer;
+
+// Toggled by SIGUSR1 - render threads wait at their next pixel while it is set
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static SNAPSHOT_FILE: OnceLock<String> = OnceLock::new();
+
+#[cfg(unix)]
+extern "C" fn toggle(_: libc::c_int) {
+    PAUSED.fetch_xor(true, Ordering::SeqCst);
+}
+
+// Pause and resume renders on SIGUSR1, writing what has been drawn when they pause. Only
+// regular CPU renders install it - in other modes the signal's default action ends them
+#[cfg(unix)]
+pub fn install(snapshot_file: &str) {
+    let _ = SNAPSHOT_FILE.set(String::from(snapshot_file));
+
+    unsafe {
+        libc::signal(libc::SIGUSR1, toggle as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install(_snapshot_file: &str) {}
+
+pub fn paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+// Tone maps and saves a snapshot of the color buffer
+pub type SnapshotWriter = Box<dyn Fn(&[Vec<Radiance>], &str) -> io::Result<()> + Send + Sync>;
+
+// Gathers the render threads' pixels while they are paused, so the last to stop can write them
+pub struct PauseControl {
+    file: String,
+    writer: SnapshotWriter,
+    state: Mutex<SnapshotState>,
+}
+
+struct SnapshotState {
+    color_buffer: Vec<Vec<Radiance>>,
+    // Threads still drawing, and how many of them are waiting
+    running: usize,
+    waiting: usize,
+    written: bool,
+    // When the first thread stopped
+    paused_at: Option<Instant>,
+}
+
+impl PauseControl {
+    // None unless pausing was installed
+    pub fn new(
+        width: usize,
+        height: usize,
+        threads: usize,
+        writer: SnapshotWriter,
+    ) -> Option<PauseControl> {
+        SNAPSHOT_FILE.get().map(|file| PauseControl {
+            file: file.clone(),
+            writer,
+            state: Mutex::new(SnapshotState {
+                color_buffer: vec![vec![Radiance::black(); height]; width],
+                running: threads,
+                waiting: 0,
+                written: false,
+                paused_at: None,
+            }),
+        })
+    }
+
+    // Hand over the thread's pixels and block until resumed - the last thread to resume gets
+    // how long the render was paused, e.g. to extend its time limit
+    pub fn wait<I>(&self, pixels: I, pixel_buffer: &PixelBuffer) -> Option<Duration>
+    where
+        I: Iterator<Item = (usize, usize)>,
+    {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.copy(pixels, pixel_buffer);
+            if state.waiting == 0 {
+                state.paused_at = Some(Instant::now());
+            }
+            state.waiting += 1;
+            self.write_when_complete(&mut state);
+        }
+
+        while paused() {
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.waiting -= 1;
+        if state.waiting > 0 {
+            return None;
+        }
+
+        if state.written {
+            state.written = false;
+            info!("Resumed");
+        }
+        state.paused_at.take().map(|paused_at| paused_at.elapsed())
+    }
+
+    // Hand over a finished thread's pixels - it won't wait again
+    pub fn finish<I>(&self, pixels: I, pixel_buffer: &PixelBuffer)
+    where
+        I: Iterator<Item = (usize, usize)>,
+    {
+        let mut state = self.state.lock().unwrap();
+        state.copy(pixels, pixel_buffer);
+        state.running -= 1;
+
+        if paused() {
+            self.write_when_complete(&mut state);
+        }
+    }
+
+    // Once every running thread is waiting, the snapshot has all drawn pixels
+    fn write_when_complete(&self, state: &mut SnapshotState) {
+        if state.written || state.waiting < state.running {
+            return;
+        }
+
+        // Runs on a render thread holding the lock, so a failed write mustn't end the render
+        match (self.writer)(&state.color_buffer, &self.file) {
+            Ok(()) => info!(
+                "Paused - snapshot written to {}, send SIGUSR1 again to resume",
+                self.file
+            ),
+            Err(error) => error!(
+                "Paused - couldn't write snapshot: {}, send SIGUSR1 again to resume",
+                error
+            ),
+        }
+        state.written = true;
+    }
+}
+
+impl SnapshotState {
+    fn copy<I>(&mut self, pixels: I, pixel_buffer: &PixelBuffer)
+    where
+        I: Iterator<Item = (usize, usize)>,
+    {
+        for (x, y) in pixels {
+            self.color_buffer[x][y] = pixel_buffer.color(x, y);
+        }
+    }
+}
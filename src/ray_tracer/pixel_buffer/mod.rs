@@ -0,0 +1,171 @@
+use ray_tracer::color::Radiance;
+
+// The layer every render draws into
+pub const COLOR: &str = "color";
+
+// Channels of each pixel of a layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    // e.g. depth, alpha or sample variance
+    Scalar,
+    Uv,
+    Rgb,
+    Rgba,
+    // e.g. normals or positions
+    Xyz,
+}
+
+impl PixelFormat {
+    // Channel names, as written to EXR files
+    pub fn channels(&self) -> &'static [&'static str] {
+        match *self {
+            PixelFormat::Scalar => &["Y"],
+            PixelFormat::Uv => &["U", "V"],
+            PixelFormat::Rgb => &["R", "G", "B"],
+            PixelFormat::Rgba => &["R", "G", "B", "A"],
+            PixelFormat::Xyz => &["X", "Y", "Z"],
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels().len()
+    }
+}
+
+// A named image of one pixel format
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub name: String,
+    pub format: PixelFormat,
+    width: usize,
+    // Row-major, each pixel's channels together
+    values: Vec<f32>,
+}
+
+impl Layer {
+    fn new(name: &str, format: PixelFormat, width: usize, height: usize) -> Layer {
+        Layer {
+            name: String::from(name),
+            format,
+            width,
+            values: vec![0f32; width * height * format.channel_count()],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> &[f32] {
+        let start: usize = self.offset(x, y);
+        &self.values[start..start + self.format.channel_count()]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: &[f32]) {
+        let start: usize = self.offset(x, y);
+        let channels: usize = self.format.channel_count();
+        self.values[start..start + channels].copy_from_slice(&value[..channels]);
+    }
+
+    // One channel of every pixel, row-major
+    pub fn channel(&self, channel: usize) -> impl Iterator<Item = f32> + '_ {
+        self.values
+            .iter()
+            .skip(channel)
+            .step_by(self.format.channel_count())
+            .cloned()
+    }
+
+    fn offset(&self, x: usize, y: usize) -> usize {
+        (y * self.width + x) * self.format.channel_count()
+    }
+}
+
+// Named float layers of one size - the color a render draws plus whatever else is
+// recorded per pixel alongside it
+#[derive(Clone, Debug)]
+pub struct PixelBuffer {
+    width: usize,
+    height: usize,
+    layers: Vec<Layer>,
+}
+
+impl PixelBuffer {
+    // With an empty color layer
+    pub fn new(width: usize, height: usize) -> PixelBuffer {
+        let mut pixel_buffer: PixelBuffer = PixelBuffer {
+            width,
+            height,
+            layers: Vec::new(),
+        };
+        pixel_buffer.add_layer(COLOR, PixelFormat::Rgb);
+
+        pixel_buffer
+    }
+
+    pub fn from_radiance(
+        color_buffer: &[Vec<Radiance>],
+        width: usize,
+        height: usize,
+    ) -> PixelBuffer {
+        let mut pixel_buffer: PixelBuffer = PixelBuffer::new(width, height);
+        for (x, column) in color_buffer.iter().enumerate().take(width) {
+            for (y, color) in column.iter().enumerate().take(height) {
+                pixel_buffer.set_color(x, y, *color);
+            }
+        }
+
+        pixel_buffer
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width * self.height == 0
+    }
+
+    // Add a layer of zeros - an existing layer of the same name is replaced
+    pub fn add_layer(&mut self, name: &str, format: PixelFormat) -> &mut Layer {
+        self.layers.retain(|layer| layer.name != name);
+        self.layers.push(Layer::new(name, format, self.width, self.height));
+
+        self.layers.last_mut().unwrap()
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    pub fn color(&self, x: usize, y: usize) -> Radiance {
+        match self.layer(COLOR) {
+            Some(layer) => {
+                let pixel: &[f32] = layer.pixel(x, y);
+                Radiance::new(f64::from(pixel[0]), f64::from(pixel[1]), f64::from(pixel[2]))
+            }
+            None => Radiance::black(),
+        }
+    }
+
+    pub fn set_color(&mut self, x: usize, y: usize, color: Radiance) {
+        if let Some(layer) = self.layer_mut(COLOR) {
+            layer.set_pixel(x, y, &[color.r as f32, color.g as f32, color.b as f32]);
+        }
+    }
+
+    // The color layer, column-major as the rest of the renderer expects
+    pub fn to_radiance(&self) -> Vec<Vec<Radiance>> {
+        (0..self.width)
+            .map(|x| (0..self.height).map(|y| self.color(x, y)).collect())
+            .collect()
+    }
+}
@@ -0,0 +1,285 @@
+extern crate cgmath;
+extern crate serde_json;
+
+use self::cgmath::*;
+
+use std::mem;
+
+use numpy::{IntoPyArray, PyArray3};
+use numpy::ndarray::Array3;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+
+use super::camera::Camera;
+use super::color::{DisplayColor, Radiance};
+use super::object::material::Material;
+use super::reader::Reader;
+use super::scene::SceneBuilder;
+use super::scene::configuration::Configuration;
+
+// Python bindings - build with `maturin develop --features python`
+//
+// import raytracer
+// pixels = (raytracer.SceneBuilder()
+//     .resolution(320, 240)
+//     .add_sphere((0, 0, 0), 1.5, color=(200, 30, 30))
+//     .add_point_light((-5, 8, 8), 5.0)
+//     .render())   # numpy uint8 array, shape (height, width, 4)
+
+type Vector = (f64, f64, f64);
+type Rgb = (u8, u8, u8);
+
+fn vector(v: Vector) -> Vector3<f64> {
+    Vector3::new(v.0, v.1, v.2)
+}
+
+fn radiance(color: Rgb) -> Radiance {
+    Radiance::from_display(DisplayColor::new(color.0, color.1, color.2))
+}
+
+// Row-major (height, width, channels) array from a column-major color buffer
+fn pixel_array<T, F>(color_buffer: &[Vec<Radiance>], channels: usize, pixel: F) -> Array3<T>
+where
+    F: Fn(Radiance) -> Vec<T>,
+{
+    let width: usize = color_buffer.len();
+    let height: usize = color_buffer.first().map_or(0, |column| column.len());
+
+    let mut values: Vec<T> = Vec::with_capacity(width * height * channels);
+    for y in 0..height {
+        for column in color_buffer {
+            values.extend(pixel(column[y]));
+        }
+    }
+
+    Array3::from_shape_vec((height, width, channels), values).unwrap()
+}
+
+fn rgba_array(color_buffer: &[Vec<Radiance>]) -> Array3<u8> {
+    pixel_array(color_buffer, 4, |color| {
+        let color: DisplayColor = color.to_display();
+        vec![color.r, color.g, color.b, 255]
+    })
+}
+
+#[pyclass(name = "SceneBuilder")]
+#[derive(Clone, Default)]
+pub struct PySceneBuilder {
+    builder: SceneBuilder,
+}
+
+impl PySceneBuilder {
+    fn update<F: FnOnce(SceneBuilder) -> SceneBuilder>(&mut self, f: F) {
+        let builder: SceneBuilder = mem::take(&mut self.builder);
+        self.builder = f(builder);
+    }
+}
+
+#[pymethods]
+impl PySceneBuilder {
+    #[new]
+    fn new() -> PySceneBuilder {
+        PySceneBuilder::default()
+    }
+
+    fn camera(
+        mut slf: PyRefMut<'_, Self>,
+        origin: Vector,
+        target: Vector,
+        up: Vector,
+    ) -> PyRefMut<'_, Self> {
+        slf.update(|builder| builder.camera(Camera::new(vector(origin), vector(target), vector(up))));
+        slf
+    }
+
+    fn resolution(mut slf: PyRefMut<'_, Self>, width: usize, height: usize) -> PyRefMut<'_, Self> {
+        slf.update(|builder| builder.resolution(width, height));
+        slf
+    }
+
+    fn viewport(mut slf: PyRefMut<'_, Self>, width: f64, distance: f64) -> PyRefMut<'_, Self> {
+        slf.update(|builder| builder.viewport(width, distance));
+        slf
+    }
+
+    fn samples(mut slf: PyRefMut<'_, Self>, samples: usize) -> PyRefMut<'_, Self> {
+        slf.update(|builder| builder.samples(samples));
+        slf
+    }
+
+    fn max_reflections(mut slf: PyRefMut<'_, Self>, max_reflections: u8) -> PyRefMut<'_, Self> {
+        slf.update(|builder| builder.max_reflections(max_reflections));
+        slf
+    }
+
+    fn reinhard(mut slf: PyRefMut<'_, Self>, key_value: f64, delta: f64) -> PyRefMut<'_, Self> {
+        slf.update(|builder| builder.reinhard(key_value, delta));
+        slf
+    }
+
+    #[pyo3(signature = (
+        origin,
+        radius,
+        color = (255, 255, 255),
+        reflectance = 0.0,
+        ambient_coefficient = 0.2,
+        specular_coefficient = 0.4,
+        specular_exponent = 20.0
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_sphere(
+        mut slf: PyRefMut<'_, Self>,
+        origin: Vector,
+        radius: f64,
+        color: Rgb,
+        reflectance: f64,
+        ambient_coefficient: f64,
+        specular_coefficient: f64,
+        specular_exponent: f64,
+    ) -> PyRefMut<'_, Self> {
+        let material: Material = Material::new(
+            radiance(color),
+            reflectance,
+            ambient_coefficient,
+            specular_coefficient,
+            specular_exponent,
+        );
+
+        slf.update(|builder| builder.add_sphere(vector(origin), radius, material));
+        slf
+    }
+
+    #[pyo3(signature = (
+        p1,
+        p2,
+        p3,
+        color = (255, 255, 255),
+        reflectance = 0.0,
+        ambient_coefficient = 0.2,
+        specular_coefficient = 0.4,
+        specular_exponent = 20.0
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_triangle(
+        mut slf: PyRefMut<'_, Self>,
+        p1: Vector,
+        p2: Vector,
+        p3: Vector,
+        color: Rgb,
+        reflectance: f64,
+        ambient_coefficient: f64,
+        specular_coefficient: f64,
+        specular_exponent: f64,
+    ) -> PyRefMut<'_, Self> {
+        let material: Material = Material::new(
+            radiance(color),
+            reflectance,
+            ambient_coefficient,
+            specular_coefficient,
+            specular_exponent,
+        );
+
+        slf.update(|builder| builder.add_triangle(vector(p1), vector(p2), vector(p3), material));
+        slf
+    }
+
+    #[pyo3(signature = (
+        filename,
+        color = (255, 255, 255),
+        reflectance = 0.0,
+        ambient_coefficient = 0.2,
+        specular_coefficient = 0.4,
+        specular_exponent = 20.0
+    ))]
+    fn add_obj(
+        mut slf: PyRefMut<'_, Self>,
+        filename: &str,
+        color: Rgb,
+        reflectance: f64,
+        ambient_coefficient: f64,
+        specular_coefficient: f64,
+        specular_exponent: f64,
+    ) -> PyResult<PyRefMut<'_, Self>> {
+        let material: Material = Material::new(
+            radiance(color),
+            reflectance,
+            ambient_coefficient,
+            specular_coefficient,
+            specular_exponent,
+        );
+
+        let mut reader: Reader = Reader::new();
+        reader
+            .read_file(filename, material)
+            .map_err(|error| PyIOError::new_err(error.to_string()))?;
+
+        slf.update(|builder| builder.add_shapes(&reader.shapes));
+        Ok(slf)
+    }
+
+    #[pyo3(signature = (origin, intensity, color = (255, 255, 255)))]
+    fn add_point_light(
+        mut slf: PyRefMut<'_, Self>,
+        origin: Vector,
+        intensity: f64,
+        color: Rgb,
+    ) -> PyRefMut<'_, Self> {
+        slf.update(|builder| builder.add_point_light(vector(origin), intensity, radiance(color)));
+        slf
+    }
+
+    // Tone mapped uint8 array, shape (height, width, 4)
+    fn render<'py>(&self, py: Python<'py>) -> &'py PyArray3<u8> {
+        let builder: SceneBuilder = self.builder.clone();
+
+        let pixels: Array3<u8> = py.allow_threads(move || {
+            let mut scene = builder.build();
+            scene.draw();
+            rgba_array(&scene.tone_mapped())
+        });
+
+        pixels.into_pyarray(py)
+    }
+
+    // Linear float32 array without tone mapping, shape (height, width, 3)
+    fn render_hdr<'py>(&self, py: Python<'py>) -> &'py PyArray3<f32> {
+        let builder: SceneBuilder = self.builder.clone();
+
+        let pixels: Array3<f32> = py.allow_threads(move || {
+            let mut scene = builder.build();
+            scene.draw();
+
+            let color_buffer: Vec<Vec<Radiance>> = (0..scene.width())
+                .map(|x| (0..scene.height()).map(|y| scene.get_pixel(x, y)).collect())
+                .collect();
+
+            pixel_array(&color_buffer, 3, |color| {
+                vec![color.r as f32, color.g as f32, color.b as f32]
+            })
+        });
+
+        pixels.into_pyarray(py)
+    }
+}
+
+// Render a JSON scene configuration using all configured threads
+#[pyfunction]
+fn render_configuration<'py>(py: Python<'py>, configuration: &str) -> PyResult<&'py PyArray3<u8>> {
+    let configuration: Configuration = serde_json::from_str(configuration)
+        .map_err(|error| PyValueError::new_err(error.to_string()))?;
+
+    let color_buffer: Vec<Vec<Radiance>> = py
+        .allow_threads(move || super::render(&configuration))
+        .map_err(|error| PyIOError::new_err(error.to_string()))?;
+    let pixels: Array3<u8> = rgba_array(&color_buffer);
+
+    Ok(pixels.into_pyarray(py))
+}
+
+#[pymodule]
+fn raytracer(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PySceneBuilder>()?;
+    module.add_function(wrap_pyfunction!(render_configuration, module)?)?;
+
+    Ok(())
+}
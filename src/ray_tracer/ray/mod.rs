@@ -2,6 +2,8 @@ extern crate cgmath;
 
 use self::cgmath::*;
 
+use ray_tracer::math;
+
 #[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Vector3<f64>,
@@ -34,7 +36,7 @@ impl Ray {
     }
 
     pub fn reflect(direction: Vector3<f64>, normal: Vector3<f64>) -> Vector3<f64> {
-        direction - 2f64 * normal * direction.dot(normal)
+        math::reflect(direction, normal)
     }
 
     pub fn reflection(&self, normal: Vector3<f64>) -> Vector3<f64> {
@@ -4,21 +4,115 @@ extern crate cgmath;
 
 use self::cgmath::*;
 
-use std::io::{self, BufReader, ErrorKind};
-use std::io::prelude::*;
+use std::io::{self, BufRead, BufReader, ErrorKind};
 use std::fs::File;
+use std::mem;
 
+use super::kd_tree::bounding_box::BoundingBox;
 use super::object::triangle::Triangle;
 use super::object::sphere::Sphere;
 use super::object::material::Material;
 use super::object::Shape;
 
+// Shapes handed on at a time when streaming
+const CHUNK_SHAPES: usize = 64 * 1024;
+
 pub struct Reader {
     vertices: Vec<Vector3<f64>>,
     normals: Vec<Vector3<f64>>,
+    texture_coordinates: Vec<Vector2<f64>>,
     pub shapes: Vec<Shape>,
 }
 
+// .obj statements that read back as the given shapes - materials are not included
+pub fn statements(shapes: &[Shape]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut vertex_count: usize = 0;
+    let mut texture_coordinate_count: usize = 0;
+
+    for shape in shapes {
+        match *shape {
+            Shape::Triangle(ref triangle) => {
+                for vertex in &triangle.vertices() {
+                    lines.push(format!("v {} {} {}", vertex.x, vertex.y, vertex.z));
+                }
+
+                match triangle.texture_coordinates {
+                    Some(texture_coordinates) => {
+                        for uv in &texture_coordinates {
+                            lines.push(format!("vt {} {}", uv.x, uv.y));
+                        }
+                        lines.push(format!(
+                            "f {}/{} {}/{} {}/{}",
+                            vertex_count + 1,
+                            texture_coordinate_count + 1,
+                            vertex_count + 2,
+                            texture_coordinate_count + 2,
+                            vertex_count + 3,
+                            texture_coordinate_count + 3
+                        ));
+                        texture_coordinate_count += 3;
+                    }
+                    None => lines.push(format!(
+                        "f {} {} {}",
+                        vertex_count + 1,
+                        vertex_count + 2,
+                        vertex_count + 3
+                    )),
+                }
+                vertex_count += 3;
+            }
+            Shape::Sphere(ref sphere) => {
+                let origin: Vector3<f64> = sphere.origin();
+                lines.push(format!(
+                    "sphere {} {} {} {}",
+                    origin.x,
+                    origin.y,
+                    origin.z,
+                    sphere.radius()
+                ));
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+// Bounds of the vertices and spheres in .obj statements, without building any shapes - None
+// when there are neither. Unused vertices count too, so this may be larger than the shapes'
+pub fn bounds<R: BufRead>(contents: R) -> Result<Option<BoundingBox>, io::Error> {
+    let mut min: Vector3<f64> = Vector3::new(f64::MAX, f64::MAX, f64::MAX);
+    let mut max: Vector3<f64> = Vector3::new(f64::MIN, f64::MIN, f64::MIN);
+    let mut empty: bool = true;
+
+    for line in contents.lines() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+
+        let statement: &str = match tokens.next() {
+            Some(statement @ "v") | Some(statement @ "sphere") => statement,
+            _ => continue,
+        };
+        let values: Vec<f64> = tokens.map(parse_float).collect::<Result<Vec<f64>, io::Error>>()?;
+        let needed: usize = if statement == "sphere" { 4 } else { 3 };
+        if values.len() < needed {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!("Expected {} values: {}", needed, line),
+            ));
+        }
+
+        let radius: f64 = if statement == "sphere" { values[3] } else { 0f64 };
+        for axis in 0..3 {
+            min[axis] = min[axis].min(values[axis] - radius);
+            max[axis] = max[axis].max(values[axis] + radius);
+        }
+        empty = false;
+    }
+
+    Ok(if empty { None } else { Some(BoundingBox::new(min, max)) })
+}
+
 enum FaceIndex {
     V(usize),
     VN(usize, usize),
@@ -35,11 +129,11 @@ fn parse_float(f: &str) -> Result<f64, ::std::io::Error> {
 }
 
 fn parse_index(i: &str) -> Result<usize, ::std::io::Error> {
-    match i.parse() {
-        Ok(n) => {
-            let index: usize = n;
-            Ok(n - 1)
-        }
+    // Indices count from 1
+    match i.parse::<usize>() {
+        Ok(n) => n.checked_sub(1).ok_or_else(|| {
+            ::std::io::Error::new(ErrorKind::Other, format!("Index out of range: {}", i))
+        }),
         Err(msg) => Err(::std::io::Error::new(
             ErrorKind::Other,
             format!("Error parsing integer: {} : {}", i, msg),
@@ -47,15 +141,26 @@ fn parse_index(i: &str) -> Result<usize, ::std::io::Error> {
     }
 }
 
-fn parse_face_indices(f: &str) -> Result<usize, ::std::io::Error> {
-    if let Some(index) = f.split("//").next() {
-        parse_index(index)
-    } else {
-        Err(::std::io::Error::new(
-            ErrorKind::Other,
-            format!("Unknown face specification: {}", f),
-        ))
-    }
+// v, v/vt, v//vn or v/vt/vn - returns the vertex and texture coordinate indices
+fn parse_face_vertex(f: &str) -> Result<(usize, Option<usize>), ::std::io::Error> {
+    let mut indices = f.split('/');
+
+    let vertex: usize = match indices.next() {
+        Some(index) => parse_index(index)?,
+        None => {
+            return Err(::std::io::Error::new(
+                ErrorKind::Other,
+                format!("Unknown face specification: {}", f),
+            ))
+        }
+    };
+
+    let texture_coordinate: Option<usize> = match indices.next() {
+        Some(index) if !index.is_empty() => Some(parse_index(index)?),
+        _ => None,
+    };
+
+    Ok((vertex, texture_coordinate))
 }
 
 impl Reader {
@@ -63,16 +168,60 @@ impl Reader {
         Reader {
             vertices: Vec::new(),
             normals: Vec::new(),
+            texture_coordinates: Vec::new(),
             shapes: Vec::new(),
         }
     }
 
+    // Texture coordinates are only kept when every corner has them
+    fn add_triangle(&mut self, corners: [&str; 3], material: Material) -> Result<(), io::Error> {
+        let mut vertices: Vec<Vector3<f64>> = Vec::with_capacity(3);
+        let mut texture_coordinates: Vec<Vector2<f64>> = Vec::with_capacity(3);
+
+        for corner in &corners {
+            let (vertex, texture_coordinate) = parse_face_vertex(corner)?;
+            vertices.push(*self.vertices.get(vertex).ok_or_else(|| {
+                io::Error::new(ErrorKind::Other, format!("Undefined vertex: {}", corner))
+            })?);
+
+            if let Some(uv) = texture_coordinate.and_then(|index| self.texture_coordinates.get(index)) {
+                texture_coordinates.push(*uv);
+            }
+        }
+
+        let mut triangle: Triangle = Triangle::new(vertices[0], vertices[1], vertices[2], material);
+        if texture_coordinates.len() == 3 {
+            triangle = triangle.with_texture_coordinates([
+                texture_coordinates[0],
+                texture_coordinates[1],
+                texture_coordinates[2],
+            ]);
+        }
+
+        self.shapes.push(Shape::Triangle(triangle));
+
+        Ok(())
+    }
+
     fn eval(
         &mut self,
         statement: &str,
         args: Vec<&str>,
         material: Material,
     ) -> Result<(), ::std::io::Error> {
+        let values: usize = match statement {
+            "v" | "vn" | "f" => 3,
+            "vt" => 1,
+            "sphere" => 4,
+            _ => 0,
+        };
+        if args.len() < values {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!("Expected {} values: {} {}", values, statement, args.join(" ")),
+            ));
+        }
+
         match statement {
             "v" => {
                 self.vertices.push(Vector3::new(
@@ -83,6 +232,14 @@ impl Reader {
 
                 Ok(())
             }
+            "vt" => {
+                self.texture_coordinates.push(Vector2::new(
+                    parse_float(args[0])?,
+                    parse_float(args.get(1).cloned().unwrap_or("0"))?,
+                ));
+
+                Ok(())
+            }
             "vn" => {
                 self.normals.push(Vector3::new(
                     parse_float(args[0])?,
@@ -103,21 +260,11 @@ impl Reader {
                         ),
                     ))
                 } else {
-                    self.shapes.push(Shape::Triangle(Triangle::new(
-                        self.vertices[parse_face_indices(args[0])?],
-                        self.vertices[parse_face_indices(args[1])?],
-                        self.vertices[parse_face_indices(args[2])?],
-                        material,
-                    )));
+                    self.add_triangle([args[0], args[1], args[2]], material)?;
 
                     if args.len() == 4 {
                         // Object is a rectangle - Make two triangles
-                        self.shapes.push(Shape::Triangle(Triangle::new(
-                            self.vertices[parse_face_indices(args[2])?],
-                            self.vertices[parse_face_indices(args[3])?],
-                            self.vertices[parse_face_indices(args[0])?],
-                            material,
-                        )));
+                        self.add_triangle([args[2], args[3], args[0]], material)?;
                     }
 
                     Ok(())
@@ -147,10 +294,12 @@ impl Reader {
         }
     }
 
-    fn parse(
+    // Shapes are kept in self.shapes, or handed to sink in chunks when given one
+    fn parse<R: BufRead>(
         &mut self,
-        file_contents: BufReader<File>,
+        file_contents: R,
         material: Material,
+        mut sink: Option<&mut dyn FnMut(Vec<Shape>) -> io::Result<()>>,
     ) -> Result<(), ::std::io::Error> {
         for line in file_contents.lines() {
             let line = line?;
@@ -163,17 +312,51 @@ impl Reader {
                     args.push(t);
                 }
 
-                if let Err(error) = self.eval(statement, args, material) {
-                    panic!("{}", error)
+                self.eval(statement, args, material)?;
+            }
+
+            if let Some(ref mut sink) = sink {
+                if self.shapes.len() >= CHUNK_SHAPES {
+                    sink(mem::take(&mut self.shapes))?;
                 }
             }
         }
 
+        if let Some(sink) = sink {
+            sink(mem::take(&mut self.shapes))?;
+        }
+
         Ok(())
     }
 
     pub fn read_file(&mut self, filename: &str, material: Material) -> Result<(), io::Error> {
         let file_contents = BufReader::new(File::open(filename)?);
-        self.parse(file_contents, material)
+        self.parse(file_contents, material, None)
+    }
+
+    // Parse .obj statements held in memory
+    pub fn read_str(&mut self, contents: &str, material: Material) -> Result<(), io::Error> {
+        self.parse(contents.as_bytes(), material, None)
+    }
+
+    // Hand the file's shapes to sink a chunk at a time instead of keeping them - only the
+    // vertices are held for the whole file
+    pub fn stream_file(
+        &mut self,
+        filename: &str,
+        material: Material,
+        sink: &mut dyn FnMut(Vec<Shape>) -> io::Result<()>,
+    ) -> Result<(), io::Error> {
+        let file_contents = BufReader::new(File::open(filename)?);
+        self.parse(file_contents, material, Some(sink))
+    }
+
+    pub fn stream_str(
+        &mut self,
+        contents: &str,
+        material: Material,
+        sink: &mut dyn FnMut(Vec<Shape>) -> io::Result<()>,
+    ) -> Result<(), io::Error> {
+        self.parse(contents.as_bytes(), material, Some(sink))
     }
 }
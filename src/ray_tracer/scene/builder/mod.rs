@@ -0,0 +1,243 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use std::sync::Arc;
+
+use super::{Scene, SceneCharacteristics, SceneContents, ViewCharacteristics};
+
+use ray_tracer::camera::Camera;
+use ray_tracer::clipping::ClippingPlane;
+use ray_tracer::color::Radiance;
+use ray_tracer::edge_overlay::EdgeOverlay;
+use ray_tracer::kd_tree::KdTree;
+use ray_tracer::light::Light;
+use ray_tracer::light::hemisphere::HemisphereLight;
+use ray_tracer::object::Shape;
+use ray_tracer::object::material::Material;
+use ray_tracer::object::sphere::Sphere;
+use ray_tracer::object::triangle::Triangle;
+use ray_tracer::tone::AutoExposure;
+use ray_tracer::units::Units;
+use ray_tracer::volume::Volume;
+
+// Construct a scene in code rather than from a configuration file
+//
+// SceneBuilder::new()
+//     .camera(camera)
+//     .add_sphere(origin, radius, material)
+//     .add_point_light(position, intensity, color)
+//     .build()
+#[derive(Clone)]
+pub struct SceneBuilder {
+    camera: Camera,
+    width: usize,
+    height: usize,
+    viewport_width: f64,
+    viewport_distance: f64,
+    samples: usize,
+    max_reflections: u8,
+    use_kd_tree: bool,
+    max_kd_tree_depth: usize,
+    reinhard_key_value: f64,
+    reinhard_delta: f64,
+    auto_exposure: Option<AutoExposure>,
+    shapes: Vec<Shape>,
+    lights: Vec<Light>,
+    clipping_planes: Vec<ClippingPlane>,
+    volumes: Vec<Volume>,
+    hemisphere_light: Option<HemisphereLight>,
+    edge_overlay: Option<EdgeOverlay>,
+    aperture: f64,
+    // Defaults to the camera target
+    focal_distance: Option<f64>,
+    // Each add_shape or add_shapes call is a separate object
+    objects: usize,
+}
+
+impl SceneBuilder {
+    // Defaults mirror configuration.json
+    pub fn new() -> SceneBuilder {
+        SceneBuilder {
+            camera: Camera::new(
+                Vector3::new(0f64, 0f64, 10f64),
+                Vector3::new(0f64, 0f64, 0f64),
+                Vector3::new(0f64, 1f64, 0f64),
+            ),
+            width: 400,
+            height: 400,
+            viewport_width: 1f64,
+            viewport_distance: 1f64,
+            samples: 1,
+            max_reflections: 5,
+            use_kd_tree: true,
+            max_kd_tree_depth: 20,
+            reinhard_key_value: 1.5,
+            reinhard_delta: 0.01,
+            auto_exposure: None,
+            shapes: Vec::new(),
+            lights: Vec::new(),
+            clipping_planes: Vec::new(),
+            volumes: Vec::new(),
+            hemisphere_light: None,
+            edge_overlay: None,
+            aperture: 0f64,
+            focal_distance: None,
+            objects: 0,
+        }
+    }
+
+    pub fn camera(mut self, camera: Camera) -> SceneBuilder {
+        self.camera = camera;
+        self
+    }
+
+    pub fn resolution(mut self, width: usize, height: usize) -> SceneBuilder {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn viewport(mut self, viewport_width: f64, viewport_distance: f64) -> SceneBuilder {
+        self.viewport_width = viewport_width;
+        self.viewport_distance = viewport_distance;
+        self
+    }
+
+    pub fn samples(mut self, samples: usize) -> SceneBuilder {
+        self.samples = samples;
+        self
+    }
+
+    pub fn max_reflections(mut self, max_reflections: u8) -> SceneBuilder {
+        self.max_reflections = max_reflections;
+        self
+    }
+
+    pub fn kd_tree(mut self, use_kd_tree: bool, max_kd_tree_depth: usize) -> SceneBuilder {
+        self.use_kd_tree = use_kd_tree;
+        self.max_kd_tree_depth = max_kd_tree_depth;
+        self
+    }
+
+    pub fn reinhard(mut self, key_value: f64, delta: f64) -> SceneBuilder {
+        self.reinhard_key_value = key_value;
+        self.reinhard_delta = delta;
+        self
+    }
+
+    pub fn depth_of_field(mut self, aperture: f64, focal_distance: f64) -> SceneBuilder {
+        self.aperture = aperture;
+        self.focal_distance = Some(focal_distance);
+        self
+    }
+
+    pub fn edge_overlay(mut self, edge_overlay: EdgeOverlay) -> SceneBuilder {
+        self.edge_overlay = Some(edge_overlay);
+        self
+    }
+
+    pub fn auto_exposure(mut self, auto_exposure: AutoExposure) -> SceneBuilder {
+        self.auto_exposure = Some(auto_exposure);
+        self
+    }
+
+    pub fn add_shape(mut self, shape: Shape) -> SceneBuilder {
+        self.shapes.push(shape.with_object_id(self.objects));
+        self.objects += 1;
+        self
+    }
+
+    pub fn add_shapes(mut self, shapes: &[Shape]) -> SceneBuilder {
+        let object_id: usize = self.objects;
+        self.shapes.extend(shapes.iter().map(|shape| shape.with_object_id(object_id)));
+        self.objects += 1;
+        self
+    }
+
+    pub fn add_sphere(self, origin: Vector3<f64>, radius: f64, material: Material) -> SceneBuilder {
+        self.add_shape(Shape::Sphere(Sphere::new(origin, radius, material)))
+    }
+
+    pub fn add_triangle(
+        self,
+        p1: Vector3<f64>,
+        p2: Vector3<f64>,
+        p3: Vector3<f64>,
+        material: Material,
+    ) -> SceneBuilder {
+        self.add_shape(Shape::Triangle(Triangle::new(p1, p2, p3, material)))
+    }
+
+    pub fn add_light(mut self, light: Light) -> SceneBuilder {
+        self.lights.push(light);
+        self
+    }
+
+    pub fn add_point_light(
+        self,
+        origin: Vector3<f64>,
+        intensity: f64,
+        color: Radiance,
+    ) -> SceneBuilder {
+        self.add_light(Light::new(origin, intensity, color))
+    }
+
+    pub fn hemisphere_light(mut self, hemisphere_light: HemisphereLight) -> SceneBuilder {
+        self.hemisphere_light = Some(hemisphere_light);
+        self
+    }
+
+    pub fn add_volume(mut self, volume: Volume) -> SceneBuilder {
+        self.volumes.push(volume);
+        self
+    }
+
+    pub fn add_clipping_plane(mut self, clipping_plane: ClippingPlane) -> SceneBuilder {
+        self.clipping_planes.push(clipping_plane);
+        self
+    }
+
+    pub fn build(self) -> Scene {
+        let kd_tree: KdTree = KdTree::new(&self.shapes, self.max_kd_tree_depth, 1);
+        let focal_distance: f64 = self.focal_distance
+            .unwrap_or_else(|| (self.camera.target - self.camera.origin).magnitude());
+
+        Scene::assemble(
+            self.camera,
+            SceneContents {
+                lights: Arc::new(self.lights),
+                kd_tree: Arc::new(kd_tree),
+                clipping_planes: self.clipping_planes,
+                volumes: Arc::new(self.volumes),
+                hemisphere_light: self.hemisphere_light,
+            },
+            SceneCharacteristics {
+                samples: self.samples,
+                max_reflections: self.max_reflections,
+                use_kd_tree: self.use_kd_tree,
+                reinhard_key_value: self.reinhard_key_value,
+                reinhard_delta: self.reinhard_delta,
+                auto_exposure: self.auto_exposure,
+                exposure: None,
+                debug_view: None,
+                edge_overlay: self.edge_overlay,
+                aperture: self.aperture,
+                focal_distance,
+                surface_bias: Units::default().surface_bias(),
+            },
+            ViewCharacteristics::new(
+                self.width,
+                self.height,
+                self.viewport_width,
+                self.viewport_distance,
+            ),
+        )
+    }
+}
+
+impl Default for SceneBuilder {
+    fn default() -> SceneBuilder {
+        SceneBuilder::new()
+    }
+}
@@ -0,0 +1,28 @@
+extern crate cgmath;
+
+extern crate serde;
+extern crate serde_json;
+
+use ray_tracer::clipping::ClippingPlane;
+use ray_tracer::color::{DisplayColor, Radiance};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClippingPlaneDefinition {
+    point: Vec<f64>,
+    // Points towards the geometry that is cut away
+    normal: Vec<f64>,
+    #[serde(default)]
+    cap_color: Option<Vec<u8>>,
+}
+
+impl ClippingPlaneDefinition {
+    pub fn as_clipping_plane(&self) -> ClippingPlane {
+        ClippingPlane::new(
+            super::Configuration::parse_vector(&self.point),
+            super::Configuration::parse_vector(&self.normal),
+            self.cap_color.as_ref().map(|color| {
+                Radiance::from_display(DisplayColor::from_slice(color))
+            }),
+        )
+    }
+}
@@ -3,26 +3,139 @@ extern crate cgmath;
 extern crate serde;
 extern crate serde_json;
 
-use ray_tracer::color::Color;
-use ray_tracer::light::Light;
+use std::io;
 
-#[derive(Serialize, Deserialize)]
+use ray_tracer::color::{DisplayColor, Radiance};
+use ray_tracer::light::{Light, ShadowStyle, Spot};
+use ray_tracer::light::gobo::Gobo;
+use ray_tracer::units::Units;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LightDefinition {
     position: Vec<f64>,
     intensity: f64,
     color: Vec<u8>,
+    // Radius for soft shadow edges, even from a point light
+    #[serde(default)]
+    shadow_softness: f64,
+    // Light reaching shadowed points is filtered by this color
+    #[serde(default)]
+    shadow_color: Option<Vec<u8>>,
+    // 0 - 1, how much light shadows block
+    #[serde(default = "default_shadow_intensity")]
+    shadow_intensity: f64,
+    // Make this a spotlight
+    #[serde(default)]
+    spot: Option<SpotDefinition>,
+    // Fall off with the square of the distance, intensity being the light 1 meter away
+    #[serde(default)]
+    falloff: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpotDefinition {
+    direction: Vec<f64>,
+    // Full angle of the cone in degrees
+    cone_angle: f64,
+    // Fraction of the cone's radius over which its edge fades
+    #[serde(default = "default_penumbra")]
+    penumbra: f64,
+    #[serde(default)]
+    gobo: Option<GoboDefinition>,
+}
+
+// {"image": "gobos/window.png"} or
+// {"gradient": {"from": [255, 200, 120], "to": [80, 120, 255], "angle": 90}}
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GoboDefinition {
+    Image(String),
+    Gradient {
+        from: Vec<u8>,
+        to: Vec<u8>,
+        // Degrees from the light's u axis
+        #[serde(default)]
+        angle: f64,
+    },
+}
+
+fn default_shadow_intensity() -> f64 {
+    1f64
+}
+
+fn default_penumbra() -> f64 {
+    0.1
+}
+
+fn parse_color(color: &[u8]) -> Radiance {
+    Radiance::from_display(DisplayColor::from_slice(color))
 }
 
 impl LightDefinition {
-    pub fn as_light(&self) -> Light {
-        Light::new(
+    // The gobo image projected by the light, if any
+    pub fn image(&self) -> Option<&str> {
+        match self.spot.as_ref()?.gobo {
+            Some(GoboDefinition::Image(ref filename)) => Some(filename),
+            _ => None,
+        }
+    }
+
+    pub fn image_mut(&mut self) -> Option<&mut String> {
+        match self.spot.as_mut()?.gobo {
+            Some(GoboDefinition::Image(ref mut filename)) => Some(filename),
+            _ => None,
+        }
+    }
+
+    pub fn as_light(&self, units: Units) -> io::Result<Light> {
+        let light: Light = Light::new(
             super::Configuration::parse_vector(&self.position),
             self.intensity,
-            self.parsed_color(),
-        )
+            parse_color(&self.color),
+        ).with_shadow(ShadowStyle {
+            softness: self.shadow_softness,
+            color: self.shadow_color.as_ref().map_or_else(Radiance::black, |color| {
+                parse_color(color)
+            }),
+            intensity: self.shadow_intensity.clamp(0f64, 1f64),
+        });
+        let light: Light = if self.falloff {
+            light.with_falloff(units)
+        } else {
+            light
+        };
+
+        Ok(match self.spot {
+            Some(ref spot) => light.with_spot(spot.as_spot()?),
+            None => light,
+        })
     }
+}
+
+impl SpotDefinition {
+    fn as_spot(&self) -> io::Result<Spot> {
+        Ok(Spot::new(
+            super::Configuration::parse_vector(&self.direction),
+            self.cone_angle.to_radians(),
+            self.penumbra,
+            self.gobo.as_ref().map(|gobo| gobo.as_gobo()).transpose()?,
+        ))
+    }
+}
 
-    fn parsed_color(&self) -> Color {
-        Color::from_rgb(self.color[0], self.color[1], self.color[2])
+impl GoboDefinition {
+    fn as_gobo(&self) -> io::Result<Gobo> {
+        Ok(match *self {
+            GoboDefinition::Image(ref filename) => Gobo::load(filename)?,
+            GoboDefinition::Gradient {
+                ref from,
+                ref to,
+                angle,
+            } => Gobo::Gradient {
+                from: parse_color(from),
+                to: parse_color(to),
+                angle: angle.to_radians(),
+            },
+        })
     }
 }
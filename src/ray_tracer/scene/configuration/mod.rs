@@ -4,24 +4,83 @@ extern crate serde;
 extern crate serde_json;
 
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::path::Path;
+use std::thread;
 
-use cgmath::Vector3;
+use cgmath::{InnerSpace, Vector3};
 
-mod object_definition;
+mod clipping_plane_definition;
+pub mod object_definition;
 mod light_definition;
+mod overrides;
+mod volume_definition;
 
+use self::clipping_plane_definition::ClippingPlaneDefinition;
 use self::object_definition::ObjectDefinition;
 use self::light_definition::LightDefinition;
 
-use ray_tracer::camera::Camera;
+use ray_tracer::budget::RenderBudget;
+use ray_tracer::camera::{Camera, PhysicalCamera, Projection};
+use ray_tracer::lens::{LensDistortion, LensElement, LensSystem};
+use ray_tracer::light::hemisphere::HemisphereLight;
+use ray_tracer::debug_view::DebugView;
+use ray_tracer::edge_overlay::EdgeOverlay;
+use ray_tracer::kd_tree::bounding_box::BoundingBox;
+use ray_tracer::object::material::Material;
+use ray_tracer::stereo::Stereo;
+use ray_tracer::tone::AutoExposure;
+use ray_tracer::units::Units;
+use ray_tracer::visibility::ObjectVisibility;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Configuration {
+    // 0 uses one thread per available core
     pub threads: usize,
+    // Render at reduced scheduling priority
+    #[serde(default)]
+    pub low_priority: bool,
+    // Keep refining the image after the first pass until this many seconds have passed
+    #[serde(default)]
+    pub time_limit: Option<f64>,
+    // ... or until this many primary samples have been traced in total
+    #[serde(default)]
+    pub sample_budget: Option<usize>,
+    // Keep k-d tree geometry beyond this many MiB on disk
+    #[serde(default)]
+    pub memory_limit_mb: Option<usize>,
+    // Record per-pixel sample counts and timings
+    #[serde(default)]
+    pub diagnostics: bool,
+    // Write Cryptomatte object and material ID mattes
+    #[serde(default)]
+    pub cryptomatte: bool,
+    // Write per-pixel lists of depth, color and coverage samples
+    #[serde(default)]
+    pub deep: bool,
+    // Render a debug view instead of shading
+    #[serde(default)]
+    pub debug_view: Option<DebugView>,
+    // Draw triangle edges over the shaded image
+    #[serde(default)]
+    pub edge_overlay: Option<EdgeOverlay>,
+    // Render a left and right eye
+    #[serde(default)]
+    pub stereo: Option<Stereo>,
     pub samples: usize,
+    // Of the camera, lights and inline geometry - objects made in other units are converted
+    #[serde(default)]
+    pub units: Units,
     pub use_kd_tree: bool,
     pub max_kd_tree_depth: usize,
+    // k-d tree nodes with this many shapes or fewer become leaves
+    #[serde(default = "default_leaf_size")]
+    pub kd_tree_leaf_size: usize,
+    // Pick k-d tree and tile settings from earlier renders' profile (<output>.profile.json),
+    // and first pass samples that leave a time limit room to refine
+    #[serde(default)]
+    pub auto_tune: bool,
     pub width: usize,
     pub height: usize,
     pub camera_position: Vec<f64>,
@@ -29,11 +88,50 @@ pub struct Configuration {
     pub camera_up: Vec<f64>,
     pub viewport_distance: f64,
     pub viewport_width: f64,
+    // Extra frame rendered beyond each edge, as a percentage of the width and height, with
+    // the view widened to match. .exr outputs keep the configured frame as the display
+    // window and the extra pixels in the data window; other formats save all of it
+    #[serde(default)]
+    pub overscan: f64,
+    // Thin lens radius in scene units - 0 is a pinhole camera with everything in focus. The
+    // blur comes from supersamples, so it needs samples above 1
+    #[serde(default)]
+    pub aperture: f64,
+    // Focal length, sensor and exposure settings, used in place of viewport_width,
+    // aperture and automatic exposure
+    #[serde(default)]
+    pub physical_camera: Option<PhysicalCamera>,
+    // Perspective, or a fisheye dome master
+    #[serde(default)]
+    pub projection: Projection,
+    // Distortion of the footage the render should match
+    #[serde(default)]
+    pub lens_distortion: Option<LensDistortion>,
+    // Lens prescription traced in place of a pinhole or thin lens - needs the physical
+    // camera's sensor and unit_length
+    #[serde(default)]
+    pub lens_elements: Vec<LensElement>,
+    // Distance along the view direction that is in focus, defaults to the camera target
+    #[serde(default)]
+    pub focal_distance: Option<f64>,
     pub max_reflections: u8,
     pub reinhard_key_value: f64,
     pub reinhard_delta: f64,
+    // Expose for the lit pixels rather than the whole image
+    #[serde(default)]
+    pub auto_exposure: Option<AutoExposure>,
     pub objects: Vec<ObjectDefinition>,
     pub lights: Vec<LightDefinition>,
+    // Sky and ground fill in place of each light's ambient term
+    #[serde(default)]
+    pub hemisphere_light: Option<HemisphereLight>,
+    // Cut the scene open, e.g. for architectural sections
+    #[serde(default)]
+    pub clipping_planes: Vec<ClippingPlaneDefinition>,
+}
+
+fn default_leaf_size() -> usize {
+    1
 }
 
 impl Configuration {
@@ -46,11 +144,343 @@ impl Configuration {
     }
 
     pub fn read_configuration(filename: &str) -> Configuration {
-        let mut file = File::open(filename).unwrap();
+        Configuration::parse_configuration(filename).unwrap()
+    }
+
+    pub fn parse_configuration(filename: &str) -> Result<Configuration, io::Error> {
+        let mut file = File::open(filename)?;
         let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
+        file.read_to_string(&mut contents)?;
+
+        Configuration::from_json(&contents).map_err(|msg| {
+            io::Error::other(format!("Error parsing configuration: {} : {}", filename, msg))
+        })
+    }
+
+    // A configuration from JSON text, checked the same way whether it came from a file or
+    // another source
+    pub fn from_json(contents: &str) -> Result<Configuration, String> {
+        let configuration: Configuration =
+            serde_json::from_str(contents).map_err(|msg| msg.to_string())?;
+        if !configuration.lens_elements.is_empty() && configuration.lens_system().is_none() {
+            return Err(String::from("lens_elements need a physical_camera with unit_length"));
+        }
+
+        Ok(configuration)
+    }
+
+    // Every file rendering reads - meshes, detail levels, VDB grids and gobo images
+    pub fn files(&self) -> Vec<&str> {
+        self.objects
+            .iter()
+            .flat_map(|object| object.files())
+            .chain(self.lights.iter().filter_map(|light| light.image()))
+            .collect()
+    }
+
+    // The same files, for rewriting their paths
+    pub fn files_mut(&mut self) -> Vec<&mut String> {
+        self.objects
+            .iter_mut()
+            .flat_map(|object| object.files_mut())
+            .chain(self.lights.iter_mut().filter_map(|light| light.image_mut()))
+            .collect()
+    }
+
+    // Pretty printed, in the format parse_configuration reads
+    pub fn save(&self, filename: &str) -> Result<(), io::Error> {
+        let contents: String =
+            serde_json::to_string_pretty(self).map_err(|error| io::Error::other(error.to_string()))?;
+
+        File::create(filename)?.write_all(contents.as_bytes())
+    }
+
+    // The configuration as loaded, with every object's shapes inline
+    pub fn resolved(&self) -> Result<Configuration, io::Error> {
+        let mut resolved: Configuration = self.clone();
+        resolved.objects = self
+            .objects
+            .iter()
+            .map(|object| {
+                object.resolved(self.units, |bounding_box| self.projected_pixels(bounding_box))
+            })
+            .collect::<Result<Vec<ObjectDefinition>, io::Error>>()?;
+
+        Ok(resolved)
+    }
+
+    // With settings replaced by key path assignments, e.g. objects[0].color=[255,0,0]
+    pub fn with_overrides(&self, assignments: &[String]) -> Result<Configuration, io::Error> {
+        let mut value: serde_json::Value =
+            serde_json::to_value(self).map_err(|error| io::Error::other(error.to_string()))?;
+        for assignment in assignments {
+            overrides::apply(&mut value, assignment).map_err(io::Error::other)?;
+        }
+
+        let configuration: Configuration = serde_json::from_value(value).map_err(|error| {
+            io::Error::other(format!("Invalid overrides {}: {}", assignments.join(" "), error))
+        })?;
+
+        // Typos in settings that weren't set before were dropped while reading
+        let read: serde_json::Value = serde_json::to_value(&configuration)
+            .map_err(|error| io::Error::other(error.to_string()))?;
+        for assignment in assignments {
+            overrides::check(&read, assignment).map_err(io::Error::other)?;
+        }
+
+        Ok(configuration)
+    }
+
+    // Set a numeric top level setting in a configuration file, leaving the rest
+    // of the file as written
+    pub fn write_setting(filename: &str, key: &str, value: f64) -> Result<(), io::Error> {
+        let mut contents = String::new();
+        File::open(filename)?.read_to_string(&mut contents)?;
+
+        let updated: String = match top_level_value(&contents, key) {
+            // Replace the existing value
+            Some((value_start, value_end)) => {
+                format!("{}{}{}", &contents[..value_start], value, &contents[value_end..])
+            }
+            // Add it as the first entry
+            None => match contents.find('{') {
+                Some(brace) => {
+                    let indentation: String = contents[brace + 1..]
+                        .chars()
+                        .skip_while(|c| *c == '\n' || *c == '\r')
+                        .take_while(|c| *c == ' ' || *c == '\t')
+                        .collect();
+
+                    format!(
+                        "{}\n{}\"{}\": {},{}",
+                        &contents[..brace + 1],
+                        indentation,
+                        key,
+                        value,
+                        &contents[brace + 1..]
+                    )
+                }
+                None => contents.clone(),
+            },
+        };
+
+        // Don't replace a working configuration with a broken one
+        let unable = |msg: String| {
+            io::Error::other(format!("Unable to update {} in {}: {}", key, filename, msg))
+        };
+        if let Err(msg) = serde_json::from_str::<Configuration>(&updated) {
+            return Err(unable(msg.to_string()));
+        }
+        let written: serde_json::Value =
+            serde_json::from_str(&updated).map_err(|msg| unable(msg.to_string()))?;
+        if written.get(key).and_then(|written| written.as_f64()) != Some(value) {
+            return Err(unable(String::from("the setting wasn't changed")));
+        }
+
+        File::create(filename)?.write_all(updated.as_bytes())
+    }
+
+    // Pixels added beyond each horizontal and vertical edge by overscan
+    pub fn overscan_pixels(&self) -> (usize, usize) {
+        let fraction: f64 = self.overscan.max(0f64) / 100f64;
+
+        (
+            (self.width as f64 * fraction).round() as usize,
+            (self.height as f64 * fraction).round() as usize,
+        )
+    }
+
+    // The configuration with overscan pixels added around the frame and the viewport
+    // widened to match, so the original frame is unchanged in the middle
+    pub fn with_overscan(&self) -> Configuration {
+        let (x, y) = self.overscan_pixels();
+
+        let mut overscanned: Configuration = self.clone();
+        overscanned.width = self.width + 2 * x;
+        overscanned.height = self.height + 2 * y;
+        overscanned.set_viewport_width(
+            self.viewport_width() * overscanned.width as f64 / self.width as f64,
+        );
+        overscanned.overscan = 0f64;
+
+        overscanned
+    }
+
+    // Shrink the output resolution, e.g. for previews - the viewport is unchanged
+    pub fn scale_resolution(&mut self, scale: f64) {
+        self.width = ((self.width as f64 * scale).round() as usize).max(1);
+        self.height = ((self.height as f64 * scale).round() as usize).max(1);
+    }
+
+    // Pixels traced for the whole image, e.g. for progress reporting
+    pub fn pixel_count(&self) -> usize {
+        match self.stereo {
+            Some(ref stereo) => stereo.pixel_count(self.width, self.height),
+            None => self.width * self.height,
+        }
+    }
+
+    // A unique name per object for ID mattes - repeated names get the object's index appended
+    pub fn object_names(&self) -> Vec<String> {
+        self.objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let repeated: bool = self
+                    .objects
+                    .iter()
+                    .filter(|other| other.name() == object.name())
+                    .count() > 1;
+
+                if repeated {
+                    format!("{}.{}", object.name(), index)
+                } else {
+                    String::from(object.name())
+                }
+            })
+            .collect()
+    }
+
+    // Index of the object going by name - its unique name as in object_names, its own
+    // name, or its file's name with or without the extension
+    pub fn find_object(&self, name: &str) -> Option<usize> {
+        let names: Vec<String> = self.object_names();
 
-        serde_json::from_str(&contents).unwrap()
+        self.objects.iter().zip(&names).position(|(object, unique_name)| {
+            unique_name == name || object.name() == name ||
+                Path::new(&object.filename).file_stem().and_then(|stem| stem.to_str()) ==
+                    Some(name)
+        })
+    }
+
+    // Each object's index among the distinct materials, and a name per distinct material
+    pub fn material_groups(&self) -> (Vec<usize>, Vec<String>) {
+        let mut materials: Vec<Material> = Vec::new();
+        let groups: Vec<usize> = self
+            .objects
+            .iter()
+            .map(|object| {
+                let material: Material = object.material();
+                match materials.iter().position(|other| *other == material) {
+                    Some(index) => index,
+                    None => {
+                        materials.push(material);
+                        materials.len() - 1
+                    }
+                }
+            })
+            .collect();
+
+        let names: Vec<String> = (0..materials.len())
+            .map(|index| format!("material{}", index))
+            .collect();
+
+        (groups, names)
+    }
+
+    // Number of render threads to use
+    pub fn thread_count(&self) -> usize {
+        if self.threads > 0 {
+            return self.threads;
+        }
+
+        thread::available_parallelism().map_or(1, |threads| threads.get())
+    }
+
+    // A single thread draws pixels in a fixed order from a fixed seed,
+    // so repeated renders are identical
+    pub fn deterministic(&self) -> bool {
+        self.thread_count() == 1
+    }
+
+    // Distance along the view direction that is in focus
+    pub fn focal_distance(&self) -> f64 {
+        self.focal_distance.unwrap_or_else(|| {
+            let camera: Camera = self.camera();
+            (camera.target - camera.origin).magnitude()
+        })
+    }
+
+    // Approximate width in pixels of the bounds' enclosing sphere, seen from the camera
+    pub fn projected_pixels(&self, bounding_box: &BoundingBox) -> f64 {
+        let center: Vector3<f64> = (bounding_box.min + bounding_box.max) / 2f64;
+        let radius: f64 = (bounding_box.max - bounding_box.min).magnitude() / 2f64;
+        let distance: f64 = (center - Configuration::parse_vector(&self.camera_position)).magnitude();
+
+        if distance <= radius {
+            return f64::INFINITY;
+        }
+
+        2f64 * radius * self.viewport_distance / distance / self.viewport_width() * self.width as f64
+    }
+
+    // Width of the viewport, from the physical camera's lens if there is one
+    pub fn viewport_width(&self) -> f64 {
+        match self.physical_camera {
+            Some(ref camera) => camera.viewport_width(self.viewport_distance),
+            None => self.viewport_width,
+        }
+    }
+
+    pub fn set_viewport_width(&mut self, viewport_width: f64) {
+        match self.physical_camera {
+            Some(ref mut camera) => {
+                camera.set_viewport_width(viewport_width, self.viewport_distance)
+            }
+            None => self.viewport_width = viewport_width,
+        }
+    }
+
+    // Thin lens radius, from the physical camera's f-stop when it knows the scene's scale
+    pub fn aperture(&self) -> f64 {
+        self.physical_camera
+            .and_then(|camera| camera.aperture())
+            .unwrap_or(self.aperture)
+    }
+
+    // Refinement budget starting now, if the configuration sets one
+    pub fn render_budget(&self) -> io::Result<Option<RenderBudget>> {
+        if self.time_limit.is_none() && self.sample_budget.is_none() {
+            return Ok(None);
+        }
+
+        let first_pass: usize = self.width * self.height * self.samples;
+        RenderBudget::new(self.time_limit, self.sample_budget, first_pass).map(Some)
+    }
+
+    // Each object's ray limits, or none when no object has any
+    pub fn object_visibility(&self) -> Vec<ObjectVisibility> {
+        let visibility: Vec<ObjectVisibility> =
+            self.objects.iter().map(|object| object.visibility()).collect();
+
+        if visibility.iter().all(|object| *object == ObjectVisibility::default()) {
+            Vec::new()
+        } else {
+            visibility
+        }
+    }
+
+    // Traced lens focused at the focal distance, if the configuration has elements and
+    // a physical camera to size its sensor and scale it to the scene
+    pub fn lens_system(&self) -> Option<LensSystem> {
+        let camera: PhysicalCamera = self.physical_camera?;
+        let unit_length: f64 = camera.unit_length?;
+        if self.lens_elements.is_empty() {
+            return None;
+        }
+
+        Some(LensSystem::new(
+            &self.lens_elements,
+            camera.sensor_width,
+            camera.sensor_width * self.height as f64 / self.width as f64,
+            unit_length,
+            self.focal_distance(),
+        ))
+    }
+
+    // Fixed radiance scale before the tone curve, replacing automatic exposure
+    pub fn exposure(&self) -> Option<f64> {
+        self.physical_camera.map(|camera| camera.exposure())
     }
 
     pub fn camera(&self) -> Camera {
@@ -61,3 +491,78 @@ impl Configuration {
         )
     }
 }
+
+// Byte range of the value of a key in the outermost object of JSON text - keys of nested
+// objects and text inside strings don't match
+fn top_level_value(contents: &str, key: &str) -> Option<(usize, usize)> {
+    let bytes: &[u8] = contents.as_bytes();
+    let mut depth: usize = 0;
+    let mut value_start: Option<usize> = None;
+    let mut i: usize = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let string_start: usize = i + 1;
+                i = string_end(bytes, string_start)?;
+
+                // A key is followed by a colon, a string value isn't
+                let after: usize = skip_whitespace(bytes, i + 1);
+                if depth == 1
+                    && value_start.is_none()
+                    && bytes.get(after) == Some(&b':')
+                    && contents[string_start..i] == *key
+                {
+                    value_start = Some(skip_whitespace(bytes, after + 1));
+                    i = after;
+                }
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return value_start.map(|start| (start, trim_end(bytes, start, i)));
+                }
+            }
+            b',' if depth == 1 => {
+                if let Some(start) = value_start {
+                    return Some((start, trim_end(bytes, start, i)));
+                }
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+// Index of the quote closing a string starting at start
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i: usize = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i),
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+fn skip_whitespace(bytes: &[u8], start: usize) -> usize {
+    start + bytes[start.min(bytes.len())..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_whitespace())
+        .count()
+}
+
+// End of the value before end, without trailing whitespace
+fn trim_end(bytes: &[u8], start: usize, end: usize) -> usize {
+    start + bytes[start..end]
+        .iter()
+        .rposition(|byte| !byte.is_ascii_whitespace())
+        .map_or(0, |last| last + 1)
+}
@@ -3,36 +3,311 @@ extern crate cgmath;
 extern crate serde;
 extern crate serde_json;
 
-use ray_tracer::color::Color;
+use std::fs::File;
+use std::io::{self, BufReader};
+
+use ray_tracer::color::{DisplayColor, Radiance};
+use ray_tracer::generator::Generator;
+use ray_tracer::kd_tree::KdTree;
+use ray_tracer::kd_tree::bounding_box::BoundingBox;
 use ray_tracer::object::Shape;
 use ray_tracer::object::material::Material;
-use ray_tracer::reader::Reader;
+use ray_tracer::reader::{self, Reader};
+use ray_tracer::units::Units;
+use ray_tracer::visibility::ObjectVisibility;
+use ray_tracer::volume::{DensityField, Volume};
+
+use super::volume_definition::VolumeDefinition;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ObjectDefinition {
+    // Identifies the object in ID mattes and descriptions
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
     pub filename: String,
+    // Inline .obj statements, used instead of reading filename
+    pub contents: Option<String>,
+    // Procedural geometry, used instead of reading filename
+    #[serde(default)]
+    pub generator: Option<Generator>,
+    // What the object's coordinates were made in, if not the scene's units
+    #[serde(default)]
+    pub units: Option<Units>,
     pub color: Vec<u8>,
     pub reflectance: f64,
     pub ambient_coefficient: f64,
     pub specular_coefficient: f64,
     pub specular_exponent: f64,
+    // Treat the shapes as a container for fog, colored by color
+    #[serde(default)]
+    pub volume: Option<VolumeDefinition>,
+    // Simpler meshes used in place of the object's own when it appears small
+    #[serde(default)]
+    pub detail_levels: Vec<DetailLevel>,
+    // Reflection depth for rays reflecting off this object, if lower than the scene's
+    #[serde(default)]
+    pub max_reflections: Option<u8>,
+    // Rays that skip the object, as if it weren't there
+    #[serde(default = "visible")]
+    pub visible_to_camera: bool,
+    #[serde(default = "visible")]
+    pub visible_in_reflections: bool,
+    #[serde(default = "visible")]
+    pub casts_shadows: bool,
+}
+
+fn visible() -> bool {
+    true
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DetailLevel {
+    pub filename: String,
+    // Used when the object's bounds span at most this many pixels
+    pub max_pixels: f64,
 }
 
 impl ObjectDefinition {
-    pub fn read_shapes(&self) -> Vec<Shape> {
-        let mut r: Reader = Reader::new();
-        let material: Material = Material::new(
+    // Files the object's geometry or density is read from
+    pub fn files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = Vec::new();
+        if self.contents.is_none() && self.generator.is_none() {
+            files.push(&self.filename);
+        }
+        files.extend(self.detail_levels.iter().map(|level| level.filename.as_str()));
+        files.extend(self.volume.as_ref().and_then(|volume| volume.vdb.as_deref()));
+
+        files
+    }
+
+    pub fn files_mut(&mut self) -> Vec<&mut String> {
+        let mut files: Vec<&mut String> = Vec::new();
+        if self.contents.is_none() && self.generator.is_none() {
+            files.push(&mut self.filename);
+        }
+        files.extend(self.detail_levels.iter_mut().map(|level| &mut level.filename));
+        files.extend(self.volume.as_mut().and_then(|volume| volume.vdb.as_mut()));
+
+        files
+    }
+
+    pub fn material(&self) -> Material {
+        Material::new(
             self.parsed_color(),
             self.reflectance,
             self.ambient_coefficient,
             self.specular_coefficient,
             self.specular_exponent,
-        );
-        r.read_file(&(self.filename), material).unwrap();
-        r.shapes
+        )
+    }
+
+    pub fn visibility(&self) -> ObjectVisibility {
+        ObjectVisibility {
+            camera: self.visible_to_camera,
+            reflections: self.visible_in_reflections,
+            shadows: self.casts_shadows,
+            max_reflections: self.max_reflections,
+        }
+    }
+
+    // Shapes from the generator, .obj file or inline contents, in the scene's units - none
+    // for a VDB volume
+    pub fn read_shapes(&self, scene_units: Units) -> Result<Vec<Shape>, io::Error> {
+        let mut shapes: Vec<Shape> = Vec::new();
+        self.stream_shapes(scene_units, &mut |chunk| {
+            shapes.extend(chunk);
+            Ok(())
+        })?;
+
+        Ok(shapes)
+    }
+
+    // The shapes read_shapes returns, handed to sink a chunk at a time so a large file is
+    // never held whole
+    pub fn stream_shapes(
+        &self,
+        scene_units: Units,
+        sink: &mut dyn FnMut(Vec<Shape>) -> io::Result<()>,
+    ) -> Result<(), io::Error> {
+        if self.volume.as_ref().is_some_and(|volume| volume.vdb.is_some()) {
+            return Ok(());
+        }
+
+        let material: Material = self.material();
+        if let Some(ref generator) = self.generator {
+            return sink(self.to_scene_units(generator.shapes(material)?, scene_units));
+        }
+
+        let mut scaled = |shapes: Vec<Shape>| sink(self.to_scene_units(shapes, scene_units));
+        let mut r: Reader = Reader::new();
+        match self.contents {
+            Some(ref contents) => r.stream_str(contents, material, &mut scaled),
+            None => r.stream_file(&(self.filename), material, &mut scaled),
+        }
+        .map_err(|error| io::Error::other(format!("{}: {}", self.name(), error)))
+    }
+
+    fn to_scene_units(&self, shapes: Vec<Shape>, scene_units: Units) -> Vec<Shape> {
+        let scale: f64 = match self.units {
+            Some(units) if units != scene_units => units.scale_to(scene_units),
+            _ => return shapes,
+        };
+
+        shapes.into_iter().map(|shape| shape.scaled(scale)).collect()
+    }
+
+    // Bounds of the object's shapes in the scene's units - generators are expanded, but
+    // files and inline statements only have their vertices scanned
+    fn bounds(&self, scene_units: Units) -> Result<Option<BoundingBox>, io::Error> {
+        if self.generator.is_some() {
+            let shapes: Vec<Shape> = self.read_shapes(scene_units)?;
+            return Ok(if shapes.is_empty() {
+                None
+            } else {
+                Some(KdTree::scene_bounding_box(&shapes))
+            });
+        }
+
+        let bounds: Option<BoundingBox> = match self.contents {
+            Some(ref contents) => reader::bounds(contents.as_bytes()),
+            None => File::open(&self.filename).and_then(|file| reader::bounds(BufReader::new(file))),
+        }
+        .map_err(|error| io::Error::other(format!("{}: {}", self.name(), error)))?;
+
+        Ok(match self.units {
+            Some(units) if units != scene_units => {
+                let scale: f64 = units.scale_to(scene_units);
+                bounds.map(|bounds| BoundingBox::new(bounds.min * scale, bounds.max * scale))
+            }
+            _ => bounds,
+        })
+    }
+
+    // Shapes of the least detailed level whose max_pixels still covers the object's projected
+    // size, or the object's own shapes when it is larger than every level allows. Only the
+    // chosen mesh is read
+    pub fn read_detailed_shapes<F>(
+        &self,
+        scene_units: Units,
+        projected_pixels: F,
+    ) -> Result<Vec<Shape>, io::Error>
+    where
+        F: Fn(&BoundingBox) -> f64,
+    {
+        let mut shapes: Vec<Shape> = Vec::new();
+        self.stream_detailed_shapes(scene_units, projected_pixels, &mut |chunk| {
+            shapes.extend(chunk);
+            Ok(())
+        })?;
+
+        Ok(shapes)
+    }
+
+    // The shapes read_detailed_shapes returns, a chunk at a time
+    pub fn stream_detailed_shapes<F>(
+        &self,
+        scene_units: Units,
+        projected_pixels: F,
+        sink: &mut dyn FnMut(Vec<Shape>) -> io::Result<()>,
+    ) -> Result<(), io::Error>
+    where
+        F: Fn(&BoundingBox) -> f64,
+    {
+        if self.detail_levels.is_empty() {
+            return self.stream_shapes(scene_units, sink);
+        }
+
+        let pixels: f64 = match self.bounds(scene_units)? {
+            Some(bounds) => projected_pixels(&bounds),
+            None => return Ok(()),
+        };
+        let level: Option<&DetailLevel> = self
+            .detail_levels
+            .iter()
+            .filter(|level| pixels <= level.max_pixels)
+            .min_by(|a, b| a.max_pixels.total_cmp(&b.max_pixels));
+
+        match level {
+            Some(level) => {
+                let mut scaled =
+                    |shapes: Vec<Shape>| sink(self.to_scene_units(shapes, scene_units));
+                Reader::new()
+                    .stream_file(&level.filename, self.material(), &mut scaled)
+                    .map_err(|error| io::Error::other(format!("{}: {}", level.filename, error)))
+            }
+            None => self.stream_shapes(scene_units, sink),
+        }
+    }
+
+    // The object with the shapes it loads as inline statements - generators and detail
+    // levels are applied and removed, and the shapes converted to the scene's units. VDB
+    // volumes are left as they are.
+    pub fn resolved<F>(
+        &self,
+        scene_units: Units,
+        projected_pixels: F,
+    ) -> Result<ObjectDefinition, io::Error>
+    where
+        F: Fn(&BoundingBox) -> f64,
+    {
+        let shapes: Vec<Shape> = match self.volume {
+            Some(ref volume) if volume.vdb.is_some() => return Ok(self.clone()),
+            Some(_) => self.read_shapes(scene_units)?,
+            None => self.read_detailed_shapes(scene_units, projected_pixels)?,
+        };
+
+        let mut resolved: ObjectDefinition = self.clone();
+        resolved.name = Some(String::from(self.name()));
+        resolved.filename = String::new();
+        resolved.contents = Some(reader::statements(&shapes));
+        resolved.generator = None;
+        resolved.units = None;
+        resolved.detail_levels = Vec::new();
+
+        Ok(resolved)
+    }
+
+    // The fog filling this object, if it is a volume
+    pub fn as_volume(&self, scene_units: Units) -> Result<Option<Volume>, io::Error> {
+        let volume: &VolumeDefinition = match self.volume {
+            Some(ref volume) => volume,
+            None => return Ok(None),
+        };
+        let field: DensityField = volume
+            .field()
+            .map_err(|error| io::Error::other(format!("{}: {}", self.name(), error)))?;
+
+        // VDB grids are contained by their own bounds
+        let container: Vec<Shape> = match field {
+            DensityField::Grid(ref grid) => grid.container(self.material()),
+            _ => self.read_shapes(scene_units)?,
+        };
+
+        Ok(Some(Volume::new(
+            container,
+            self.parsed_color(),
+            self.ambient_coefficient,
+            volume.density,
+            volume.step_size,
+            field,
+        )))
+    }
+
+    // The configured name, otherwise a human readable source of the object's shapes
+    pub fn name(&self) -> &str {
+        if let Some(ref name) = self.name {
+            name
+        } else if self.generator.is_some() {
+            "(generated)"
+        } else if self.contents.is_some() {
+            "(inline)"
+        } else {
+            &self.filename
+        }
     }
 
-    fn parsed_color(&self) -> Color {
-        Color::from_rgb(self.color[0], self.color[1], self.color[2])
+    fn parsed_color(&self) -> Radiance {
+        Radiance::from_display(DisplayColor::from_slice(&self.color))
     }
 }
@@ -0,0 +1,260 @@
+extern crate serde_json;
+
+use self::serde_json::Value;
+
+// One step of a key path - a field of an object or an element of an array
+#[derive(Debug, PartialEq)]
+enum Step {
+    Field(String),
+    Index(usize),
+}
+
+// Set the setting at a key path, e.g. camera_position=[0,2,10] or
+// objects[3].specular_exponent=40, in a configuration as JSON. Paths use the
+// configuration's own names, with . into objects and [n] into lists, where one past the end
+// appends. Values are JSON, or else taken as a string, so name=car needs no quotes
+pub fn apply(configuration: &mut Value, assignment: &str) -> Result<(), String> {
+    let equals: usize = assignment
+        .find('=')
+        .ok_or_else(|| format!("Expected <key path>=<value>: {}", assignment))?;
+    let (path, value): (&str, &str) = (&assignment[..equals], &assignment[equals + 1..]);
+
+    let steps: Vec<Step> = parse_path(path)?;
+    let value: Value =
+        serde_json::from_str(value).unwrap_or_else(|_| Value::String(String::from(value)));
+
+    let mut target: &mut Value = configuration;
+    for (i, step) in steps.iter().enumerate() {
+        let walked: String = display(&steps[..i + 1]);
+
+        // Optional settings that aren't set yet are filled in as they're walked into
+        if target.is_null() {
+            *target = match *step {
+                Step::Field(_) => Value::Object(Default::default()),
+                Step::Index(_) => Value::Array(Vec::new()),
+            };
+        }
+
+        target = match (step, target) {
+            (Step::Field(name), Value::Object(fields)) => {
+                let created: bool = fields.is_empty();
+                if !created && !fields.contains_key(name) {
+                    return Err(format!("Unknown setting {}", walked));
+                }
+                fields.entry(name.clone()).or_insert(Value::Null)
+            }
+            (Step::Index(index), Value::Array(elements)) => {
+                // One past the end appends
+                if *index == elements.len() {
+                    elements.push(Value::Null);
+                }
+                let count: usize = elements.len();
+                elements.get_mut(*index).ok_or_else(|| {
+                    format!("{} is out of range, there are {}", walked, count)
+                })?
+            }
+            (Step::Field(_), _) => {
+                return Err(format!("{} is not an object", display(&steps[..i])))
+            }
+            (Step::Index(_), _) => return Err(format!("{} is not a list", display(&steps[..i]))),
+        };
+    }
+
+    *target = value;
+    Ok(())
+}
+
+// Make sure an applied assignment's key path is still there once the configuration has
+// been read back. Settings that weren't set yet are filled in as empty objects, which take
+// any key, and keys the configuration doesn't have are dropped by reading it - so a typo
+// inside them only shows up here. The configuration has no free-form maps whose keys
+// would legitimately be dropped
+pub fn check(configuration: &Value, assignment: &str) -> Result<(), String> {
+    let path: &str = assignment.split('=').next().unwrap_or(assignment);
+    let steps: Vec<Step> = parse_path(path)?;
+
+    let mut target: &Value = configuration;
+    for (i, step) in steps.iter().enumerate() {
+        let found: Option<&Value> = match *step {
+            Step::Field(ref name) => target.get(name.as_str()),
+            Step::Index(index) => target.get(index),
+        };
+
+        target = found.ok_or_else(|| format!("Unknown setting {}", display(&steps[..i + 1])))?;
+    }
+
+    Ok(())
+}
+
+// name.name[1][2].name
+fn parse_path(path: &str) -> Result<Vec<Step>, String> {
+    let mut steps: Vec<Step> = Vec::new();
+
+    for part in path.split('.') {
+        let name_end: usize = part.find('[').unwrap_or(part.len());
+        if name_end == 0 {
+            return Err(format!("Missing setting name in {}", path));
+        }
+        steps.push(Step::Field(String::from(&part[..name_end])));
+
+        let mut rest: &str = &part[name_end..];
+        while !rest.is_empty() {
+            let close: usize = match rest.find(']') {
+                Some(close) if rest.starts_with('[') => close,
+                _ => return Err(format!("Invalid key path {}", path)),
+            };
+            let index: usize = rest[1..close]
+                .parse()
+                .map_err(|_| format!("Invalid index {} in {}", &rest[1..close], path))?;
+            steps.push(Step::Index(index));
+            rest = &rest[close + 1..];
+        }
+    }
+
+    Ok(steps)
+}
+
+// The key path steps were parsed from
+fn display(steps: &[Step]) -> String {
+    let mut path: String = String::new();
+    for step in steps {
+        match *step {
+            Step::Field(ref name) if path.is_empty() => path.push_str(name),
+            Step::Field(ref name) => path.push_str(&format!(".{}", name)),
+            Step::Index(index) => path.push_str(&format!("[{}]", index)),
+        }
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> Value {
+        serde_json::from_str(
+            r#"{
+                "samples": 1,
+                "camera_position": [0, 0, 0],
+                "objects": [{"name": "floor"}, {"name": "car", "reflectance": 0.0}],
+                "stereo": null
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn applied(assignment: &str) -> Result<Value, String> {
+        let mut configuration: Value = scene();
+        apply(&mut configuration, assignment).map(|()| configuration)
+    }
+
+    #[test]
+    fn parses_indexed_paths() {
+        assert_eq!(
+            parse_path("objects[1].name").unwrap(),
+            vec![
+                Step::Field(String::from("objects")),
+                Step::Index(1),
+                Step::Field(String::from("name")),
+            ]
+        );
+        assert_eq!(
+            parse_path("lens_elements[0][2]").unwrap(),
+            vec![
+                Step::Field(String::from("lens_elements")),
+                Step::Index(0),
+                Step::Index(2),
+            ]
+        );
+
+        for invalid in &[
+            "",
+            "objects.",
+            "[0]",
+            "objects[",
+            "objects[x]",
+            "objects[-1]",
+        ] {
+            assert!(parse_path(invalid).is_err(), "{} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn sets_indexed_settings() {
+        let configuration: Value = applied("objects[1].reflectance=0.5").unwrap();
+        assert_eq!(configuration["objects"][1]["reflectance"], 0.5);
+        assert_eq!(configuration["objects"][0], scene()["objects"][0]);
+
+        let configuration: Value = applied("camera_position[2]=10").unwrap();
+        assert_eq!(
+            configuration["camera_position"],
+            serde_json::json!([0, 0, 10])
+        );
+    }
+
+    #[test]
+    fn appends_one_past_the_end() {
+        let configuration: Value = applied("objects[2].name=wheel").unwrap();
+        assert_eq!(configuration["objects"][2]["name"], "wheel");
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        assert_eq!(
+            applied("objects[3].name=wheel").unwrap_err(),
+            "objects[3] is out of range, there are 2"
+        );
+        assert!(applied("samples[0]=1").unwrap_err().contains("not a list"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(applied("sampels=4").unwrap_err(), "Unknown setting sampels");
+        assert_eq!(
+            applied("objects[0].reflectence=0.5").unwrap_err(),
+            "Unknown setting objects[0].reflectence"
+        );
+        assert!(applied("samples.count=4")
+            .unwrap_err()
+            .contains("not an object"));
+    }
+
+    #[test]
+    fn checks_keys_of_created_settings() {
+        // An unset setting takes any key while applying - reading the configuration drops
+        // the typo, which check then reports
+        let configuration: Value = applied("stereo.interocular_distanse=0.1").unwrap();
+        assert!(configuration["stereo"]
+            .get("interocular_distanse")
+            .is_some());
+
+        let read: Value = serde_json::json!({"stereo": {"interocular_distance": 0.1}});
+        assert_eq!(
+            check(&read, "stereo.interocular_distanse=0.1").unwrap_err(),
+            "Unknown setting stereo.interocular_distanse"
+        );
+        assert!(check(&read, "stereo.interocular_distance=0.1").is_ok());
+    }
+
+    #[test]
+    fn takes_json_values_or_strings() {
+        assert_eq!(applied("samples=4").unwrap()["samples"], 4);
+        assert_eq!(applied("samples=\"4\"").unwrap()["samples"], "4");
+        assert_eq!(
+            applied("objects[0].name=car").unwrap()["objects"][0]["name"],
+            "car"
+        );
+        assert_eq!(
+            applied("camera_position=[1,2,3]").unwrap()["camera_position"],
+            serde_json::json!([1, 2, 3])
+        );
+        assert_eq!(applied("stereo=null").unwrap()["stereo"], Value::Null);
+        // Only the first = separates the path from the value
+        assert_eq!(
+            applied("objects[0].name=a=b").unwrap()["objects"][0]["name"],
+            "a=b"
+        );
+        assert!(applied("samples").is_err());
+    }
+}
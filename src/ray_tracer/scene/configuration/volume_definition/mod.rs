@@ -0,0 +1,96 @@
+extern crate cgmath;
+
+extern crate serde;
+extern crate serde_json;
+
+use std::io;
+use std::sync::Arc;
+
+#[cfg(feature = "vdb")]
+use cgmath::Vector3;
+
+use ray_tracer::volume::{DensityField, NoiseField};
+use ray_tracer::volume::grid::VoxelGrid;
+
+// Fill an object with fog instead of rendering its surface
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VolumeDefinition {
+    // Extinction per scene unit at full density - multiplies VDB values
+    pub density: f64,
+    // Ray marching step, in scene units
+    pub step_size: f64,
+    // Size of the largest noise features - uniform density if not given
+    #[serde(default)]
+    pub noise_scale: Option<f64>,
+    #[serde(default = "default_octaves")]
+    pub noise_octaves: usize,
+    // Fraction of the volume holding any density
+    #[serde(default = "default_coverage")]
+    pub coverage: f64,
+    // OpenVDB file to take density from, contained by the grid's bounds
+    // rather than the object's shapes - needs the vdb feature
+    #[serde(default)]
+    pub vdb: Option<String>,
+    #[serde(default = "default_grid")]
+    pub grid: String,
+    // Override the file's transform - the distance between voxels, and the world position
+    // of voxel 0, 0, 0's lowest corner
+    #[serde(default)]
+    pub voxel_size: Option<f64>,
+    #[serde(default)]
+    pub origin: Option<Vec<f64>>,
+}
+
+fn default_octaves() -> usize {
+    4
+}
+
+fn default_coverage() -> f64 {
+    1f64
+}
+
+fn default_grid() -> String {
+    String::from("density")
+}
+
+impl VolumeDefinition {
+    pub fn field(&self) -> io::Result<DensityField> {
+        if self.step_size.is_nan() || self.step_size <= 0f64 {
+            return Err(io::Error::other(format!(
+                "step_size must be positive, got {}",
+                self.step_size
+            )));
+        }
+
+        if let Some(ref filename) = self.vdb {
+            return self.load_grid(filename).map(|grid| DensityField::Grid(Arc::new(grid)));
+        }
+
+        Ok(match self.noise_scale {
+            Some(scale) => DensityField::Noise(NoiseField {
+                scale,
+                octaves: self.noise_octaves,
+                coverage: self.coverage,
+            }),
+            None => DensityField::Uniform,
+        })
+    }
+
+    #[cfg(feature = "vdb")]
+    fn load_grid(&self, filename: &str) -> io::Result<VoxelGrid> {
+        let origin: Option<Vector3<f64>> = self
+            .origin
+            .as_ref()
+            .map(|origin| super::Configuration::parse_vector(origin));
+
+        ::ray_tracer::volume::vdb::load_grid(filename, &self.grid, self.voxel_size, origin)
+    }
+
+    #[cfg(not(feature = "vdb"))]
+    fn load_grid(&self, filename: &str) -> io::Result<VoxelGrid> {
+        Err(io::Error::other(format!(
+            "{}: built without the vdb feature",
+            filename
+        )))
+    }
+}
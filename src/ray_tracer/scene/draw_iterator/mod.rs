@@ -26,15 +26,15 @@ impl Iterator for DrawIterator {
     type Item = (usize, usize);
 
     fn next(&mut self) -> Option<(usize, usize)> {
-        self.y += 1;
-
         if self.y >= self.height {
             self.y = 0;
             self.x += self.threads;
         }
 
         if self.x < self.width {
-            Some((self.x, self.y))
+            let pixel: (usize, usize) = (self.x, self.y);
+            self.y += 1;
+            Some(pixel)
         } else {
             None
         }
@@ -1,39 +1,93 @@
 extern crate cgmath;
-extern crate image;
 extern crate rand;
 
 use self::cgmath::*;
 
+use std::cell::{Cell, RefCell};
 use std::f64;
-use rand::Rng;
+use std::io;
+use rand::{Rng, XorShiftRng};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
+mod builder;
 pub mod configuration;
 mod draw_iterator;
 
+pub use self::builder::SceneBuilder;
+
 use self::configuration::Configuration;
 use self::draw_iterator::DrawIterator;
 
-use super::camera::Camera;
-use super::color::Color;
-use super::intersection::Intersection;
-use super::kd_tree::KdTree;
+use super::budget::RenderBudget;
+use super::camera::{self, Camera, Projection};
+use super::clipping::{self, ClippingPlane};
+use super::color::Radiance;
+use super::cryptomatte::Cryptomatte;
+use super::deep::DeepBuffer;
+use super::debug_view::DebugView;
+use super::diagnostics::PixelStatistics;
+use super::edge_overlay::EdgeOverlay;
+use super::intersection::{Hit, Intersection};
+use super::kd_tree::{KdTree, TraversalCost};
+use super::lens::{LensDistortion, LensSystem};
 use super::light::Light;
+use super::light::hemisphere::HemisphereLight;
+use super::math;
 use super::object::*;
 use super::object::material::Material;
+use super::pause::{self, PauseControl};
+use super::pixel_buffer::PixelBuffer;
 use super::ray::Ray;
+use super::tile::Tile;
+use super::tone::{self, AutoExposure};
+use super::tuning::TraversalStatistics;
+use super::visibility::{ObjectVisibility, RayKind};
+use super::volume::Volume;
+
+// Shadow rays per light for soft shadows
+const SOFT_SHADOW_RAYS: usize = 8;
+
+// Stop marching once a volume hides almost everything behind it
+const MIN_TRANSMITTANCE: f64 = 0.01;
+
+// Hidden objects a ray passes through before giving up, and how far past each it restarts,
+// relative to the distance travelled
+const MAX_HIDDEN_SURFACES: usize = 64;
+const HIDDEN_SURFACE_OFFSET: f64 = 1e-9;
 
 pub struct Scene {
     camera: Camera,
     scene_contents: SceneContents,
     scene_characteristics: SceneCharacteristics,
     view_characteristics: ViewCharacteristics,
-    color_buffer: Vec<Vec<Color>>,
+    pixels: PixelBuffer,
+    progress: Option<Arc<AtomicUsize>>,
+    statistics: Option<PixelStatistics>,
+    cryptomatte: Option<Cryptomatte>,
+    deep: Option<DeepBuffer>,
+    // Per object, empty when every object is seen by every ray
+    object_visibility: Vec<ObjectVisibility>,
+    projection: Projection,
+    lens_distortion: Option<LensDistortion>,
+    // Traced in place of the pinhole or thin lens
+    lens_system: Option<LensSystem>,
+    // Where threads hand over their pixels while a render is paused
+    pause: Option<Arc<PauseControl>>,
+    // What the scene was loaded from, if anything
+    configuration: Option<Configuration>,
+    // Intersection work so far, for render profiles
+    traversal: Cell<TraversalStatistics>,
+    rng: RefCell<XorShiftRng>,
 }
 
 struct SceneContents {
-    lights: Vec<Light>,
+    lights: Arc<Vec<Light>>,
     kd_tree: Arc<KdTree>,
+    clipping_planes: Vec<ClippingPlane>,
+    volumes: Arc<Vec<Volume>>,
+    hemisphere_light: Option<HemisphereLight>,
 }
 
 struct SceneCharacteristics {
@@ -42,6 +96,14 @@ struct SceneCharacteristics {
     use_kd_tree: bool,
     reinhard_key_value: f64,
     reinhard_delta: f64,
+    auto_exposure: Option<AutoExposure>,
+    exposure: Option<f64>,
+    debug_view: Option<DebugView>,
+    edge_overlay: Option<EdgeOverlay>,
+    aperture: f64,
+    focal_distance: f64,
+    // How far shadow and reflection rays start off the surface, in scene units
+    surface_bias: f64,
 }
 
 struct ViewCharacteristics {
@@ -56,97 +118,597 @@ struct ViewCharacteristics {
     viewport_distance: f64,
 }
 
-impl Scene {
-    pub fn new(configuration: &Configuration, kd_tree: Arc<KdTree>) -> Scene {
-        /* Set up lights */
-        let mut lights: Vec<Light> = Vec::new();
-        for light_definition in &configuration.lights {
-            lights.push(light_definition.as_light());
-        }
-
-        /* Set up camera */
-        let camera: Camera = configuration.camera();
-
+impl ViewCharacteristics {
+    fn new(
+        pixel_width: usize,
+        pixel_height: usize,
+        viewport_width: f64,
+        viewport_distance: f64,
+    ) -> ViewCharacteristics {
         /* Calculate viewport height from aspect ratio */
-        let viewport_height: f64 = (configuration.height as f64 / configuration.width as f64) *
-            configuration.viewport_width;
+        let viewport_height: f64 = (pixel_height as f64 / pixel_width as f64) * viewport_width;
 
         /* Normalized pixel tolerance - for supersampling */
-        let width_tolerance: f64 = 1f64 / configuration.width as f64;
-        let height_tolerance: f64 = 1f64 / configuration.height as f64;
+        let width_tolerance: f64 = 1f64 / pixel_width as f64;
+        let height_tolerance: f64 = 1f64 / pixel_height as f64;
 
-        Scene {
-            scene_contents: SceneContents { lights, kd_tree },
-            scene_characteristics: SceneCharacteristics {
+        ViewCharacteristics {
+            pixel_width,
+            pixel_height,
+            viewport_width,
+            viewport_height,
+            width_tolerance,
+            half_width_tolerance: width_tolerance / 2f64,
+            height_tolerance,
+            half_height_tolerance: height_tolerance / 2f64,
+            viewport_distance,
+        }
+    }
+}
+
+impl Scene {
+    // Geometry, volumes and lights (with their gobo images) are loaded once and shared by
+    // every thread's scene - see build_kd_tree, load_volumes and load_lights
+    pub fn new(
+        configuration: &Configuration,
+        kd_tree: Arc<KdTree>,
+        volumes: Arc<Vec<Volume>>,
+        lights: Arc<Vec<Light>>,
+    ) -> io::Result<Scene> {
+        let clipping_planes: Vec<ClippingPlane> = configuration
+            .clipping_planes
+            .iter()
+            .map(|definition| definition.as_clipping_plane())
+            .collect();
+
+        let mut scene: Scene = Scene::assemble(
+            configuration.camera(),
+            SceneContents {
+                lights,
+                kd_tree,
+                clipping_planes,
+                volumes,
+                hemisphere_light: configuration.hemisphere_light.clone(),
+            },
+            SceneCharacteristics {
                 samples: configuration.samples,
                 max_reflections: configuration.max_reflections,
                 use_kd_tree: configuration.use_kd_tree,
                 reinhard_key_value: configuration.reinhard_key_value,
                 reinhard_delta: configuration.reinhard_delta,
+                auto_exposure: configuration.auto_exposure,
+                exposure: configuration.exposure(),
+                debug_view: configuration.debug_view,
+                edge_overlay: configuration.edge_overlay.clone(),
+                aperture: configuration.aperture(),
+                focal_distance: configuration.focal_distance(),
+                surface_bias: configuration.units.surface_bias(),
             },
-            view_characteristics: ViewCharacteristics {
-                pixel_width: configuration.width,
-                pixel_height: configuration.height,
-                viewport_width: configuration.viewport_width,
-                viewport_height,
-                width_tolerance,
-                half_width_tolerance: width_tolerance / 2f64,
-                height_tolerance,
-                half_height_tolerance: height_tolerance / 2f64,
-                viewport_distance: configuration.viewport_distance,
-            },
+            ViewCharacteristics::new(
+                configuration.width,
+                configuration.height,
+                configuration.viewport_width(),
+                configuration.viewport_distance,
+            ),
+        );
+
+        if configuration.deterministic() {
+            scene.rng = RefCell::new(math::seeded_rng());
+        }
+
+        if configuration.diagnostics {
+            scene.statistics = Some(PixelStatistics::new(configuration.width, configuration.height));
+        }
+
+        if configuration.cryptomatte {
+            scene.cryptomatte = Some(Cryptomatte::new(configuration.width, configuration.height));
+        }
+
+        if configuration.deep {
+            scene.deep = Some(DeepBuffer::new(configuration.width, configuration.height));
+        }
+
+        scene.object_visibility = configuration.object_visibility();
+        scene.projection = configuration.projection;
+        scene.lens_distortion = configuration.lens_distortion;
+        scene.lens_system = configuration.lens_system();
+        scene.configuration = Some(configuration.clone());
+
+        Ok(scene)
+    }
+
+    fn assemble(
+        camera: Camera,
+        scene_contents: SceneContents,
+        scene_characteristics: SceneCharacteristics,
+        view_characteristics: ViewCharacteristics,
+    ) -> Scene {
+        Scene {
             camera,
-            color_buffer: vec![
-                vec![Color::new(0f64, 0f64, 0f64); configuration.height];
-                configuration.width
-            ],
+            scene_contents,
+            scene_characteristics,
+            view_characteristics,
+            // Allocated by the first draw - tiles are rendered without one
+            pixels: PixelBuffer::new(0, 0),
+            progress: None,
+            statistics: None,
+            cryptomatte: None,
+            deep: None,
+            object_visibility: Vec::new(),
+            projection: Projection::default(),
+            lens_distortion: None,
+            lens_system: None,
+            pause: None,
+            configuration: None,
+            traversal: Cell::new(TraversalStatistics::default()),
+            rng: RefCell::new(math::new_rng()),
+        }
+    }
+
+    // Write the configuration the scene was loaded from with generators, detail levels and
+    // files resolved into inline shapes - scenes from a SceneBuilder have none to write
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        match self.configuration {
+            Some(ref configuration) => configuration.resolved()?.save(filename),
+            None => Err(io::Error::other("Scene was not loaded from a configuration")),
+        }
+    }
+
+    // Shared counter incremented as each pixel is completed
+    pub fn set_progress(&mut self, progress: Arc<AtomicUsize>) {
+        self.progress = Some(progress);
+    }
+
+    // Wait while the render is paused, sharing this thread's pixels for snapshots
+    pub fn set_pause(&mut self, pause: Arc<PauseControl>) {
+        self.pause = Some(pause);
+    }
+
+    // A leaf couldn't be read from the page file, so the image would have holes - drawing
+    // stops early and the error is reported once the threads finish
+    pub fn paging_failed(&self) -> bool {
+        self.scene_contents.kd_tree.paging_failed()
+    }
+
+    // Time spent paused doesn't count against the budget - returns how long this thread waited
+    fn pause_point(
+        &self,
+        threads: usize,
+        thread_number: usize,
+        budget: Option<&RenderBudget>,
+    ) -> Duration {
+        let start: Instant = Instant::now();
+        if let (true, Some(pause)) = (pause::paused(), self.pause.as_ref()) {
+            let paused: Option<Duration> =
+                pause.wait(self.draw_iterator(threads, thread_number), &self.pixels);
+
+            if let (Some(paused), Some(budget)) = (paused, budget) {
+                budget.extend(paused);
+            }
+            return start.elapsed();
         }
+
+        Duration::ZERO
+    }
+
+    // Count time spent tracing since start, less any time paused
+    fn count_time(&mut self, start: Instant, paused: Duration) {
+        self.traversal.get_mut().seconds += start.elapsed().saturating_sub(paused).as_secs_f64();
     }
 
-    pub fn get_pixel(&self, x: usize, y: usize) -> Color {
-        self.color_buffer[x][y]
+    // The thread has drawn its last pixel - later snapshots use them as they are
+    pub fn finish_pause(&self, threads: usize, thread_number: usize) {
+        if let Some(ref pause) = self.pause {
+            pause.finish(self.draw_iterator(threads, thread_number), &self.pixels);
+        }
+    }
+
+    // Per-pixel samples and timings, if the configuration asked for diagnostics
+    pub fn statistics(&self) -> Option<&PixelStatistics> {
+        self.statistics.as_ref()
+    }
+
+    // Per-pixel object coverage, if the configuration asked for ID mattes
+    pub fn cryptomatte(&self) -> Option<&Cryptomatte> {
+        self.cryptomatte.as_ref()
+    }
+
+    // Per-pixel deep samples, if the configuration asked for deep output
+    pub fn deep(&self) -> Option<&DeepBuffer> {
+        self.deep.as_ref()
+    }
+
+    pub fn traversal_statistics(&self) -> TraversalStatistics {
+        self.traversal.get()
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> Radiance {
+        self.pixels.color(x, y)
+    }
+
+    // Every layer drawn so far
+    pub fn pixel_buffer(&self) -> &PixelBuffer {
+        &self.pixels
+    }
+
+    pub fn width(&self) -> usize {
+        self.view_characteristics.pixel_width
+    }
+
+    pub fn height(&self) -> usize {
+        self.view_characteristics.pixel_height
+    }
+
+    // Copy of the drawn image with Reinhard tone correction applied
+    pub fn tone_mapped(&self) -> Vec<Vec<Radiance>> {
+        let mut color_buffer: Vec<Vec<Radiance>> = self.pixels.to_radiance();
+
+        if let Some(debug_view) = self.scene_characteristics.debug_view {
+            debug_view.finish(&mut color_buffer);
+            return color_buffer;
+        }
+
+        tone::tone_correction(
+            &mut color_buffer,
+            self.view_characteristics.pixel_width,
+            self.view_characteristics.pixel_height,
+            self.scene_characteristics.reinhard_key_value,
+            self.scene_characteristics.reinhard_delta,
+            self.scene_characteristics.auto_exposure,
+            self.scene_characteristics.exposure,
+        );
+
+        color_buffer
     }
 
-    // Generate a ray from the camera through the viewport
-    pub fn generate_ray(&self, x: usize, y: usize, randomize: bool) -> Ray {
-        let camera_position: Vector3<f64> = self.camera.origin;
+    // Camera origin plus the right, up and forward viewport vectors, scaled to the viewport size
+    pub fn viewport_basis(&self) -> (Vector3<f64>, Vector3<f64>, Vector3<f64>, Vector3<f64>) {
         let camera_direction: Vector3<f64> = self.camera.direction();
         let camera_right: Vector3<f64> = camera_direction.cross(self.camera.up).normalize();
         let camera_up: Vector3<f64> = camera_direction.cross(camera_right).normalize();
 
+        (
+            self.camera.origin,
+            camera_right * self.view_characteristics.viewport_width,
+            camera_up * self.view_characteristics.viewport_height,
+            camera_direction * self.view_characteristics.viewport_distance,
+        )
+    }
+
+    // Generate a ray from the camera through the viewport - None when the lens elements
+    // block it, which makes the sample black
+    pub fn generate_ray(&self, x: usize, y: usize, randomize: bool) -> Option<Ray> {
+        let (origin, right, up, forward) = self.viewport_basis();
+
         // normalize x and y from -0.5 to 0.5
         let mut normalized_x = (x as f64 / self.view_characteristics.pixel_width as f64) - 0.5;
         let mut normalized_y = (y as f64 / self.view_characteristics.pixel_height as f64) - 0.5;
 
         if randomize {
-            let mut rng = rand::thread_rng();
+            let mut rng = self.rng.borrow_mut();
             normalized_x += rng.gen::<f64>() * self.view_characteristics.width_tolerance -
                 self.view_characteristics.half_width_tolerance;
             normalized_y += rng.gen::<f64>() * self.view_characteristics.height_tolerance -
                 self.view_characteristics.half_height_tolerance;
         }
 
+        if let Projection::Fulldome { angle } = self.projection {
+            let camera_direction: Vector3<f64> = self.camera.direction();
+            let camera_right: Vector3<f64> = camera_direction.cross(self.camera.up).normalize();
+            let camera_down: Vector3<f64> = camera_direction.cross(camera_right).normalize();
+            let direction: Vector3<f64> = camera::fulldome_direction(
+                angle,
+                normalized_x,
+                normalized_y,
+                self.view_characteristics.pixel_width,
+                self.view_characteristics.pixel_height,
+            );
+
+            return Some(Ray::new(
+                origin,
+                camera_right * direction.x + camera_down * direction.y +
+                    camera_direction * direction.z,
+            ));
+        }
+
+        // The footage's pixel shows what an undistorted lens would show elsewhere
+        if let Some(distortion) = self.lens_distortion {
+            let view: &ViewCharacteristics = &self.view_characteristics;
+            let tan_width: f64 = view.viewport_width / view.viewport_distance;
+            let tan_height: f64 = view.viewport_height / view.viewport_distance;
+            let (x, y): (f64, f64) =
+                distortion.undistort(normalized_x * tan_width, normalized_y * tan_height);
+            normalized_x = x / tan_width;
+            normalized_y = y / tan_height;
+        }
+
+        if self.lens_system.is_some() {
+            return self.lens_system_ray(normalized_x, normalized_y, randomize);
+        }
+
         // camera position + x factor + y factor + viewport direction / distance
-        let viewport_intersection: Vector3<f64> = self.camera.origin +
-            normalized_x * camera_right * self.view_characteristics.viewport_width +
-            normalized_y * camera_up * self.view_characteristics.viewport_height +
-            camera_direction * self.view_characteristics.viewport_distance;
+        let viewport_intersection: Vector3<f64> =
+            origin + normalized_x * right + normalized_y * up + forward;
+
+        let ray: Ray = Ray::from_points(origin, viewport_intersection);
+
+        // The unjittered ray stays at the center of the lens
+        if randomize && self.scene_characteristics.aperture > 0f64 {
+            return Some(self.lens_ray(&ray));
+        }
+
+        Some(ray)
+    }
+
+    // Traced through the lens elements from a random point on the rear element, trying
+    // again if the lens blocks it - None without lens elements, or when every try is blocked
+    fn lens_system_ray(&self, x: f64, y: f64, randomize: bool) -> Option<Ray> {
+        let lens_system: &LensSystem = self.lens_system.as_ref()?;
+        let camera_direction: Vector3<f64> = self.camera.direction();
+        let camera_right: Vector3<f64> = camera_direction.cross(self.camera.up).normalize();
+        let camera_down: Vector3<f64> = camera_direction.cross(camera_right).normalize();
+
+        let attempts: usize = if randomize { 8 } else { 1 };
+        (0..attempts)
+            .filter_map(|_| {
+                let pupil: (f64, f64) = if randomize {
+                    math::uniform_disk(&mut *self.rng.borrow_mut())
+                } else {
+                    (0f64, 0f64)
+                };
+                lens_system.ray(x, y, pupil)
+            })
+            .next()
+            .map(|(origin, direction)| {
+                let to_world = |v: Vector3<f64>| -> Vector3<f64> {
+                    camera_right * v.x + camera_down * v.y + camera_direction * v.z
+                };
+                Ray::new(self.camera.origin + to_world(origin), to_world(direction))
+            })
+    }
+
+    // Thin lens - start from a random point on the aperture, aimed at where the
+    // pinhole ray crosses the focal plane, so only that plane stays sharp
+    fn lens_ray(&self, ray: &Ray) -> Ray {
+        let camera_direction: Vector3<f64> = self.camera.direction();
+        let camera_right: Vector3<f64> = camera_direction.cross(self.camera.up).normalize();
+        let camera_up: Vector3<f64> = camera_direction.cross(camera_right).normalize();
+
+        let focus: Vector3<f64> = ray.origin +
+            ray.direction *
+                (self.scene_characteristics.focal_distance / ray.direction.dot(camera_direction));
+
+        let (x, y): (f64, f64) = math::uniform_disk(&mut *self.rng.borrow_mut());
+        let aperture: f64 = self.scene_characteristics.aperture;
+        let origin: Vector3<f64> =
+            ray.origin + camera_right * x * aperture + camera_up * y * aperture;
+
+        Ray::from_points(origin, focus)
+    }
+
+    // Distance along the view direction to whatever the pixel's center ray hits,
+    // e.g. to focus on it
+    pub fn focus_distance(&self, x: usize, y: usize) -> Option<f64> {
+        let ray: Ray = self.generate_ray(x, y, false)?;
+
+        self.intersection(&ray, RayKind::Camera).map(|ray_intersection| {
+            ray_intersection.distance * ray.direction.dot(self.camera.direction())
+        })
+    }
+
+    // The closest surface along the ray that the camera would see - objects hidden from the
+    // camera and geometry cut away by clipping planes are skipped. Directions needn't be
+    // normalized, distances are in their lengths
+    pub fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let length: f64 = ray.direction.magnitude();
+        let unit: Ray = Ray::new(ray.origin, ray.direction / length);
+
+        self.intersection(&unit, RayKind::Camera).map(|intersection| {
+            let mut hit: Hit = Hit::from_intersection(&intersection);
+            hit.distance /= length;
+            hit
+        })
+    }
+
+    // Whether anything that casts shadows lies between two points, e.g. for line of sight -
+    // surfaces within the scene's surface bias of either point don't count, so points on
+    // surfaces can be tested directly
+    pub fn occluded(&self, a: Vector3<f64>, b: Vector3<f64>) -> bool {
+        let bias: f64 = self.scene_characteristics.surface_bias;
+        let distance: f64 = (b - a).magnitude();
+        if distance <= 2f64 * bias {
+            return false;
+        }
+
+        let direction: Vector3<f64> = (b - a) / distance;
+        let ray: Ray = Ray::new(a + direction * bias, direction);
+        self.intersection(&ray, RayKind::Shadow)
+            .is_some_and(|intersection| intersection.distance < distance - 2f64 * bias)
+    }
+
+    // Diffuse and ambient light arriving at a point on shape's front side, weighted by its
+    // material's coefficients but not its color - what a lightmap holds, so that the color
+    // times the lightmap gives the view independent part of the shading
+    pub fn irradiance(&self, shape: Shape, point: Vector3<f64>, normal: Vector3<f64>) -> Radiance {
+        let material: Material = shape.material();
+        let surface: Intersection = Intersection::new(-normal, shape, point, normal, 0f64);
+        let hemisphere_light: Option<&HemisphereLight> =
+            self.scene_contents.hemisphere_light.as_ref();
+        let mut result: Radiance = hemisphere_light.map_or(Radiance::black(), |hemisphere| {
+            hemisphere.irradiance(normal) * material.ambient_coefficient
+        });
+
+        for light in self.scene_contents.lights.iter() {
+            if hemisphere_light.is_none() {
+                result += light.color * light.intensity * material.ambient_coefficient;
+            }
+
+            let to_light: Vector3<f64> = (light.origin - point).normalize();
+            let shadowed: f64 =
+                (1f64 - self.light_visibility(&surface, light)) * light.shadow.intensity;
+            let transmission: Radiance = Radiance::new(1f64, 1f64, 1f64) * (1f64 - shadowed) +
+                light.shadow.color * shadowed;
+
+            result += light.illumination(point) * transmission * material.diffuse_coefficient *
+                f64::max(0f64, normal.dot(to_light));
+        }
+
+        result
+    }
+
+    // Fraction of rays from a point on a surface, spread over the hemisphere around its
+    // normal, that travel distance without hitting anything
+    pub fn ambient_occlusion(
+        &self,
+        shape: Shape,
+        point: Vector3<f64>,
+        normal: Vector3<f64>,
+        rays: usize,
+        distance: f64,
+    ) -> f64 {
+        let surface: Intersection = Intersection::new(-normal, shape, point, normal, 0f64);
+        let origin: Vector3<f64> = self.offset_point(&surface, true);
+
+        let open: usize = (0..rays)
+            .filter(|_| {
+                let direction: Vector3<f64> =
+                    math::cosine_weighted_direction(&mut *self.rng.borrow_mut(), normal);
+                let ray: Ray = Ray::new(origin, direction);
+                !self.shadow(shape, &ray, distance)
+            })
+            .count();
+
+        open as f64 / rays.max(1) as f64
+    }
+
+    // Find the closest intersection (if any) with an object the kind of ray sees
+    fn intersection(&self, ray: &Ray, kind: RayKind) -> Option<Intersection> {
+        self.visible_intersection(ray, kind, &mut TraversalCost::default()).0
+    }
+
+    // Closest intersection, also counting the work needed to find it
+    fn intersection_counted(
+        &self,
+        ray: &Ray,
+        kind: RayKind,
+        cost: &mut TraversalCost,
+    ) -> Option<Intersection> {
+        self.visible_intersection(ray, kind, cost).0
+    }
+
+    // Closest clipped intersection, continuing the ray past objects hidden from its kind
+    fn visible_intersection(
+        &self,
+        ray: &Ray,
+        kind: RayKind,
+        cost: &mut TraversalCost,
+    ) -> (Option<Intersection>, Option<ClippingPlane>) {
+        let (start, end, entry) = match self.clip_span(ray) {
+            Some(span) => span,
+            None => return (None, None),
+        };
+
+        let mut ray_intersection: Option<Intersection> =
+            self.span_intersection(ray, start, end, cost);
+        if self.object_visibility.is_empty() {
+            return (ray_intersection, entry);
+        }
+
+        // Continue within the span the ray was clipped to, so the visible surface behind a
+        // hidden one is still capped by the plane the ray entered through
+        for _ in 0..MAX_HIDDEN_SURFACES {
+            let hidden: Intersection = match ray_intersection {
+                Some(hit) if !self.object_visibility[hit.shape.object_id()].visible_to(kind) => hit,
+                _ => break,
+            };
+
+            // Start just past the hidden surface, so it isn't hit again
+            let past: f64 = hidden.distance + HIDDEN_SURFACE_OFFSET * (1f64 + hidden.distance);
+            ray_intersection = self.span_intersection(ray, past, end, cost);
+        }
+
+        (ray_intersection, entry)
+    }
+
+    // Closest intersection with all geometry, ignoring clipping planes
+    fn unclipped_intersection(&self, ray: &Ray, cost: &mut TraversalCost) -> Option<Intersection> {
+        let mut ray_cost: TraversalCost = TraversalCost::default();
+        let ray_intersection: Option<Intersection> = if self.scene_characteristics.use_kd_tree {
+            self.scene_contents.kd_tree.intersect_counted(ray, &mut ray_cost)
+        } else {
+            let objects: &[Shape] = &self.scene_contents.kd_tree.root_node().objects;
+            ray_cost.intersection_tests += objects.len();
+            Intersection::closest_intersection(ray, objects)
+        };
+
+        cost.add(&ray_cost);
+        let mut traversal: TraversalStatistics = self.traversal.get();
+        traversal.count(&ray_cost);
+        self.traversal.set(traversal);
+
+        ray_intersection
+    }
+
+    // Distances along the ray inside the kept region, and the plane it enters through -
+    // None if the clipping planes remove the whole ray
+    fn clip_span(&self, ray: &Ray) -> Option<(f64, f64, Option<ClippingPlane>)> {
+        if self.scene_contents.clipping_planes.is_empty() {
+            return Some((0f64, f64::MAX, None));
+        }
 
-        Ray::from_points(self.camera.origin, viewport_intersection)
+        clipping::clip_ray(&self.scene_contents.clipping_planes, ray)
     }
 
-    // Find the closest intersection (if any)
-    fn intersection(&self, ray: &Ray) -> Option<Intersection> {
-        if self.scene_characteristics.use_kd_tree {
-            return self.scene_contents.kd_tree.intersect(ray);
+    // Closest intersection between start and end along the ray
+    fn span_intersection(
+        &self,
+        ray: &Ray,
+        start: f64,
+        end: f64,
+        cost: &mut TraversalCost,
+    ) -> Option<Intersection> {
+        // Skip everything before start
+        let ray_intersection: Option<Intersection> = if start > 0f64 {
+            let start_ray: Ray = Ray::new(ray.origin + ray.direction * start, ray.direction);
+            self.unclipped_intersection(&start_ray, cost).map(|mut ray_intersection| {
+                ray_intersection.distance += start;
+                ray_intersection
+            })
+        } else {
+            self.unclipped_intersection(ray, cost)
+        };
+
+        ray_intersection.filter(|ray_intersection| ray_intersection.distance <= end)
+    }
+
+    // Cap color where the ray entered the kept region inside a solid - i.e. the
+    // first surface it meets is the inside of one
+    fn cap(ray_intersection: &Intersection, entry: Option<ClippingPlane>) -> Option<Radiance> {
+        let cap_color: Radiance = entry.and_then(|plane| plane.cap_color)?;
+        let normal: Vector3<f64> = ray_intersection.shape.geometric_normal(ray_intersection.point);
+
+        if ray_intersection.ray_direction.dot(normal) > 0f64 {
+            Some(cap_color)
+        } else {
+            None
         }
+    }
+
+    // The hit moved off the surface by the bias, to the normal's side or the other, so rays
+    // starting there don't hit the surface again
+    fn offset_point(&self, ray_intersection: &Intersection, normal_side: bool) -> Vector3<f64> {
+        let bias: f64 = if normal_side {
+            self.scene_characteristics.surface_bias
+        } else {
+            -self.scene_characteristics.surface_bias
+        };
 
-        Intersection::closest_intersection(ray, &self.scene_contents.kd_tree.root_node().objects)
+        ray_intersection.point + ray_intersection.normal * bias
     }
 
     // Check if there is anything between the object and the light
     fn shadow(&self, object: Shape, to_light: &Ray, light_distance: f64) -> bool {
-        if let Some(shadow_intersection) = self.intersection(to_light) {
+        if let Some(shadow_intersection) = self.intersection(to_light, RayKind::Shadow) {
             shadow_intersection.distance < light_distance
         } else {
             false
@@ -154,88 +716,411 @@ impl Scene {
     }
 
     // Phong shading for determining diffuse + specular contribution
-    fn phong(&self, ray_intersection: &Intersection, light: &Light, to_light: &Ray) -> Color {
+    fn phong(&self, ray_intersection: &Intersection, light: &Light, to_light: &Ray) -> Radiance {
         let material: Material = ray_intersection.shape.material();
 
         let reflection: Vector3<f64> =
             Ray::reflect(ray_intersection.ray_direction, ray_intersection.normal);
-        let specular_component: Color = light.color * light.intensity *
-            material.specular_coefficient *
+        // Spotlights shape and pattern what arrives
+        let illumination: Radiance = light.illumination(ray_intersection.point);
+
+        let specular_component: Radiance = illumination * material.specular_coefficient *
             f64::max(0f64, to_light.direction.dot(reflection)).powf(material.specular_exponent);
 
-        let diffuse_component: Color = light.color * light.intensity * material.color *
+        let diffuse_component: Radiance = illumination * material.color *
             material.diffuse_coefficient *
             f64::max(0f64, ray_intersection.normal.dot(to_light.direction));
 
         diffuse_component + specular_component
     }
 
+    // Fraction of the light reaching the point - soft shadows aim several
+    // shadow rays into a sphere around the light
+    fn light_visibility(&self, ray_intersection: &Intersection, light: &Light) -> f64 {
+        let softness: f64 = light.shadow.softness;
+        let lit_side: bool =
+            ray_intersection.normal.dot(light.origin - ray_intersection.point) > 0f64;
+        let point: Vector3<f64> = self.offset_point(ray_intersection, lit_side);
+        let shadow_rays: usize = if softness > 0f64 { SOFT_SHADOW_RAYS } else { 1 };
+
+        let visible: usize = (0..shadow_rays)
+            .filter(|_| {
+                let target: Vector3<f64> = if softness > 0f64 {
+                    light.origin + math::uniform_sphere(&mut *self.rng.borrow_mut()) * softness
+                } else {
+                    light.origin
+                };
+
+                let to_light: Ray = Ray::from_points(point, target);
+                !self.shadow(ray_intersection.shape, &to_light, (target - point).magnitude())
+            })
+            .count();
+
+        visible as f64 / shadow_rays as f64
+    }
+
     // Use material characteristics and lighting to determine the color
-    fn shade(&self, ray: &Ray, ray_intersection: &Intersection) -> Color {
+    fn shade(&self, ray: &Ray, ray_intersection: &Intersection) -> Radiance {
         let material: Material = ray_intersection.shape.material();
-        let mut result: Color = Color::new(0f64, 0f64, 0f64);
+        let hemisphere_light: Option<&HemisphereLight> =
+            self.scene_contents.hemisphere_light.as_ref();
+        let mut result: Radiance = hemisphere_light.map_or(Radiance::black(), |hemisphere| {
+            hemisphere.irradiance(ray_intersection.normal) * material.color *
+                material.ambient_coefficient
+        });
 
-        for light in &self.scene_contents.lights {
-            let ambient_contribution: Color = light.color * light.intensity * material.color *
-                material.ambient_coefficient;
+        for light in self.scene_contents.lights.iter() {
+            let ambient_contribution: Radiance = if hemisphere_light.is_some() {
+                Radiance::black()
+            } else {
+                light.color * light.intensity * material.color * material.ambient_coefficient
+            };
 
             let to_light: Ray = Ray::from_points(ray_intersection.point, light.origin);
-            let light_distance: f64 = (light.origin - ray_intersection.point).magnitude();
 
-            if self.shadow(ray_intersection.shape, &to_light, light_distance) {
+            let shadowed: f64 =
+                (1f64 - self.light_visibility(ray_intersection, light)) * light.shadow.intensity;
+            if shadowed >= 1f64 && light.shadow.color == Radiance::black() {
                 result += ambient_contribution;
                 continue;
             }
 
-            result += ambient_contribution + self.phong(ray_intersection, light, &to_light);
+            // Light blocked by shadows is filtered by the shadow color
+            let transmission: Radiance = Radiance::new(1f64, 1f64, 1f64) * (1f64 - shadowed) +
+                light.shadow.color * shadowed;
+
+            result += ambient_contribution +
+                self.phong(ray_intersection, light, &to_light) * transmission;
         }
 
         result
     }
 
     // Follow the ray to determine the color of the pixel
-    fn trace(&self, ray: &Ray, reflection_level: u8) -> Option<Color> {
-        match self.intersection(ray) {
-            None => None,
-            Some(ray_intersection) => {
-                let material: Material = ray_intersection.shape.material();
-                let mut object_color: Color = self.shade(ray, &ray_intersection);
-                if reflection_level < self.scene_characteristics.max_reflections &&
-                    material.reflectance > 0f64
-                {
-                    // Object is reflective - recursively trace reflection ray
-                    let reflection_ray = Ray::new(
-                        ray_intersection.point,
-                        ray.reflection(ray_intersection.normal),
-                    );
+    fn trace(&self, ray: &Ray, reflection_level: u8) -> Option<Radiance> {
+        self.trace_with_hit(ray, reflection_level).0
+    }
+
+    // Also returns the object and distance of the surface the ray hit, so ID mattes and
+    // deep output don't need to intersect the ray again
+    fn trace_with_hit(
+        &self,
+        ray: &Ray,
+        reflection_level: u8,
+    ) -> (Option<Radiance>, Option<(usize, f64)>) {
+        let kind: RayKind = if reflection_level == 0 {
+            RayKind::Camera
+        } else {
+            RayKind::Reflection
+        };
+        let (ray_intersection, entry) =
+            self.visible_intersection(ray, kind, &mut TraversalCost::default());
 
-                    if let Some(reflection_color) =
-                        self.trace(&reflection_ray, reflection_level + 1u8)
-                    {
-                        // Combine reflection color and object color
-                        object_color = object_color * material.normal +
-                            reflection_color * material.reflectance;
-                    }
+        let hit: Option<(usize, f64)> = ray_intersection
+            .as_ref()
+            .map(|hit| (hit.shape.object_id(), hit.distance));
+        let distance: f64 = hit.map_or(f64::MAX, |(_, distance)| distance);
+        let surface_color: Option<Radiance> = ray_intersection.map(|ray_intersection| {
+            Scene::cap(&ray_intersection, entry).unwrap_or_else(|| {
+                self.trace_intersection(ray, &ray_intersection, reflection_level)
+            })
+        });
+
+        if self.scene_contents.volumes.is_empty() {
+            return (surface_color, hit);
+        }
+
+        let background: Radiance = surface_color.unwrap_or_else(Radiance::black);
+        (Some(self.march_volumes(ray, distance, background)), hit)
+    }
+
+    // Ray march the volumes in front of a surface, front to back, adding light
+    // scattered toward the camera and dimming the surface behind
+    fn march_volumes(&self, ray: &Ray, distance: f64, background: Radiance) -> Radiance {
+        let mut segments: Vec<(f64, f64, &Volume)> = Vec::new();
+        for volume in self.scene_contents.volumes.iter() {
+            for (start, end) in volume.spans(ray, distance) {
+                segments.push((start, end, volume));
+            }
+        }
+        segments.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut scattered: Radiance = Radiance::black();
+        let mut transmittance: f64 = 1f64;
+
+        for (start, end, volume) in segments {
+            // Jitter the first step to trade banding for noise
+            let mut t: f64 = start + volume.step_size * self.rng.borrow_mut().gen::<f64>();
+
+            while t < end && transmittance > MIN_TRANSMITTANCE {
+                let step: f64 = volume.step_size.min(end - t);
+                let point: Vector3<f64> = ray.origin + ray.direction * t;
+                let density: f64 = volume.density(point);
+
+                if density > 0f64 {
+                    scattered += self.in_scattering(point, volume) *
+                        (transmittance * density * step);
+                    transmittance *= (-density * step).exp();
                 }
 
-                Some(object_color)
+                t += volume.step_size;
+            }
+        }
+
+        scattered + background * transmittance
+    }
+
+    // Light scattered at a point inside a volume, from every light that isn't
+    // blocked by geometry and dimmed by the volumes in between
+    fn in_scattering(&self, point: Vector3<f64>, volume: &Volume) -> Radiance {
+        let hemisphere_light: Option<&HemisphereLight> =
+            self.scene_contents.hemisphere_light.as_ref();
+        let mut result: Radiance = hemisphere_light.map_or(Radiance::black(), |hemisphere| {
+            hemisphere.average() * volume.albedo * volume.ambient_coefficient
+        });
+
+        for light in self.scene_contents.lights.iter() {
+            if hemisphere_light.is_none() {
+                result +=
+                    light.color * light.intensity * volume.albedo * volume.ambient_coefficient;
+            }
+
+            let to_light: Ray = Ray::from_points(point, light.origin);
+            let light_distance: f64 = (light.origin - point).magnitude();
+
+            if self.intersection(&to_light, RayKind::Shadow)
+                .is_some_and(|blocker| blocker.distance < light_distance)
+            {
+                continue;
+            }
+
+            let optical_depth: f64 = self.scene_contents
+                .volumes
+                .iter()
+                .map(|volume| volume.optical_depth(&to_light, light_distance))
+                .sum();
+
+            result += light.illumination(point) * volume.albedo * (-optical_depth).exp();
+        }
+
+        result
+    }
+
+    // Overlay color if the hit is within the line width of a triangle edge
+    fn edge_color(&self, ray_intersection: &Intersection) -> Option<Radiance> {
+        let edge_overlay: &EdgeOverlay = self.scene_characteristics.edge_overlay.as_ref()?;
+
+        // Size of a pixel at the hit's distance from the camera
+        let pixel_size: f64 = ray_intersection.distance * self.view_characteristics.viewport_width /
+            (self.view_characteristics.pixel_width as f64 *
+                self.view_characteristics.viewport_distance);
+
+        ray_intersection
+            .shape
+            .edge_distance(ray_intersection.point)
+            .filter(|distance| *distance < edge_overlay.width * pixel_size / 2f64)
+            .map(|_| edge_overlay.color())
+    }
+
+    // How the shape's object limits rays - unlimited for scenes without per-object settings
+    fn visibility(&self, shape: Shape) -> ObjectVisibility {
+        self.object_visibility
+            .get(shape.object_id())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Color at an intersection that has already been found
+    fn trace_intersection(
+        &self,
+        ray: &Ray,
+        ray_intersection: &Intersection,
+        reflection_level: u8,
+    ) -> Radiance {
+        if reflection_level == 0 {
+            if let Some(edge_color) = self.edge_color(ray_intersection) {
+                return edge_color;
+            }
+        }
+
+        let material: Material = ray_intersection.shape.material();
+        let mut object_color: Radiance = self.shade(ray, ray_intersection);
+        if self.visibility(ray_intersection.shape)
+            .reflects(reflection_level, self.scene_characteristics.max_reflections) &&
+            material.reflectance > 0f64
+        {
+            // Object is reflective - recursively trace reflection ray
+            let reflection_ray = Ray::new(
+                self.offset_point(ray_intersection, true),
+                ray.reflection(ray_intersection.normal),
+            );
+
+            if let Some(reflection_color) = self.trace(&reflection_ray, reflection_level + 1u8) {
+                // Combine reflection color and object color
+                object_color =
+                    object_color * material.normal + reflection_color * material.reflectance;
+            }
+        }
+
+        object_color
+    }
+
+    // Raw value of a debug view for the pixel's center ray - see DebugView::finish
+    // Normals and uv are final colors
+    fn debug_sample(&self, x: usize, y: usize, debug_view: DebugView) -> Radiance {
+        let ray: Ray = match self.generate_ray(x, y, false) {
+            Some(ray) => ray,
+            None => return Radiance::black(),
+        };
+
+        let mut cost: TraversalCost = TraversalCost::default();
+        let ray_intersection: Option<Intersection> =
+            self.intersection_counted(&ray, RayKind::Camera, &mut cost);
+
+        let value: f64 = match (debug_view, ray_intersection) {
+            (DebugView::Intersections, _) => cost.intersection_tests as f64,
+            (DebugView::Traversals, _) => cost.nodes as f64,
+            (DebugView::Normals, Some(ray_intersection)) => {
+                let normal: Vector3<f64> =
+                    ray_intersection.shape.geometric_normal(ray_intersection.point);
+                return Radiance::new(
+                    (normal.x + 1f64) / 2f64,
+                    (normal.y + 1f64) / 2f64,
+                    (normal.z + 1f64) / 2f64,
+                );
+            }
+            (DebugView::Uv, Some(ray_intersection)) => {
+                let uv: Vector2<f64> = ray_intersection.shape.uv(ray_intersection.point);
+                return Radiance::new(uv.x - uv.x.floor(), uv.y - uv.y.floor(), 0f64);
+            }
+            (DebugView::Depth, Some(ray_intersection)) => ray_intersection.distance,
+            (DebugView::Depth, None) => -1f64,
+            (DebugView::ObjectId, Some(ray_intersection)) => {
+                (ray_intersection.shape.object_id() + 1) as f64
+            }
+            (_, None) => 0f64,
+        };
+
+        Radiance::new(value, value, value)
+    }
+
+    // False for pixels masked by the projection, e.g. outside a dome master's circle
+    fn in_frame(&self, x: usize, y: usize) -> bool {
+        match self.projection {
+            Projection::Perspective => true,
+            Projection::Fulldome { .. } => {
+                let view: &ViewCharacteristics = &self.view_characteristics;
+                camera::in_fulldome(
+                    (x as f64 + 0.5) / view.pixel_width as f64 - 0.5,
+                    (y as f64 + 0.5) / view.pixel_height as f64 - 0.5,
+                    view.pixel_width,
+                    view.pixel_height,
+                )
             }
         }
     }
 
     // Sample the given pixel by tracing one or more rays through it
-    pub fn sample(&mut self, x: usize, y: usize) -> Color {
-        let mut final_color: Color = Color::new(0f64, 0f64, 0f64);
+    pub fn sample(&mut self, x: usize, y: usize) -> Radiance {
+        self.sample_pixel(x, y, self.scene_characteristics.samples)
+    }
+
+    // One camera ray through the pixel - its color, with the object and distance of its
+    // primary hit for ID mattes and deep output
+    fn camera_sample(
+        &mut self,
+        x: usize,
+        y: usize,
+        jitter: bool,
+    ) -> (Option<(usize, f64)>, Radiance) {
+        let (color, hit) = match self.generate_ray(x, y, jitter) {
+            Some(ray) => self.trace_with_hit(&ray, 0u8),
+            None => (None, None),
+        };
+
+        (hit, color.unwrap_or_else(Radiance::black))
+    }
+
+    fn sample_pixel(&mut self, x: usize, y: usize, samples: usize) -> Radiance {
+        if !self.in_frame(x, y) {
+            return Radiance::black();
+        }
+
+        if let Some(debug_view) = self.scene_characteristics.debug_view {
+            return self.debug_sample(x, y, debug_view);
+        }
 
-        for s in 0..self.scene_characteristics.samples {
-            let super_sample: bool = s > 0;
-            let mut ray: Ray = self.generate_ray(x, y, super_sample);
+        let mut final_color: Radiance = Radiance::black();
+        // Primary hit object and distance, and color, of each sample for ID mattes and deep output
+        let record_hits: bool = self.cryptomatte.is_some() || self.deep.is_some();
+        let mut hits: Vec<(Option<(usize, f64)>, Radiance)> = Vec::new();
+
+        for s in 0..samples {
+            let (hit, color) = self.camera_sample(x, y, s > 0);
+            final_color += color;
+
+            if record_hits {
+                hits.push((hit, color));
+            }
+        }
 
-            if let Some(color) = self.trace(&ray, 0u8) {
+        if let Some(ref mut cryptomatte) = self.cryptomatte {
+            let objects: Vec<Option<usize>> =
+                hits.iter().map(|&(hit, _)| hit.map(|(object_id, _)| object_id)).collect();
+            cryptomatte.record(x, y, &objects);
+        }
+
+        if let Some(ref mut deep) = self.deep {
+            deep.record(x, y, &hits);
+        }
+
+        self.traversal.get_mut().camera_rays += samples as u64;
+        final_color / samples as f64
+    }
+
+    // Sample the pixel when the closest shape along its first, unjittered ray is
+    // already known - e.g. from the GPU backend
+    pub fn sample_with_primary(&mut self, x: usize, y: usize, primary: Option<Shape>) -> Radiance {
+        let start: Instant = Instant::now();
+
+        // The lens blocked the ray the primary hit was found for
+        let ray: Ray = match self.generate_ray(x, y, false) {
+            Some(ray) => ray,
+            None => return self.sample(x, y),
+        };
+
+        let mut final_color: Radiance = match primary {
+            // Recompute the hit in double precision - fall back to a full trace on disagreement,
+            // or when the camera doesn't see the object
+            Some(shape) if !self.visibility(shape).camera => {
+                self.trace(&ray, 0u8).unwrap_or_else(Radiance::black)
+            }
+            Some(shape) => match shape.intersect(&ray) {
+                Some(point) => {
+                    let ray_intersection: Intersection = Intersection::new(
+                        ray.direction,
+                        shape,
+                        point,
+                        shape.normal(point, ray.direction),
+                        (point - ray.origin).magnitude(),
+                    );
+                    self.trace_intersection(&ray, &ray_intersection, 0u8)
+                }
+                None => self.trace(&ray, 0u8).unwrap_or_else(Radiance::black),
+            },
+            None => Radiance::black(),
+        };
+
+        for _ in 1..self.scene_characteristics.samples {
+            let ray: Option<Ray> = self.generate_ray(x, y, true);
+
+            if let Some(color) = ray.and_then(|ray| self.trace(&ray, 0u8)) {
                 final_color += color;
             }
         }
 
+        self.traversal.get_mut().camera_rays += self.scene_characteristics.samples as u64;
+        self.count_time(start, Duration::ZERO);
         final_color / self.scene_characteristics.samples as f64
     }
 
@@ -251,24 +1136,142 @@ impl Scene {
         )
     }
 
-    // Draw part of the image - used for multi-threaded tracing
-    pub fn partial_draw(&mut self, threads: usize, thread_number: usize) {
+    fn allocate_pixels(&mut self) {
+        if self.pixels.is_empty() {
+            self.pixels = PixelBuffer::new(
+                self.view_characteristics.pixel_width,
+                self.view_characteristics.pixel_height,
+            );
+        }
+    }
+
+    // Draw part of the image - used for multi-threaded tracing. Once the budget's time is
+    // up, the rest of the pixels get a single sample so the image is still finished quickly
+    pub fn partial_draw(
+        &mut self,
+        threads: usize,
+        thread_number: usize,
+        budget: Option<&RenderBudget>,
+    ) {
+        self.allocate_pixels();
         let iterator: DrawIterator = self.draw_iterator(threads, thread_number);
+        let start: Instant = Instant::now();
+        let mut paused: Duration = Duration::ZERO;
 
         for (x, y) in iterator {
+            paused += self.pause_point(threads, thread_number, budget);
+            if self.paging_failed() {
+                break;
+            }
             if x % 10 == 0 && y == 0 {
-                println!("{}", x);
+                trace!("Column {}", x);
+            }
+            let samples: usize = if budget.is_some_and(|budget| budget.expired()) {
+                1
+            } else {
+                self.scene_characteristics.samples
+            };
+
+            let start: Option<Instant> = self.statistics.as_ref().map(|_| Instant::now());
+            let color: Radiance = self.sample_pixel(x, y, samples);
+            self.pixels.set_color(x, y, color);
+
+            if let (Some(start), Some(statistics)) = (start, self.statistics.as_mut()) {
+                statistics.record(x, y, samples, start.elapsed());
+            }
+
+            if let Some(ref progress) = self.progress {
+                progress.fetch_add(1, Ordering::Relaxed);
             }
-            self.color_buffer[x][y] = self.sample(x, y);
         }
+
+        self.count_time(start, paused);
+    }
+
+    // Add a jittered sample to each of the thread's pixels in turn, pass after pass, until
+    // the budget runs out - after partial_draw, so every pixel already has a color
+    pub fn refine(&mut self, threads: usize, thread_number: usize, budget: &RenderBudget) {
+        if self.scene_characteristics.debug_view.is_some() {
+            return;
+        }
+
+        let pixels: Vec<(usize, usize)> = self.draw_iterator(threads, thread_number)
+            .filter(|&(x, y)| self.in_frame(x, y))
+            .collect();
+        if pixels.is_empty() {
+            return;
+        }
+
+        let total_pixels: usize =
+            self.view_characteristics.pixel_width * self.view_characteristics.pixel_height;
+        let mut share: Option<usize> = budget.share(pixels.len(), total_pixels);
+
+        let start: Instant = Instant::now();
+        let mut paused: Duration = Duration::ZERO;
+        let mut samples: usize = self.scene_characteristics.samples;
+        loop {
+            for &(x, y) in &pixels {
+                paused += self.pause_point(threads, thread_number, Some(budget));
+                if share == Some(0) || self.paging_failed() || !budget.take() {
+                    self.count_time(start, paused);
+                    return;
+                }
+                share = share.map(|share| share - 1);
+
+                let start: Option<Instant> = self.statistics.as_ref().map(|_| Instant::now());
+                let (hit, color) = self.camera_sample(x, y, true);
+                self.traversal.get_mut().camera_rays += 1;
+
+                let mean: Radiance =
+                    (self.pixels.color(x, y) * samples as f64 + color) / (samples + 1) as f64;
+                self.pixels.set_color(x, y, mean);
+
+                if let Some(ref mut cryptomatte) = self.cryptomatte {
+                    cryptomatte.add(x, y, hit.map(|(object_id, _)| object_id), samples);
+                }
+                if let Some(ref mut deep) = self.deep {
+                    deep.add(x, y, hit, color, samples);
+                }
+
+                if let (Some(start), Some(statistics)) = (start, self.statistics.as_mut()) {
+                    statistics.add(x, y, 1, start.elapsed());
+                }
+            }
+
+            samples += 1;
+        }
+    }
+
+    // Sample each pixel of the tile - returned row-major
+    pub fn render_tile(&mut self, tile: &Tile) -> Vec<Radiance> {
+        let mut pixels: Vec<Radiance> = Vec::with_capacity(tile.pixel_count());
+        let start: Instant = Instant::now();
+
+        for (x, y) in tile.pixels() {
+            if self.paging_failed() {
+                pixels.push(Radiance::black());
+                continue;
+            }
+            pixels.push(self.sample(x, y));
+
+            if let Some(ref progress) = self.progress {
+                progress.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        self.count_time(start, Duration::ZERO);
+        pixels
     }
 
     // Draw the whole image
     pub fn draw(&mut self) {
+        self.allocate_pixels();
+
         // Ray tracing for each pixel
         for x in 0..self.view_characteristics.pixel_width {
             for y in 0..self.view_characteristics.pixel_height {
-                self.color_buffer[x][y] = self.sample(x, y);
+                let color: Radiance = self.sample(x, y);
+                self.pixels.set_color(x, y, color);
             }
         }
     }
@@ -0,0 +1,629 @@
+extern crate serde;
+extern crate serde_json;
+
+use self::serde::Serialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use super::color::Radiance;
+use super::exr::ExrImage;
+use super::scene::configuration::Configuration;
+
+// Scene files larger than this are rejected
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+// Request line and headers together
+const MAX_HEADER_BYTES: u64 = 16 * 1024;
+const MAX_HEADERS: usize = 64;
+// Idle connections are dropped after this long
+const TIMEOUT_SECONDS: u64 = 30;
+// Connections handled at once, each on its own thread - more are refused until one closes
+const MAX_CONNECTIONS: usize = 64;
+// Finished and failed renders kept for download - older ones are dropped as new ones finish
+const MAX_FINISHED_JOBS: usize = 16;
+// Queued and rendering jobs - further submissions are refused until one finishes
+const MAX_PENDING_JOBS: usize = 16;
+// Limits on submitted scenes. Each render thread draws into its own full size pixel
+// buffer, and the result is one more, so the pixels are counted once per thread plus one
+const MAX_BUFFERED_PIXELS: usize = 64 * 1024 * 1024;
+const MAX_SAMPLES: usize = 4096;
+const MAX_GENERATED_SHAPES: usize = 4 * 1024 * 1024;
+const MAX_LENS_ELEMENTS: usize = 64;
+// Renders run one at a time, so refinement past the first pass is bounded too
+const MAX_TIME_LIMIT_SECONDS: f64 = 3600f64;
+const MAX_SAMPLE_BUDGET: usize = 1024 * 1024 * 1024;
+
+// Small HTTP API for queueing renders
+//
+// POST /renders                  scene configuration JSON -> {"id": n}
+// GET  /renders/<id>             job status and progress
+// GET  /renders/<id>/image.png   tone mapped result
+// GET  /renders/<id>/image.exr   linear HDR result
+//
+// Submitted scenes may only read files under root, and relative paths are taken from it
+pub struct Server {
+    jobs: Arc<Mutex<Jobs>>,
+    root: Arc<PathBuf>,
+}
+
+// Jobs by id - ids aren't reused once a job is evicted
+#[derive(Default)]
+struct Jobs {
+    next_id: usize,
+    jobs: BTreeMap<usize, Job>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Queued,
+    Rendering,
+    Finished,
+    Failed,
+}
+
+struct Job {
+    configuration: Arc<Configuration>,
+    status: Status,
+    progress: Arc<AtomicUsize>,
+    color_buffer: Option<Vec<Vec<Radiance>>>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JobReport {
+    id: usize,
+    status: &'static str,
+    progress: f64,
+    width: usize,
+    height: usize,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JobCreated {
+    id: usize,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Rendering => "rendering",
+            Status::Finished => "finished",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+impl Status {
+    fn done(self) -> bool {
+        self == Status::Finished || self == Status::Failed
+    }
+}
+
+impl Jobs {
+    fn insert(&mut self, job: Job) -> usize {
+        let id: usize = self.next_id;
+        self.next_id += 1;
+        self.jobs.insert(id, job);
+        id
+    }
+
+    // Queued and rendering jobs
+    fn pending(&self) -> usize {
+        self.jobs.values().filter(|job| !job.status.done()).count()
+    }
+
+    // Drops the oldest finished and failed jobs beyond MAX_FINISHED_JOBS
+    fn evict(&mut self) {
+        let done: Vec<usize> = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.status.done())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in done.iter().take(done.len().saturating_sub(MAX_FINISHED_JOBS)) {
+            self.jobs.remove(id);
+        }
+    }
+}
+
+impl Job {
+    fn report(&self, id: usize) -> JobReport {
+        let pixels: usize = self.configuration.pixel_count();
+
+        JobReport {
+            id,
+            status: self.status.name(),
+            progress: self.progress.load(Ordering::Relaxed) as f64 / pixels.max(1) as f64,
+            width: self.configuration.width,
+            height: self.configuration.height,
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl Response {
+    fn new(status: u16, content_type: &'static str, body: Vec<u8>) -> Response {
+        Response {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    fn json<T: Serialize>(status: u16, value: &T) -> Response {
+        Response::new(
+            status,
+            "application/json",
+            serde_json::to_vec(value).unwrap(),
+        )
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        #[derive(Serialize)]
+        struct ErrorBody<'a> {
+            error: &'a str,
+        }
+
+        Response::json(status, &ErrorBody { error: message })
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            202 => "Accepted",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            431 => "Request Header Fields Too Large",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            self.reason(),
+            self.content_type,
+            self.body.len()
+        )?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+impl Request {
+    // A line cut short by the header limit has no newline
+    fn read_line<R: BufRead>(reader: &mut R) -> Result<String, Response> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).is_err() {
+            return Err(Response::error(400, "Unreadable request"));
+        }
+        if !line.ends_with('\n') {
+            return Err(Response::error(431, "Headers too large"));
+        }
+
+        Ok(line)
+    }
+
+    fn read(stream: &TcpStream) -> Result<Request, Response> {
+        let mut reader = BufReader::new(stream.take(MAX_HEADER_BYTES));
+
+        let request_line: String = Request::read_line(&mut reader)?;
+
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = match (parts.next(), parts.next()) {
+            (Some(method), Some(path)) => (String::from(method), String::from(path)),
+            _ => return Err(Response::error(400, "Malformed request line")),
+        };
+
+        // Headers - only the body length matters here
+        let mut content_length: usize = 0;
+        for count in 0.. {
+            let header: String = Request::read_line(&mut reader)?;
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if count == MAX_HEADERS {
+                return Err(Response::error(431, "Too many headers"));
+            }
+
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = match value.trim().parse() {
+                        Ok(length) => length,
+                        Err(_) => return Err(Response::error(400, "Invalid Content-Length")),
+                    };
+                }
+            }
+        }
+
+        if content_length > MAX_BODY_BYTES {
+            return Err(Response::error(413, "Scene too large"));
+        }
+
+        // Anything already buffered was taken from the header limit, so this is enough
+        reader.get_mut().set_limit(content_length as u64);
+        let mut body: Vec<u8> = vec![0; content_length];
+        if reader.read_exact(&mut body).is_err() {
+            return Err(Response::error(400, "Truncated body"));
+        }
+
+        Ok(Request { method, path, body })
+    }
+}
+
+impl Server {
+    pub fn new(root: PathBuf) -> Server {
+        Server {
+            jobs: Arc::new(Mutex::new(Jobs::default())),
+            root: Arc::new(root),
+        }
+    }
+
+    // Relative paths are read from root rather than the server's working directory
+    fn resolve_files(&self, configuration: &mut Configuration) {
+        for file in configuration.files_mut() {
+            if Path::new(file.as_str()).is_relative() {
+                *file = self.root.join(file.as_str()).to_string_lossy().into_owned();
+            }
+        }
+    }
+
+    // Meshes, grids and images must resolve, through any links, to somewhere under root
+    fn check_files(&self, configuration: &Configuration) -> Result<(), Response> {
+        let root: PathBuf = fs::canonicalize(&*self.root)
+            .map_err(|error| Response::error(500, &error.to_string()))?;
+
+        for file in configuration.files() {
+            match fs::canonicalize(file) {
+                Ok(ref path) if path.starts_with(&root) => {}
+                Ok(_) => {
+                    return Err(Response::error(
+                        403,
+                        &format!("{}: outside {}", file, root.display()),
+                    ))
+                }
+                Err(error) => return Err(Response::error(400, &format!("{}: {}", file, error))),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Bounds the memory and work a single render can ask for
+    fn check_limits(configuration: &Configuration) -> Result<(), Response> {
+        if configuration.width == 0 || configuration.height == 0 {
+            return Err(Response::error(400, "width and height must be at least 1"));
+        }
+
+        let cores: usize = thread::available_parallelism().map_or(1, |threads| threads.get());
+        if configuration.threads > cores {
+            return Err(Response::error(413, &format!("threads must be at most {}", cores)));
+        }
+
+        let buffered_pixels: Option<usize> = configuration
+            .pixel_count()
+            .checked_mul(configuration.thread_count() + 1);
+        if buffered_pixels.is_none_or(|pixels| pixels > MAX_BUFFERED_PIXELS) {
+            return Err(Response::error(
+                413,
+                &format!(
+                    "width x height x (threads + 1) must be at most {}",
+                    MAX_BUFFERED_PIXELS
+                ),
+            ));
+        }
+
+        if configuration.samples > MAX_SAMPLES {
+            return Err(Response::error(
+                413,
+                &format!("samples must be at most {}", MAX_SAMPLES),
+            ));
+        }
+
+        if configuration
+            .time_limit
+            .is_some_and(|seconds| !(0f64..=MAX_TIME_LIMIT_SECONDS).contains(&seconds))
+        {
+            return Err(Response::error(
+                413,
+                &format!("time_limit must be between 0 and {} seconds", MAX_TIME_LIMIT_SECONDS),
+            ));
+        }
+
+        if configuration.sample_budget.is_some_and(|samples| samples > MAX_SAMPLE_BUDGET) {
+            return Err(Response::error(
+                413,
+                &format!("sample_budget must be at most {}", MAX_SAMPLE_BUDGET),
+            ));
+        }
+
+        if configuration.lens_elements.len() > MAX_LENS_ELEMENTS {
+            return Err(Response::error(
+                413,
+                &format!("lens_elements may have at most {} elements", MAX_LENS_ELEMENTS),
+            ));
+        }
+
+        // Spooled geometry would be written to the server's disk
+        if configuration.memory_limit_mb.is_some() {
+            return Err(Response::error(400, "memory_limit_mb isn't accepted by the server"));
+        }
+
+        // A slowed render only holds up the renders queued behind it
+        if configuration.low_priority {
+            return Err(Response::error(400, "low_priority isn't accepted by the server"));
+        }
+
+        let generated_shapes: Option<usize> = configuration
+            .objects
+            .iter()
+            .filter_map(|object| object.generator.as_ref())
+            .try_fold(0usize, |total, generator| {
+                generator.max_shapes().and_then(|shapes| total.checked_add(shapes))
+            });
+        if generated_shapes.is_none_or(|shapes| shapes > MAX_GENERATED_SHAPES) {
+            return Err(Response::error(
+                413,
+                &format!("generators may make at most {} shapes", MAX_GENERATED_SHAPES),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Renders run one at a time - each render already uses every configured thread
+    fn render_worker(jobs: Arc<Mutex<Jobs>>, queue: Receiver<usize>) {
+        for id in queue {
+            let (configuration, progress) = {
+                let mut jobs = jobs.lock().unwrap();
+                let job: &mut Job = jobs.jobs.get_mut(&id).unwrap();
+                job.status = Status::Rendering;
+                (Arc::clone(&job.configuration), Arc::clone(&job.progress))
+            };
+
+            // A panicking render fails the job, not the server
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                super::trace(&configuration, &progress)
+            }));
+
+            let mut jobs = jobs.lock().unwrap();
+            {
+                let job: &mut Job = jobs.jobs.get_mut(&id).unwrap();
+                match result {
+                    Ok(Ok(color_buffer)) => {
+                        job.color_buffer = Some(color_buffer);
+                        job.status = Status::Finished;
+                    }
+                    Ok(Err(error)) => {
+                        job.error = Some(error.to_string());
+                        job.status = Status::Failed;
+                    }
+                    Err(_) => {
+                        job.error = Some(String::from("Render failed"));
+                        job.status = Status::Failed;
+                    }
+                }
+            }
+            jobs.evict();
+        }
+    }
+
+    fn submit(&self, queue: &Sender<usize>, body: &[u8]) -> Response {
+        // Checked as the command line checks scene files
+        let parsed: Result<Configuration, String> = str::from_utf8(body)
+            .map_err(|error| error.to_string())
+            .and_then(Configuration::from_json);
+        let mut configuration: Configuration = match parsed {
+            Ok(configuration) => configuration,
+            Err(error) => return Response::error(400, &format!("Invalid scene: {}", error)),
+        };
+        if let Err(response) = Server::check_limits(&configuration) {
+            return response;
+        }
+        self.resolve_files(&mut configuration);
+        if let Err(response) = self.check_files(&configuration) {
+            return response;
+        }
+
+        let id: usize = {
+            let mut jobs = self.jobs.lock().unwrap();
+            if jobs.pending() >= MAX_PENDING_JOBS {
+                return Response::error(503, "Too many renders queued, try again later");
+            }
+
+            jobs.insert(Job {
+                configuration: Arc::new(configuration),
+                status: Status::Queued,
+                progress: Arc::new(AtomicUsize::new(0)),
+                color_buffer: None,
+                error: None,
+            })
+        };
+
+        queue.send(id).unwrap();
+
+        Response::json(202, &JobCreated { id })
+    }
+
+    fn image(&self, id: usize, format: &str) -> Response {
+        let jobs = self.jobs.lock().unwrap();
+        let job: &Job = match jobs.jobs.get(&id) {
+            Some(job) => job,
+            None => return Response::error(404, "Unknown render"),
+        };
+
+        let color_buffer: &Vec<Vec<Radiance>> = match job.color_buffer {
+            Some(ref color_buffer) => color_buffer,
+            None => return Response::error(409, "Render not finished"),
+        };
+
+        let width: usize = job.configuration.width;
+        let height: usize = job.configuration.height;
+
+        let encoded: io::Result<Vec<u8>> = match format {
+            "png" => {
+                let mut tone_mapped: Vec<Vec<Radiance>> = color_buffer.clone();
+                super::tone_map(&job.configuration, &mut tone_mapped);
+                super::encode_png(&tone_mapped, width, height)
+            }
+            _ => {
+                let mut exr: Vec<u8> = Vec::new();
+                ExrImage::from_radiance(color_buffer, width, height)
+                    .write(&mut exr)
+                    .map(|_| exr)
+            }
+        };
+
+        match encoded {
+            Ok(body) if format == "png" => Response::new(200, "image/png", body),
+            Ok(body) => Response::new(200, "image/x-exr", body),
+            Err(error) => Response::error(500, &error.to_string()),
+        }
+    }
+
+    fn route(&self, queue: &Sender<usize>, request: &Request) -> Response {
+        let segments: Vec<&str> = request
+            .path
+            .trim_matches('/')
+            .split('/')
+            .collect();
+
+        if segments[0] != "renders" {
+            return Response::error(404, "Not found");
+        }
+
+        if segments.len() == 1 {
+            return match request.method.as_str() {
+                "POST" => self.submit(queue, &request.body),
+                _ => Response::error(405, "Use POST to submit a scene"),
+            };
+        }
+
+        if request.method != "GET" {
+            return Response::error(405, "Method not allowed");
+        }
+
+        let id: usize = match segments[1].parse() {
+            Ok(id) => id,
+            _ => return Response::error(404, "Unknown render"),
+        };
+
+        match &segments[2..] {
+            [] => match self.jobs.lock().unwrap().jobs.get(&id) {
+                Some(job) => Response::json(200, &job.report(id)),
+                None => Response::error(404, "Unknown render"),
+            },
+            ["image.png"] => self.image(id, "png"),
+            ["image.exr"] => self.image(id, "exr"),
+            _ => Response::error(404, "Not found"),
+        }
+    }
+
+    fn handle(&self, queue: &Sender<usize>, mut stream: TcpStream) -> io::Result<()> {
+        let timeout: Option<Duration> = Some(Duration::from_secs(TIMEOUT_SECONDS));
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+
+        let response: Response = match Request::read(&stream) {
+            Ok(request) => self.route(queue, &request),
+            Err(response) => response,
+        };
+
+        response.write(&mut stream)
+    }
+
+    pub fn run(&self, address: &str) -> io::Result<()> {
+        let listener: TcpListener = TcpListener::bind(address)?;
+        info!("Listening on http://{}", listener.local_addr()?);
+
+        let (queue, receiver): (Sender<usize>, Receiver<usize>) = mpsc::channel();
+        let jobs = Arc::clone(&self.jobs);
+        thread::spawn(move || Server::render_worker(jobs, receiver));
+
+        let connections: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            let mut stream: TcpStream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    warn!("Connection failed: {}", error);
+                    continue;
+                }
+            };
+
+            if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                // Small enough to fit the socket's buffer, so this doesn't wait on the client
+                let refused: io::Result<()> = stream
+                    .set_write_timeout(Some(Duration::from_secs(1)))
+                    .and_then(|_| {
+                        Response::error(503, "Too many connections, try again later")
+                            .write(&mut stream)
+                    });
+                if let Err(error) = refused {
+                    warn!("Request failed: {}", error);
+                }
+                continue;
+            }
+
+            let server = Server {
+                jobs: Arc::clone(&self.jobs),
+                root: Arc::clone(&self.root),
+            };
+            let queue = queue.clone();
+            let handled: Arc<AtomicUsize> = Arc::clone(&connections);
+
+            let spawned: io::Result<thread::JoinHandle<()>> =
+                thread::Builder::new().spawn(move || {
+                    if let Err(error) = server.handle(&queue, stream) {
+                        warn!("Request failed: {}", error);
+                    }
+                    handled.fetch_sub(1, Ordering::SeqCst);
+                });
+            if let Err(error) = spawned {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                warn!("Couldn't start a connection thread: {}", error);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new(PathBuf::from("."))
+    }
+}
@@ -0,0 +1,178 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use super::camera::Camera;
+use super::color::Radiance;
+use super::cryptomatte::Cryptomatte;
+use super::deep::DeepBuffer;
+use super::diagnostics::PixelStatistics;
+use super::scene::configuration::Configuration;
+use super::RenderPasses;
+use super::tuning::TraversalStatistics;
+
+// How the two eyes are combined into one image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StereoLayout {
+    // Left eye in the left half of the image, right eye in the right half
+    #[default]
+    SideBySide,
+    // Red from the left eye, green and blue from the right - for red-cyan glasses
+    Anaglyph,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+// Render a left and right eye from either side of the configured camera
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stereo {
+    // Distance between the eyes, in scene units
+    pub interocular_distance: f64,
+    // Distance from the camera at which the eyes converge - things there
+    // appear at screen depth. Defaults to the camera target
+    #[serde(default)]
+    pub convergence_distance: Option<f64>,
+    #[serde(default)]
+    pub layout: StereoLayout,
+}
+
+impl Stereo {
+    // The configuration with the camera moved to one eye and turned toward the
+    // convergence point. Side by side eyes each get half of the image.
+    pub fn eye_configuration(&self, configuration: &Configuration, eye: Eye) -> Configuration {
+        let camera: Camera = configuration.camera();
+        let direction: Vector3<f64> = camera.direction();
+        let right: Vector3<f64> = direction.cross(camera.up).normalize();
+
+        let convergence_distance: f64 = self.convergence_distance
+            .unwrap_or_else(|| (camera.target - camera.origin).magnitude());
+        let convergence: Vector3<f64> = camera.origin + direction * convergence_distance;
+
+        let offset: f64 = match eye {
+            Eye::Left => -self.interocular_distance / 2f64,
+            Eye::Right => self.interocular_distance / 2f64,
+        };
+        let origin: Vector3<f64> = camera.origin + right * offset;
+
+        let mut eye_configuration: Configuration = configuration.clone();
+        eye_configuration.stereo = None;
+        // The eyes are rendered one after the other
+        eye_configuration.time_limit = configuration.time_limit.map(|seconds| seconds / 2f64);
+        eye_configuration.sample_budget = configuration.sample_budget.map(|samples| samples / 2);
+        eye_configuration.camera_position = vec![origin.x, origin.y, origin.z];
+        eye_configuration.camera_target = vec![convergence.x, convergence.y, convergence.z];
+
+        if self.layout == StereoLayout::SideBySide {
+            let width: usize = match eye {
+                Eye::Left => configuration.width / 2,
+                Eye::Right => configuration.width - configuration.width / 2,
+            };
+
+            // Keep the pixels square
+            eye_configuration.set_viewport_width(
+                configuration.viewport_width() * width as f64 / configuration.width as f64,
+            );
+            eye_configuration.width = width;
+        }
+
+        eye_configuration
+    }
+
+    // Pixels traced for a configured image of the given size
+    pub fn pixel_count(&self, width: usize, height: usize) -> usize {
+        match self.layout {
+            StereoLayout::SideBySide => width * height,
+            StereoLayout::Anaglyph => 2 * width * height,
+        }
+    }
+
+    pub fn combine(&self, left: Vec<Vec<Radiance>>, right: Vec<Vec<Radiance>>) -> Vec<Vec<Radiance>> {
+        match self.layout {
+            StereoLayout::SideBySide => left.into_iter().chain(right).collect(),
+            StereoLayout::Anaglyph => left
+                .iter()
+                .zip(right.iter())
+                .map(|(left_column, right_column)| {
+                    left_column
+                        .iter()
+                        .zip(right_column.iter())
+                        .map(|(left, right)| Radiance::new(left.r, right.g, right.b))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    pub fn combine_passes(&self, left: RenderPasses, right: RenderPasses) -> RenderPasses {
+        let mut traversal: TraversalStatistics = left.traversal;
+        traversal.add(&right.traversal);
+
+        RenderPasses {
+            statistics: self.combine_statistics(left.statistics, right.statistics),
+            cryptomatte: self.combine_cryptomattes(left.cryptomatte, right.cryptomatte),
+            deep: self.combine_deep(left.deep, right.deep),
+            traversal,
+        }
+    }
+
+    // Anaglyph eyes see different objects at the same pixel, so only
+    // side-by-side images get ID mattes
+    fn combine_cryptomattes(
+        &self,
+        left: Option<Cryptomatte>,
+        right: Option<Cryptomatte>,
+    ) -> Option<Cryptomatte> {
+        match (self.layout, left, right) {
+            (StereoLayout::SideBySide, Some(mut left), Some(right)) => {
+                left.coverage.extend(right.coverage);
+                Some(left)
+            }
+            _ => None,
+        }
+    }
+
+    // Likewise deep samples
+    fn combine_deep(&self, left: Option<DeepBuffer>, right: Option<DeepBuffer>) -> Option<DeepBuffer> {
+        match (self.layout, left, right) {
+            (StereoLayout::SideBySide, Some(mut left), Some(right)) => {
+                left.pixels.extend(right.pixels);
+                Some(left)
+            }
+            _ => None,
+        }
+    }
+
+    // Anaglyph pixels cost the sum of both eyes
+    fn combine_statistics(
+        &self,
+        left: Option<PixelStatistics>,
+        right: Option<PixelStatistics>,
+    ) -> Option<PixelStatistics> {
+        let (mut left, right) = match (left, right) {
+            (Some(left), Some(right)) => (left, right),
+            _ => return None,
+        };
+
+        match self.layout {
+            StereoLayout::SideBySide => {
+                left.samples.extend(right.samples);
+                left.milliseconds.extend(right.milliseconds);
+            }
+            StereoLayout::Anaglyph => {
+                for (x, column) in right.samples.iter().enumerate() {
+                    for (y, samples) in column.iter().enumerate() {
+                        left.samples[x][y] += samples;
+                        left.milliseconds[x][y] += right.milliseconds[x][y];
+                    }
+                }
+            }
+        }
+
+        Some(left)
+    }
+}
@@ -0,0 +1,65 @@
+// Rectangular region of the image, in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Tile {
+        Tile {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // Cover the image with tiles of at most tile_size x tile_size, row by row
+    pub fn split(width: usize, height: usize, tile_size: usize) -> Vec<Tile> {
+        let mut tiles: Vec<Tile> = Vec::new();
+
+        for y in (0..height).step_by(tile_size) {
+            for x in (0..width).step_by(tile_size) {
+                tiles.push(Tile::new(
+                    x,
+                    y,
+                    tile_size.min(width - x),
+                    tile_size.min(height - y),
+                ));
+            }
+        }
+
+        tiles
+    }
+
+    // Divide the tile into at most n horizontal bands
+    pub fn bands(&self, n: usize) -> Vec<Tile> {
+        let band_height: usize = self.height.div_ceil(n.max(1));
+
+        (self.y..self.y + self.height)
+            .step_by(band_height.max(1))
+            .map(|y| {
+                Tile::new(
+                    self.x,
+                    y,
+                    self.width,
+                    band_height.min(self.y + self.height - y),
+                )
+            })
+            .collect()
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    // Row-major (x, y) coordinates
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> {
+        let tile: Tile = *self;
+        (tile.y..tile.y + tile.height)
+            .flat_map(move |y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+    }
+}
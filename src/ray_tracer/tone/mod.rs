@@ -1,8 +1,22 @@
-use super::color::Color;
+use super::color::Radiance;
+
+// How auto exposure measures the image, e.g.
+// "auto_exposure": {"metering": "percentile", "percentile": 0.9}. The metered luminance of
+// the lit pixels is mapped to reinhard_key_value before tone mapping; unlit background is
+// ignored and .exr output is unaffected
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "metering")]
+pub enum AutoExposure {
+    // Log average luminance
+    Average,
+    // Luminance at the given fraction through the sorted pixels - high
+    // percentiles keep highlights from blowing out
+    Percentile { percentile: f64 },
+}
 
 // e ^ (1/n SUM( ln( luminance[x][y] + delta ) ) )
 pub fn log_average_luminance(
-    color_buffer: &mut Vec<Vec<Color>>,
+    color_buffer: &mut Vec<Vec<Radiance>>,
     width: usize,
     height: usize,
     delta: f64,
@@ -17,18 +31,72 @@ pub fn log_average_luminance(
     (sum / (width * height) as f64).exp()
 }
 
+// Luminance of the lit pixels as measured by auto exposure. Unlit pixels, e.g.
+// background, are ignored so they can't drag the exposure up. None if nothing is lit.
+pub fn metered_luminance(color_buffer: &[Vec<Radiance>], auto_exposure: AutoExposure) -> Option<f64> {
+    let mut luminances: Vec<f64> = color_buffer
+        .iter()
+        .flat_map(|column| column.iter().map(|color| color.to_luminance()))
+        .filter(|luminance| *luminance > 0f64)
+        .collect();
+
+    if luminances.is_empty() {
+        return None;
+    }
+
+    match auto_exposure {
+        AutoExposure::Average => Some(
+            (luminances.iter().map(|luminance| luminance.ln()).sum::<f64>() /
+                luminances.len() as f64)
+                .exp(),
+        ),
+        AutoExposure::Percentile { percentile } => {
+            luminances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let index: f64 = (luminances.len() - 1) as f64 * percentile.clamp(0f64, 1f64);
+            Some(luminances[index.round() as usize])
+        }
+    }
+}
+
 pub fn reinhard_tone_correction(
-    color_buffer: &mut Vec<Vec<Color>>,
+    color_buffer: &mut Vec<Vec<Radiance>>,
     width: usize,
     height: usize,
     key_value: f64,
     delta: f64,
 ) {
     let scale_factor: f64 = key_value / log_average_luminance(color_buffer, width, height, delta);
-    for x in 0..width {
-        for y in 0..height {
-            let color: Color = color_buffer[x][y] * scale_factor;
-            color_buffer[x][y] = color / (color + 1f64);
+    reinhard_curve(color_buffer, scale_factor);
+}
+
+// Reinhard, with auto exposure's metered luminance mapped to the key value in
+// place of the log average of every pixel. A fixed exposure skips metering.
+pub fn tone_correction(
+    color_buffer: &mut Vec<Vec<Radiance>>,
+    width: usize,
+    height: usize,
+    key_value: f64,
+    delta: f64,
+    auto_exposure: Option<AutoExposure>,
+    exposure: Option<f64>,
+) {
+    if let Some(exposure) = exposure {
+        reinhard_curve(color_buffer, exposure);
+        return;
+    }
+
+    match auto_exposure.and_then(|auto_exposure| metered_luminance(color_buffer, auto_exposure)) {
+        Some(luminance) => reinhard_curve(color_buffer, key_value / luminance),
+        None => reinhard_tone_correction(color_buffer, width, height, key_value, delta),
+    }
+}
+
+// Scale, then compress into 0 - 1
+fn reinhard_curve(color_buffer: &mut [Vec<Radiance>], scale_factor: f64) {
+    for column in color_buffer.iter_mut() {
+        for pixel in column.iter_mut() {
+            let color: Radiance = *pixel * scale_factor;
+            *pixel = color / (color + 1f64);
         }
     }
 }
@@ -0,0 +1,343 @@
+extern crate serde_json;
+
+use std::fs;
+use std::io;
+
+use ray_tracer::kd_tree::TraversalCost;
+use ray_tracer::scene::configuration::Configuration;
+
+// Settings tried for each knob, in order
+const KD_TREE_DEPTHS: [usize; 7] = [8, 12, 16, 20, 24, 28, 32];
+const KD_TREE_LEAF_SIZES: [usize; 5] = [1, 2, 4, 8, 16];
+const TILE_SIZES: [usize; 5] = [16, 32, 64, 128, 256];
+
+// Renders kept in a profile - older ones describe a scene that has likely moved on
+const MAX_RENDERS: usize = 50;
+
+// Share of a time limit the first pass may take before refinement, when tuning samples
+const FIRST_PASS_SHARE: f64 = 0.5;
+
+// Intersection work over a whole render
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TraversalStatistics {
+    // Primary samples, including refinement
+    pub camera_rays: u64,
+    // Rays intersected with the geometry, of any kind
+    pub traversals: u64,
+    pub nodes: u64,
+    pub intersection_tests: u64,
+    // Thread-seconds spent tracing, without loading or building the tree
+    #[serde(default)]
+    pub seconds: f64,
+}
+
+impl TraversalStatistics {
+    pub fn count(&mut self, cost: &TraversalCost) {
+        self.traversals += 1;
+        self.nodes += cost.nodes as u64;
+        self.intersection_tests += cost.intersection_tests as u64;
+    }
+
+    pub fn add(&mut self, other: &TraversalStatistics) {
+        self.camera_rays += other.camera_rays;
+        self.traversals += other.traversals;
+        self.nodes += other.nodes;
+        self.intersection_tests += other.intersection_tests;
+        self.seconds += other.seconds;
+    }
+}
+
+// The knobs a profile tunes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TuningSettings {
+    pub max_kd_tree_depth: usize,
+    pub kd_tree_leaf_size: usize,
+    // Only for tiled output
+    pub tile_size: Option<usize>,
+}
+
+impl TuningSettings {
+    pub fn new(configuration: &Configuration, tile_size: Option<usize>) -> TuningSettings {
+        TuningSettings {
+            max_kd_tree_depth: configuration.max_kd_tree_depth,
+            kd_tree_leaf_size: configuration.kd_tree_leaf_size,
+            tile_size,
+        }
+    }
+
+    pub fn apply(&self, configuration: &mut Configuration) {
+        configuration.max_kd_tree_depth = self.max_kd_tree_depth;
+        configuration.kd_tree_leaf_size = self.kd_tree_leaf_size;
+    }
+
+    // Settings one step away on a single knob
+    fn neighbors(&self) -> Vec<TuningSettings> {
+        let mut neighbors: Vec<TuningSettings> = Vec::new();
+
+        for depth in steps(&KD_TREE_DEPTHS, self.max_kd_tree_depth) {
+            neighbors.push(TuningSettings {
+                max_kd_tree_depth: depth,
+                ..*self
+            });
+        }
+        for leaf_size in steps(&KD_TREE_LEAF_SIZES, self.kd_tree_leaf_size) {
+            neighbors.push(TuningSettings {
+                kd_tree_leaf_size: leaf_size,
+                ..*self
+            });
+        }
+        if let Some(tile_size) = self.tile_size {
+            for tile_size in steps(&TILE_SIZES, tile_size) {
+                neighbors.push(TuningSettings {
+                    tile_size: Some(tile_size),
+                    ..*self
+                });
+            }
+        }
+
+        neighbors
+    }
+}
+
+// The closest settings above and below value
+fn steps(settings: &[usize], value: usize) -> Vec<usize> {
+    let above: Option<usize> = settings.iter().cloned().find(|&setting| setting > value);
+    let below: Option<usize> = settings.iter().cloned().rev().find(|&setting| setting < value);
+
+    above.into_iter().chain(below).collect()
+}
+
+// One render of the scene
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RenderRecord {
+    pub settings: TuningSettings,
+    // Loading, building the tree and tracing
+    pub seconds: f64,
+    pub threads: usize,
+    pub statistics: TraversalStatistics,
+}
+
+impl RenderRecord {
+    // Thread-seconds spent tracing - profiles from before it was counted fall back on the
+    // whole render's time
+    fn trace_seconds(&self) -> f64 {
+        if self.statistics.seconds > 0f64 {
+            self.statistics.seconds
+        } else {
+            self.seconds * self.threads as f64
+        }
+    }
+
+    fn seconds_per_traversal(&self) -> f64 {
+        self.trace_seconds() / self.statistics.traversals.max(1) as f64
+    }
+}
+
+// Earlier renders of a scene, kept next to its output so later renders of it can pick
+// faster settings - a render tries one untried neighbor of the fastest settings so far,
+// so repeated renders settle on the best nearby
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RenderProfile {
+    // Key of the geometry the renders were of
+    pub scene: String,
+    pub renders: Vec<RenderRecord>,
+}
+
+impl RenderProfile {
+    // The profile in file if it is of this scene, otherwise an empty one
+    pub fn load(file: &str, scene: &str) -> RenderProfile {
+        let profile: Option<RenderProfile> = fs::read_to_string(file)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<RenderProfile>(&contents).ok())
+            .filter(|profile| profile.scene == scene);
+
+        profile.unwrap_or_else(|| RenderProfile {
+            scene: scene.to_string(),
+            renders: Vec::new(),
+        })
+    }
+
+    pub fn save(&self, file: &str) -> io::Result<()> {
+        let contents: String =
+            serde_json::to_string_pretty(self).map_err(|error| io::Error::other(error.to_string()))?;
+        fs::write(file, contents)
+    }
+
+    pub fn record(&mut self, render: RenderRecord) {
+        self.renders.push(render);
+        if self.renders.len() > MAX_RENDERS {
+            let excess: usize = self.renders.len() - MAX_RENDERS;
+            self.renders.drain(..excess);
+        }
+    }
+
+    // Settings for the next render - configured is used until a render has been profiled
+    pub fn next_settings(&self, configured: TuningSettings) -> TuningSettings {
+        let tiled: bool = configured.tile_size.is_some();
+        let renders: Vec<&RenderRecord> = self.renders
+            .iter()
+            .filter(|render| render.settings.tile_size.is_some() == tiled)
+            .collect();
+
+        let cost = |settings: TuningSettings| RenderProfile::mean_cost(&renders, settings);
+        let best: TuningSettings = match renders
+            .iter()
+            .map(|render| render.settings)
+            .min_by(|a, b| cost(*a).partial_cmp(&cost(*b)).unwrap())
+        {
+            Some(best) => best,
+            None => return configured,
+        };
+
+        best.neighbors()
+            .into_iter()
+            .find(|neighbor| renders.iter().all(|render| render.settings != *neighbor))
+            .unwrap_or(best)
+    }
+
+    fn mean_cost(renders: &[&RenderRecord], settings: TuningSettings) -> f64 {
+        let costs: Vec<f64> = renders
+            .iter()
+            .filter(|render| render.settings == settings)
+            .map(|render| render.seconds_per_traversal())
+            .collect();
+
+        costs.iter().sum::<f64>() / costs.len() as f64
+    }
+
+    // Samples per pixel for the first pass, fewer than configured when the last render
+    // says they would take most of the time limit, leaving the rest to refinement
+    pub fn first_pass_samples(&self, configuration: &Configuration) -> usize {
+        let time_limit: f64 = match configuration.time_limit {
+            Some(time_limit) => time_limit,
+            None => return configuration.samples,
+        };
+        let last: &RenderRecord = match self.renders
+            .iter()
+            .rev()
+            .find(|render| render.statistics.camera_rays > 0)
+        {
+            Some(last) => last,
+            None => return configuration.samples,
+        };
+
+        // Assuming the threads scale evenly
+        let seconds_per_sample: f64 = last.trace_seconds()
+            / configuration.thread_count() as f64
+            / last.statistics.camera_rays as f64;
+        let pixels: f64 = (configuration.width * configuration.height) as f64;
+        let affordable: f64 = time_limit * FIRST_PASS_SHARE / (pixels * seconds_per_sample);
+
+        configuration.samples.min(affordable.max(1f64) as usize)
+    }
+}
+
+// Identifies the scene's geometry - FNV-1a of the object definitions, so any edit to them
+// starts a new profile
+pub fn scene_key(configuration: &Configuration) -> String {
+    let objects: String = serde_json::to_string(&configuration.objects).unwrap_or_default();
+    let hash: u64 = objects.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configuration(time_limit: Option<f64>) -> Configuration {
+        let json: String = format!(
+            r#"{{
+                "threads": 2, "samples": 16, "use_kd_tree": true, "max_kd_tree_depth": 16,
+                "kd_tree_leaf_size": 4,
+                "time_limit": {}, "width": 10, "height": 10,
+                "camera_position": [0, 0, 0], "camera_target": [0, 1, 0], "camera_up": [0, 0, 1],
+                "viewport_distance": 1, "viewport_width": 1, "lights": [], "objects": [],
+                "max_reflections": 1, "reinhard_key_value": 0.18, "reinhard_delta": 0.01
+            }}"#,
+            serde_json::to_string(&time_limit).unwrap()
+        );
+
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn settings(max_kd_tree_depth: usize, kd_tree_leaf_size: usize) -> TuningSettings {
+        TuningSettings {
+            max_kd_tree_depth,
+            kd_tree_leaf_size,
+            tile_size: None,
+        }
+    }
+
+    // A render taking seconds of tracing for 1000 traversals of 100 camera rays
+    fn render(settings: TuningSettings, seconds: f64) -> RenderRecord {
+        RenderRecord {
+            settings,
+            seconds: 100f64,
+            threads: 2,
+            statistics: TraversalStatistics {
+                camera_rays: 100,
+                traversals: 1000,
+                seconds,
+                ..TraversalStatistics::default()
+            },
+        }
+    }
+
+    #[test]
+    fn steps_are_the_neighboring_settings() {
+        assert_eq!(steps(&KD_TREE_DEPTHS, 16), vec![20, 12]);
+        assert_eq!(steps(&KD_TREE_DEPTHS, 8), vec![12]);
+        assert_eq!(steps(&KD_TREE_DEPTHS, 32), vec![28]);
+        assert_eq!(steps(&KD_TREE_DEPTHS, 18), vec![20, 16]);
+        assert_eq!(steps(&KD_TREE_DEPTHS, 50), vec![32]);
+    }
+
+    #[test]
+    fn unprofiled_scenes_use_the_configured_settings() {
+        let profile: RenderProfile = RenderProfile::default();
+        assert_eq!(profile.next_settings(settings(16, 4)), settings(16, 4));
+    }
+
+    #[test]
+    fn next_settings_try_an_untried_neighbor_of_the_fastest() {
+        let mut profile: RenderProfile = RenderProfile::default();
+        profile.record(render(settings(16, 4), 2f64));
+        profile.record(render(settings(20, 4), 1f64));
+        assert_eq!(profile.next_settings(settings(16, 4)), settings(24, 4));
+
+        // Every neighbor tried and slower - stay on the fastest
+        for &(depth, leaf_size) in &[(24, 4), (20, 8), (20, 2)] {
+            profile.record(render(settings(depth, leaf_size), 3f64));
+        }
+        assert_eq!(profile.next_settings(settings(16, 4)), settings(20, 4));
+    }
+
+    #[test]
+    fn tiled_and_untiled_renders_are_profiled_apart() {
+        let mut profile: RenderProfile = RenderProfile::default();
+        profile.record(render(settings(20, 4), 1f64));
+
+        let tiled: TuningSettings = TuningSettings {
+            tile_size: Some(64),
+            ..settings(16, 4)
+        };
+        assert_eq!(profile.next_settings(tiled), tiled);
+    }
+
+    #[test]
+    fn first_pass_samples_fit_the_time_limit() {
+        let mut profile: RenderProfile = RenderProfile::default();
+        assert_eq!(profile.first_pass_samples(&configuration(Some(1f64))), 16);
+
+        // 0.02 thread-seconds per camera ray over 2 threads, for 100 pixels - half of 4
+        // seconds affords 2 samples per pixel
+        profile.record(render(settings(16, 4), 2f64));
+        assert_eq!(profile.first_pass_samples(&configuration(Some(4f64))), 2);
+        assert_eq!(profile.first_pass_samples(&configuration(Some(1000f64))), 16);
+        assert_eq!(profile.first_pass_samples(&configuration(Some(0.001))), 1);
+        assert_eq!(profile.first_pass_samples(&configuration(None)), 16);
+    }
+}
@@ -0,0 +1,39 @@
+// How far secondary rays start off the surface they leave, so rounding doesn't let them
+// hit it again - a micrometre, whatever the scene's units
+const SURFACE_BIAS_METERS: f64 = 1e-6;
+
+// Length of one unit of scene or model coordinates. Objects with their own units are scaled
+// into the scene's on import, e.g. a CAD model in millimetres in a scene in meters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    Millimeters,
+    Centimeters,
+    #[default]
+    Meters,
+    Kilometers,
+    Inches,
+    Feet,
+}
+
+impl Units {
+    pub fn meters(&self) -> f64 {
+        match *self {
+            Units::Millimeters => 0.001,
+            Units::Centimeters => 0.01,
+            Units::Meters => 1f64,
+            Units::Kilometers => 1000f64,
+            Units::Inches => 0.0254,
+            Units::Feet => 0.3048,
+        }
+    }
+
+    // Factor taking coordinates in these units to the other
+    pub fn scale_to(&self, other: Units) -> f64 {
+        self.meters() / other.meters()
+    }
+
+    pub fn surface_bias(&self) -> f64 {
+        SURFACE_BIAS_METERS / self.meters()
+    }
+}
@@ -0,0 +1,48 @@
+// Per-object limits on which rays see an object and how far reflections off it go,
+// for skipping work where it doesn't show, e.g. a shadow-only stand-in. Rays pass straight
+// through hidden surfaces, at the cost of another traversal for each
+
+// What a ray is traced for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayKind {
+    Camera,
+    Reflection,
+    Shadow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObjectVisibility {
+    pub camera: bool,
+    pub reflections: bool,
+    pub shadows: bool,
+    // Reflection depth for rays reflecting off the object, below the global limit
+    pub max_reflections: Option<u8>,
+}
+
+impl Default for ObjectVisibility {
+    fn default() -> ObjectVisibility {
+        ObjectVisibility {
+            camera: true,
+            reflections: true,
+            shadows: true,
+            max_reflections: None,
+        }
+    }
+}
+
+impl ObjectVisibility {
+    pub fn visible_to(&self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.camera,
+            RayKind::Reflection => self.reflections,
+            RayKind::Shadow => self.shadows,
+        }
+    }
+
+    // Whether a ray at the given reflection level reflects off the object
+    pub fn reflects(&self, reflection_level: u8, max_reflections: u8) -> bool {
+        reflection_level < self.max_reflections.map_or(max_reflections, |limit| {
+            limit.min(max_reflections)
+        })
+    }
+}
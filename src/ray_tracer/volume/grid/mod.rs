@@ -0,0 +1,166 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use std::collections::HashMap;
+
+use ray_tracer::object::Shape;
+use ray_tracer::object::material::Material;
+use ray_tracer::object::triangle::Triangle;
+
+// Sparse voxel densities, e.g. from a VDB file. Missing voxels are empty.
+pub struct VoxelGrid {
+    voxels: HashMap<(i32, i32, i32), f32>,
+    // Cubes of voxels sharing one value, keyed by side and lowest voxel
+    tiles: HashMap<(i32, (i32, i32, i32)), f32>,
+    tile_sizes: Vec<i32>,
+    voxel_size: Vector3<f64>,
+    // World position of voxel 0, 0, 0
+    origin: Vector3<f64>,
+    min: Vector3<i32>,
+    max: Vector3<i32>,
+}
+
+// Box corners and outward facing quads, as in an .obj cube
+const CORNERS: [(f64, f64, f64); 8] = [
+    (0f64, 0f64, 0f64),
+    (1f64, 0f64, 0f64),
+    (1f64, 1f64, 0f64),
+    (0f64, 1f64, 0f64),
+    (0f64, 0f64, 1f64),
+    (1f64, 0f64, 1f64),
+    (1f64, 1f64, 1f64),
+    (0f64, 1f64, 1f64),
+];
+const FACES: [[usize; 4]; 6] = [
+    [0, 3, 2, 1],
+    [4, 5, 6, 7],
+    [0, 1, 5, 4],
+    [1, 2, 6, 5],
+    [2, 3, 7, 6],
+    [3, 0, 4, 7],
+];
+
+impl VoxelGrid {
+    pub fn new(voxel_size: Vector3<f64>, origin: Vector3<f64>) -> VoxelGrid {
+        VoxelGrid {
+            voxels: HashMap::new(),
+            tiles: HashMap::new(),
+            tile_sizes: Vec::new(),
+            voxel_size,
+            origin,
+            min: Vector3::new(i32::MAX, i32::MAX, i32::MAX),
+            max: Vector3::new(i32::MIN, i32::MIN, i32::MIN),
+        }
+    }
+
+    pub fn insert(&mut self, index: (i32, i32, i32), value: f32) {
+        if value <= 0f32 {
+            return;
+        }
+
+        self.extend_bounds(index, 1);
+        self.voxels.insert(index, value);
+    }
+
+    // A size x size x size cube of voxels from index, all with the value - its lowest corner
+    // must be a multiple of size, as VDB tiles are
+    pub fn insert_tile(&mut self, index: (i32, i32, i32), size: i32, value: f32) {
+        if value <= 0f32 {
+            return;
+        }
+        if size == 1 {
+            return self.insert(index, value);
+        }
+
+        self.extend_bounds(index, size);
+        if !self.tile_sizes.contains(&size) {
+            self.tile_sizes.push(size);
+        }
+        self.tiles.insert((size, index), value);
+    }
+
+    fn extend_bounds(&mut self, index: (i32, i32, i32), size: i32) {
+        self.min = Vector3::new(
+            self.min.x.min(index.0),
+            self.min.y.min(index.1),
+            self.min.z.min(index.2),
+        );
+        self.max = Vector3::new(
+            self.max.x.max(index.0 + size - 1),
+            self.max.y.max(index.1 + size - 1),
+            self.max.z.max(index.2 + size - 1),
+        );
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty() && self.tiles.is_empty()
+    }
+
+    fn value(&self, x: i32, y: i32, z: i32) -> f64 {
+        if let Some(value) = self.voxels.get(&(x, y, z)) {
+            return f64::from(*value);
+        }
+
+        self.tile_sizes
+            .iter()
+            .filter_map(|&size| {
+                let corner = |i: i32| i.div_euclid(size) * size;
+                self.tiles.get(&(size, (corner(x), corner(y), corner(z))))
+            })
+            .next()
+            .map_or(0f64, |value| f64::from(*value))
+    }
+
+    // Trilinear interpolation between voxel centers
+    pub fn sample(&self, point: Vector3<f64>) -> f64 {
+        let index: Vector3<f64> = (point - self.origin).div_element_wise(self.voxel_size) -
+            Vector3::new(0.5, 0.5, 0.5);
+        let (x, y, z): (i32, i32, i32) = (
+            index.x.floor() as i32,
+            index.y.floor() as i32,
+            index.z.floor() as i32,
+        );
+        let t: Vector3<f64> = index - Vector3::new(f64::from(x), f64::from(y), f64::from(z));
+
+        let lerp = |a: f64, b: f64, t: f64| -> f64 { a + (b - a) * t };
+        let row = |y: i32, z: i32| -> f64 {
+            lerp(self.value(x, y, z), self.value(x + 1, y, z), t.x)
+        };
+
+        lerp(
+            lerp(row(y, z), row(y + 1, z), t.y),
+            lerp(row(y, z + 1), row(y + 1, z + 1), t.y),
+            t.z,
+        )
+    }
+
+    // Outward facing box around the occupied voxels, to contain the volume
+    pub fn container(&self, material: Material) -> Vec<Shape> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let low: Vector3<f64> =
+            self.origin + self.min.cast::<f64>().mul_element_wise(self.voxel_size);
+        let size: Vector3<f64> = (self.max - self.min + Vector3::new(1, 1, 1))
+            .cast::<f64>()
+            .mul_element_wise(self.voxel_size);
+
+        let corner = |i: usize| -> Vector3<f64> {
+            let (x, y, z) = CORNERS[i];
+            low + Vector3::new(x * size.x, y * size.y, z * size.z)
+        };
+
+        FACES
+            .iter()
+            .flat_map(|face| {
+                let [a, b, c, d] = face.map(corner);
+                vec![
+                    Shape::Triangle(Triangle::new(a, b, c, material)),
+                    Shape::Triangle(Triangle::new(a, c, d, material)),
+                ]
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,199 @@
+extern crate cgmath;
+
+use self::cgmath::*;
+
+use std::f64;
+use std::sync::Arc;
+
+pub mod grid;
+#[cfg(feature = "vdb")]
+pub mod vdb;
+
+use self::grid::VoxelGrid;
+
+use super::color::Radiance;
+use super::kd_tree::KdTree;
+use super::kd_tree::bounding_box::BoundingBox;
+use super::noise;
+use super::object::Shape;
+use super::ray::Ray;
+
+// 3D noise shaping a volume's density
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseField {
+    // Size of the largest features, in scene units
+    pub scale: f64,
+    pub octaves: usize,
+    // Fraction of the volume that holds any density, 0 - 1
+    pub coverage: f64,
+}
+
+// Where a volume's density comes from
+#[derive(Clone)]
+pub enum DensityField {
+    Uniform,
+    Noise(NoiseField),
+    // Sampled from voxels, e.g. simulation output
+    Grid(Arc<VoxelGrid>),
+}
+
+// Participating medium filling a closed container, e.g. fog, smoke or cloud.
+// Rays are marched through it with single scattering toward each light, so spotlights
+// cast visible shafts. Surfaces aren't shadowed by volumes.
+#[derive(Clone)]
+pub struct Volume {
+    // Closed, outward facing shapes - tested directly rather than through
+    // the k-d tree, so keep them simple
+    container: Vec<Shape>,
+    // Marching stops where a ray leaves these, even if the container is open
+    bounds: BoundingBox,
+    pub albedo: Radiance,
+    pub ambient_coefficient: f64,
+    // Extinction per scene unit where the noise is fully dense
+    pub density: f64,
+    pub step_size: f64,
+    pub field: DensityField,
+}
+
+// A surface crossing along a ray, entering or leaving the container
+struct Crossing {
+    distance: f64,
+    entering: bool,
+}
+
+impl Volume {
+    pub fn new(
+        container: Vec<Shape>,
+        albedo: Radiance,
+        ambient_coefficient: f64,
+        density: f64,
+        step_size: f64,
+        field: DensityField,
+    ) -> Volume {
+        Volume {
+            bounds: KdTree::scene_bounding_box(&container),
+            container,
+            albedo,
+            ambient_coefficient,
+            density,
+            step_size,
+            field,
+        }
+    }
+
+    fn crossings(&self, ray: &Ray) -> Vec<Crossing> {
+        let mut crossings: Vec<Crossing> = Vec::new();
+
+        for shape in &self.container {
+            match *shape {
+                Shape::Triangle(triangle) => {
+                    if let Some(point) = triangle.intersect(ray) {
+                        crossings.push(Crossing {
+                            distance: (point - ray.origin).dot(ray.direction),
+                            entering: ray.direction.dot(triangle.normal) < 0f64,
+                        });
+                    }
+                }
+                // Both roots - sphere intersection only finds hits from outside
+                Shape::Sphere(sphere) => {
+                    let to_center: Vector3<f64> = sphere.origin() - ray.origin;
+                    let along: f64 = to_center.dot(ray.direction);
+                    let squared: f64 = sphere.radius() * sphere.radius() -
+                        (to_center.magnitude2() - along * along);
+
+                    if squared > 0f64 {
+                        let half_chord: f64 = squared.sqrt();
+                        crossings.push(Crossing {
+                            distance: along - half_chord,
+                            entering: true,
+                        });
+                        crossings.push(Crossing {
+                            distance: along + half_chord,
+                            entering: false,
+                        });
+                    }
+                }
+            }
+        }
+
+        // A degenerate container or ray gives NaN distances, which bound nothing
+        crossings.retain(|crossing| crossing.distance.is_finite());
+        crossings.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        crossings
+    }
+
+    // Distance ranges along the ray inside the container, up to max_distance
+    pub fn spans(&self, ray: &Ray, max_distance: f64) -> Vec<(f64, f64)> {
+        let max_distance: f64 = match self.bounds.exit_distance(ray) {
+            Some(exit) => max_distance.min(exit),
+            None => return Vec::new(),
+        };
+        let crossings: Vec<Crossing> = self.crossings(ray);
+
+        // A ray starting inside leaves more often than it enters
+        let mut depth: i32 = 0;
+        let mut lowest: i32 = 0;
+        for crossing in crossings.iter().filter(|crossing| crossing.distance > 0f64) {
+            depth += if crossing.entering { 1 } else { -1 };
+            lowest = lowest.min(depth);
+        }
+
+        let mut spans: Vec<(f64, f64)> = Vec::new();
+        let mut depth: i32 = -lowest;
+        let mut start: f64 = 0f64;
+
+        for crossing in crossings.iter().filter(|crossing| crossing.distance > 0f64) {
+            let inside: bool = depth > 0;
+            depth += if crossing.entering { 1 } else { -1 };
+
+            if !inside && depth > 0 {
+                start = crossing.distance;
+            } else if inside && depth <= 0 {
+                spans.push((start, crossing.distance));
+            }
+        }
+        if depth > 0 {
+            spans.push((start, f64::MAX));
+        }
+
+        spans
+            .into_iter()
+            .filter(|&(start, _)| start < max_distance)
+            .map(|(start, end)| (start, end.min(max_distance)))
+            .collect()
+    }
+
+    // Extinction at a point inside the container
+    pub fn density(&self, point: Vector3<f64>) -> f64 {
+        match self.field {
+            DensityField::Uniform => self.density,
+            DensityField::Noise(noise) => {
+                let value: f64 = noise::fbm(point / noise.scale, noise.octaves) * 0.5 + 0.5;
+                let coverage: f64 = noise.coverage.clamp(0.01, 1f64);
+
+                self.density * ((value - (1f64 - coverage)) / coverage).clamp(0f64, 1f64)
+            }
+            DensityField::Grid(ref grid) => self.density * grid.sample(point),
+        }
+    }
+
+    // Total extinction along the ray up to max_distance, sampled at twice the step size
+    pub fn optical_depth(&self, ray: &Ray, max_distance: f64) -> f64 {
+        let step: f64 = self.step_size * 2f64;
+
+        self.spans(ray, max_distance)
+            .iter()
+            .map(|&(start, end)| {
+                let steps: usize = ((end - start) / step).ceil().max(1f64) as usize;
+                let length: f64 = (end - start) / steps as f64;
+
+                (0..steps)
+                    .map(|i| {
+                        let distance: f64 = start + (i as f64 + 0.5) * length;
+                        self.density(ray.origin + ray.direction * distance) * length
+                    })
+                    .sum::<f64>()
+            })
+            .sum()
+    }
+}
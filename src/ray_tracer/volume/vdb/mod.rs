@@ -0,0 +1,62 @@
+extern crate cgmath;
+extern crate vdb_rs;
+
+use self::cgmath::*;
+use self::vdb_rs::{Map, VdbReader};
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::panic::{self, AssertUnwindSafe};
+
+use super::grid::VoxelGrid;
+
+// Read a float grid from an OpenVDB file, placed by the file's transform unless voxel_size
+// or origin (the world position of voxel 0, 0, 0's lowest corner) override it. Tiles fill
+// every voxel they cover.
+pub fn load_grid(
+    filename: &str,
+    grid_name: &str,
+    voxel_size: Option<f64>,
+    origin: Option<Vector3<f64>>,
+) -> io::Result<VoxelGrid> {
+    let error = |error: String| io::Error::other(format!("{}: {}", filename, error));
+
+    let mut reader = VdbReader::new(BufReader::new(File::open(filename)?))
+        .map_err(|e| error(format!("{:?}", e)))?;
+    // The reader panics on transforms it doesn't know
+    let vdb_grid = panic::catch_unwind(AssertUnwindSafe(|| reader.read_grid::<f32>(grid_name)))
+        .map_err(|_| error(format!("grid {}: unsupported transform", grid_name)))?
+        .map_err(|e| error(format!("grid {}: {:?}", grid_name, e)))?;
+
+    // OpenVDB places voxel centers at index * scale + translation
+    let (scale, translation) = match vdb_grid.transform {
+        Map::UniformScaleMap { voxel_size, .. } => (voxel_size, Default::default()),
+        Map::ScaleTranslateMap {
+            voxel_size,
+            translation,
+            ..
+        } => (voxel_size, translation),
+    };
+    let file_voxel_size: Vector3<f64> = Vector3::new(scale.x, scale.y, scale.z);
+    let voxel_size: Vector3<f64> = voxel_size.map_or(file_voxel_size, |voxel_size| {
+        Vector3::new(voxel_size, voxel_size, voxel_size)
+    });
+    let origin: Vector3<f64> = origin.unwrap_or_else(|| {
+        Vector3::new(translation.x, translation.y, translation.z) - voxel_size / 2f64
+    });
+
+    let mut grid: VoxelGrid = VoxelGrid::new(voxel_size, origin);
+    for (position, value, level) in vdb_grid.iter() {
+        grid.insert_tile(
+            (
+                position.x.round() as i32,
+                position.y.round() as i32,
+                position.z.round() as i32,
+            ),
+            level.scale() as i32,
+            value,
+        );
+    }
+
+    Ok(grid)
+}
@@ -0,0 +1,15 @@
+extern crate serde_json;
+
+use wasm_bindgen::prelude::*;
+
+use super::scene::configuration::Configuration;
+
+// Render a scene configuration (JSON) into tone mapped, row-major RGBA bytes
+// There is no filesystem - objects must supply their .obj statements in "contents"
+#[wasm_bindgen]
+pub fn render_rgba(configuration: &str) -> Result<Vec<u8>, JsValue> {
+    let configuration: Configuration = serde_json::from_str(configuration)
+        .map_err(|error| JsValue::from_str(&error.to_string()))?;
+
+    super::render_rgba(&configuration).map_err(|error| JsValue::from_str(&error.to_string()))
+}
@@ -0,0 +1,187 @@
+extern crate notify;
+
+use self::notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::color::Radiance;
+use super::scene::configuration::Configuration;
+
+// Editors may save in several writes - changes this close together cause one render
+const SETTLE_MS: u64 = 100;
+
+// Re-renders when the configuration, or any mesh, VDB grid or gobo image it reads, changes.
+// Their directories are watched rather than the files themselves, so files that editors
+// replace instead of writing in place are still seen
+pub struct Watcher {
+    config_file: String,
+    // Key path assignments applied to each reload
+    overrides: Vec<String>,
+    out_file: String,
+    preview_scale: f64,
+    // Files the configuration read when it was last loaded
+    referenced_files: Vec<String>,
+    // Absolute paths of the configuration and its referenced files, and their directories
+    watched_files: HashSet<PathBuf>,
+    watched_directories: HashSet<PathBuf>,
+    watcher: RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<Event>>,
+}
+
+impl Watcher {
+    pub fn new(
+        config_file: &str,
+        overrides: &[String],
+        out_file: &str,
+        preview_scale: f64,
+    ) -> io::Result<Watcher> {
+        let (sender, events) = mpsc::channel();
+        let watcher: RecommendedWatcher = notify::recommended_watcher(sender)
+            .map_err(|error| io::Error::other(error.to_string()))?;
+
+        let mut watcher: Watcher = Watcher {
+            config_file: String::from(config_file),
+            overrides: overrides.to_vec(),
+            out_file: String::from(out_file),
+            preview_scale,
+            referenced_files: Vec::new(),
+            watched_files: HashSet::new(),
+            watched_directories: HashSet::new(),
+            watcher,
+            events,
+        };
+        watcher.watch();
+
+        Ok(watcher)
+    }
+
+    // Watch the directories of the configuration and the files it references, dropping
+    // directories nothing is read from any more
+    fn watch(&mut self) {
+        let mut watched_files: HashSet<PathBuf> = HashSet::new();
+        let mut watched_directories: HashSet<PathBuf> = HashSet::new();
+        for file in iter::once(&self.config_file).chain(self.referenced_files.iter()) {
+            match absolute(file) {
+                Some((path, directory)) => {
+                    watched_files.insert(path);
+                    watched_directories.insert(directory);
+                }
+                None => warn!("Can't watch {} - its directory doesn't exist", file),
+            }
+        }
+
+        for directory in self.watched_directories.difference(&watched_directories) {
+            let _ = self.watcher.unwatch(directory);
+        }
+        for directory in watched_directories.difference(&self.watched_directories) {
+            if let Err(error) = self.watcher.watch(directory, RecursiveMode::NonRecursive) {
+                warn!("Can't watch {}: {}", directory.display(), error);
+            }
+        }
+
+        self.watched_files = watched_files;
+        self.watched_directories = watched_directories;
+    }
+
+    // Whether the event modified, created or removed a watched file
+    fn affects_watched_file(&self, event: notify::Result<Event>) -> bool {
+        match event {
+            Ok(ref event) if matches!(event.kind, EventKind::Access(_)) => false,
+            Ok(event) => event.paths.iter().any(|path| self.watched_files.contains(path)),
+            Err(error) => {
+                warn!("File watcher error: {}", error);
+                false
+            }
+        }
+    }
+
+    fn preview(&mut self) {
+        let mut configuration: Configuration =
+            match Configuration::parse_configuration(&self.config_file)
+                .and_then(|configuration| configuration.with_overrides(&self.overrides))
+            {
+                Ok(configuration) => configuration,
+                Err(error) => {
+                    error!("{}", error);
+                    return;
+                }
+            };
+
+        let referenced_files: Vec<String> =
+            configuration.files().into_iter().map(String::from).collect();
+        if referenced_files != self.referenced_files {
+            debug!("Watching {} referenced files", referenced_files.len());
+            self.referenced_files = referenced_files;
+            self.watch();
+        }
+
+        configuration.scale_resolution(self.preview_scale);
+
+        let start: Instant = Instant::now();
+        let result: io::Result<()> =
+            super::render(&configuration).and_then(|color_buffer: Vec<Vec<Radiance>>| {
+                super::save_image(
+                    &self.out_file,
+                    &color_buffer,
+                    configuration.width,
+                    configuration.height,
+                )
+            });
+
+        match result {
+            Ok(()) => info!(
+                "Rendered {}x{} preview to {} in {:.1}s",
+                configuration.width,
+                configuration.height,
+                self.out_file,
+                start.elapsed().as_secs_f64()
+            ),
+            Err(error) => error!("{}", error),
+        }
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        // Fail early if the file can't be found at all
+        fs::metadata(&self.config_file)?;
+
+        info!("Watching {} for changes", self.config_file);
+        self.preview();
+
+        // Changes made while rendering wait in the channel and start the next render
+        loop {
+            let event: notify::Result<Event> = self
+                .events
+                .recv()
+                .map_err(|_| io::Error::other("The file watcher stopped"))?;
+            if !self.affects_watched_file(event) {
+                continue;
+            }
+
+            // Let the rest of the save land, then render once for all of it
+            thread::sleep(Duration::from_millis(SETTLE_MS));
+            while self.events.try_recv().is_ok() {}
+
+            self.preview();
+        }
+    }
+}
+
+// The file's absolute path and its directory, as the watcher reports them - None if the
+// directory doesn't exist. The file itself may not exist yet
+fn absolute(file: &str) -> Option<(PathBuf, PathBuf)> {
+    let path: &Path = Path::new(file);
+    let directory: &Path = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let directory: PathBuf = fs::canonicalize(directory).ok()?;
+
+    Some((directory.join(path.file_name()?), directory))
+}